@@ -0,0 +1,243 @@
+//! Benson's algorithm for pass-alive groups and territory.
+//!
+//! A chain is pass-alive (unconditionally alive) if the opponent cannot
+//! capture it even when its owner passes every turn. Regions enclosed by
+//! pass-alive chains of one color, where each empty point touches one of
+//! those chains, are that color's pass-alive territory: any opponent stones
+//! inside are dead. This is the same definition KataGo uses for scoring.
+
+/// Regions enclosed by one color and the chains bordering them
+struct Partition {
+    /// Chain index of each point holding the color's stones
+    chain_of: Vec<Option<usize>>,
+    /// Points of each region
+    regions: Vec<Vec<usize>>,
+    /// Chains bordering each region, without duplicates
+    borders: Vec<Vec<usize>>,
+    num_chains: usize,
+}
+
+/// On-board orthogonal neighbors of point `p`, indexed as y * size + x
+fn neighbors(p: usize, size: usize) -> impl Iterator<Item = usize> {
+    let (x, y) = (p % size, p / size);
+    [
+        (x.wrapping_sub(1), y),
+        (x + 1, y),
+        (x, y.wrapping_sub(1)),
+        (x, y + 1),
+    ]
+    .into_iter()
+    .filter(move |&(nx, ny)| nx < size && ny < size)
+    .map(move |(nx, ny)| ny * size + nx)
+}
+
+/// Label connected components of the points where `member` holds
+fn label(points: &[i8], size: usize, member: impl Fn(i8) -> bool) -> (Vec<Option<usize>>, usize) {
+    let mut labels = vec![None; points.len()];
+    let mut count = 0;
+    for start in 0..points.len() {
+        if labels[start].is_some() || !member(points[start]) {
+            continue;
+        }
+        labels[start] = Some(count);
+        let mut stack = vec![start];
+        while let Some(p) = stack.pop() {
+            for n in neighbors(p, size) {
+                if labels[n].is_none() && member(points[n]) {
+                    labels[n] = Some(count);
+                    stack.push(n);
+                }
+            }
+        }
+        count += 1;
+    }
+    (labels, count)
+}
+
+/// Split the board into `color`'s chains and the regions they enclose
+fn partition(points: &[i8], size: usize, color: i8) -> Partition {
+    let (chain_of, num_chains) = label(points, size, |s| s == color);
+    let (region_of, num_regions) = label(points, size, |s| s != color);
+
+    let mut regions = vec![Vec::new(); num_regions];
+    let mut borders: Vec<Vec<usize>> = vec![Vec::new(); num_regions];
+    for (p, region) in region_of.iter().enumerate() {
+        let Some(region) = *region else {
+            continue;
+        };
+        regions[region].push(p);
+        for n in neighbors(p, size) {
+            if let Some(chain) = chain_of[n] {
+                if !borders[region].contains(&chain) {
+                    borders[region].push(chain);
+                }
+            }
+        }
+    }
+
+    Partition {
+        chain_of,
+        regions,
+        borders,
+        num_chains,
+    }
+}
+
+/// Whether every empty point of `region` is a liberty of `chain`
+/// Regions without empty points are never vital
+fn is_vital(
+    partition: &Partition,
+    points: &[i8],
+    size: usize,
+    region: usize,
+    chain: usize,
+) -> bool {
+    let mut empty = partition.regions[region]
+        .iter()
+        .filter(|&&p| points[p] == 0)
+        .peekable();
+    empty.peek().is_some()
+        && empty.all(|&p| neighbors(p, size).any(|n| partition.chain_of[n] == Some(chain)))
+}
+
+/// Mark `color`'s pass-alive chains and territory in `owner`
+fn mark_pass_alive(points: &[i8], size: usize, color: i8, owner: &mut [i8]) {
+    let partition = partition(points, size, color);
+    let mut chain_alive = vec![true; partition.num_chains];
+    let mut region_alive = vec![true; partition.regions.len()];
+
+    // Benson: drop chains with fewer than two vital regions, then the regions
+    // bordering a dropped chain, until nothing changes
+    loop {
+        let dropped: Vec<usize> = (0..partition.num_chains)
+            .filter(|&chain| chain_alive[chain])
+            .filter(|&chain| {
+                let vital_regions = (0..partition.regions.len())
+                    .filter(|&region| {
+                        region_alive[region]
+                            && partition.borders[region].contains(&chain)
+                            && is_vital(&partition, points, size, region, chain)
+                    })
+                    .count();
+                vital_regions < 2
+            })
+            .collect();
+        let mut changed = !dropped.is_empty();
+        for chain in dropped {
+            chain_alive[chain] = false;
+        }
+        for (region, alive) in region_alive.iter_mut().enumerate() {
+            if *alive
+                && partition.borders[region]
+                    .iter()
+                    .any(|&chain| !chain_alive[chain])
+            {
+                *alive = false;
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    for (p, chain) in partition.chain_of.iter().enumerate() {
+        if chain.is_some_and(|chain| chain_alive[chain]) {
+            owner[p] = color;
+        }
+    }
+    for (region, points_in_region) in partition.regions.iter().enumerate() {
+        let is_territory = region_alive[region]
+            && partition.borders[region]
+                .iter()
+                .any(|&chain| is_vital(&partition, points, size, region, chain));
+        if is_territory {
+            for &p in points_in_region {
+                owner[p] = color;
+            }
+        }
+    }
+}
+
+/// Classify every point of a position as pass-alive for Black (1), White (-1) or neither (0)
+/// A point is pass-alive for a color if it holds one of that color's pass-alive
+/// stones or lies in its pass-alive territory, dead opponent stones included
+pub fn pass_alive_regions(sign_map: &[Vec<i8>]) -> Result<Vec<Vec<i8>>, String> {
+    let size = sign_map.len();
+    if let Some((y, row)) = sign_map
+        .iter()
+        .enumerate()
+        .find(|(_, row)| row.len() != size)
+    {
+        return Err(format!(
+            "Board is not square: row {} has {} cells, expected {}",
+            y,
+            row.len(),
+            size
+        ));
+    }
+    let points: Vec<i8> = sign_map.iter().flatten().map(|&s| s.signum()).collect();
+
+    let mut owner = vec![0i8; points.len()];
+    mark_pass_alive(&points, size, 1, &mut owner);
+    mark_pass_alive(&points, size, -1, &mut owner);
+
+    Ok(owner.chunks(size.max(1)).map(|row| row.to_vec()).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parse rows of 'B', 'W' and '.' into a sign map
+    fn board(rows: &[&str]) -> Vec<Vec<i8>> {
+        rows.iter()
+            .map(|row| {
+                row.chars()
+                    .map(|c| match c {
+                        'B' => 1,
+                        'W' => -1,
+                        _ => 0,
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn two_eyed_group_is_alive_and_owns_its_eyes() {
+        let owner =
+            pass_alive_regions(&board(&[".B.B.", "BBBBB", ".....", ".....", "....."])).unwrap();
+        assert_eq!(owner[0], vec![1; 5]);
+        assert_eq!(owner[1], vec![1; 5]);
+        // The open area below touches the group but isn't enclosed by it
+        assert_eq!(owner[3], vec![0; 5]);
+    }
+
+    #[test]
+    fn one_eyed_group_is_not_alive() {
+        let owner =
+            pass_alive_regions(&board(&[".BBBB", "BBBBB", ".....", ".....", "....."])).unwrap();
+        assert!(owner.iter().flatten().all(|&o| o == 0));
+    }
+
+    #[test]
+    fn dead_stones_in_pass_alive_territory_are_flipped() {
+        let owner =
+            pass_alive_regions(&board(&[".BW.B", "BBBBB", ".....", ".....", "....."])).unwrap();
+        assert_eq!(owner[0], vec![1; 5]);
+    }
+
+    #[test]
+    fn empty_board_has_no_pass_alive_points() {
+        assert_eq!(pass_alive_regions(&[]).unwrap(), Vec::<Vec<i8>>::new());
+        let owner = pass_alive_regions(&board(&["...", "...", "..."])).unwrap();
+        assert_eq!(owner, vec![vec![0; 3]; 3]);
+    }
+
+    #[test]
+    fn ragged_board_is_rejected() {
+        let error = pass_alive_regions(&board(&["...", "..", "..."])).unwrap_err();
+        assert!(error.contains("not square"), "{}", error);
+    }
+}
@@ -0,0 +1,459 @@
+//! Incremental Go board with captures, simple ko and undo.
+//!
+//! Replaying games move by move on a `GoBoard` avoids rebuilding a
+//! `sign_map` and re-deriving captures and ko for every position. The
+//! `go_board_*` commands expose it so the frontend shares one
+//! authoritative implementation of the rules.
+//!
+//! Coordinates follow [`HistoryMove`](crate::onnx_engine::HistoryMove):
+//! 0-based (x, y) from the top-left corner, with negative values for a pass.
+
+use serde::{Deserialize, Serialize};
+
+/// Stones removed by a move
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Captures {
+    /// Captured points as (x, y)
+    pub points: Vec<(usize, usize)>,
+    /// Point the opponent may not retake on the next move, if the move took a ko
+    pub ko_point: Option<(usize, usize)>,
+}
+
+/// Everything needed to take back one move
+#[derive(Debug, Clone)]
+struct Undo {
+    /// Point played, None for a pass
+    point: Option<usize>,
+    color: i8,
+    captured: Vec<usize>,
+    ko_before: Option<(usize, i8)>,
+}
+
+/// A Go board that applies moves incrementally
+#[derive(Debug, Clone)]
+pub struct GoBoard {
+    size: usize,
+    /// Stones in row-major order: 1 = Black, -1 = White, 0 = empty
+    points: Vec<i8>,
+    /// Simple ko point and the player forbidden from playing there
+    ko: Option<(usize, i8)>,
+    /// Stones captured by Black and by White
+    prisoners: [usize; 2],
+    history: Vec<Undo>,
+}
+
+impl GoBoard {
+    /// Create an empty board
+    pub fn new(size: usize) -> Result<Self, String> {
+        if size == 0 {
+            return Err("Board size must be at least 1".to_string());
+        }
+        Ok(Self {
+            size,
+            points: vec![0; size * size],
+            ko: None,
+            prisoners: [0, 0],
+            history: Vec::new(),
+        })
+    }
+
+    /// Create a board holding the stones of a `sign_map`, with no ko and no history
+    pub fn from_signmap(sign_map: &[Vec<i8>]) -> Result<Self, String> {
+        let size = sign_map.len();
+        if let Some((y, row)) = sign_map
+            .iter()
+            .enumerate()
+            .find(|(_, row)| row.len() != size)
+        {
+            return Err(format!(
+                "Board is not square: row {} has {} cells, expected {}",
+                y,
+                row.len(),
+                size
+            ));
+        }
+        let mut board = Self::new(size)?;
+        board.points = sign_map.iter().flatten().map(|&s| s.signum()).collect();
+        Ok(board)
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// The stones as a [y][x] grid
+    pub fn to_signmap(&self) -> Vec<Vec<i8>> {
+        self.points
+            .chunks(self.size)
+            .map(|row| row.to_vec())
+            .collect()
+    }
+
+    /// Point the player to move may not play at because of simple ko
+    pub fn ko_point(&self) -> Option<(usize, usize)> {
+        self.ko.map(|(p, _)| (p % self.size, p / self.size))
+    }
+
+    /// Number of stones `color` has captured
+    pub fn prisoners(&self, color: i8) -> usize {
+        self.prisoners[usize::from(color != 1)]
+    }
+
+    /// Number of moves played, passes included
+    pub fn move_count(&self) -> usize {
+        self.history.len()
+    }
+
+    /// Forbid `color` from playing at `point` because of simple ko, or clear the ko with None
+    /// For positions that don't come with their history, like a `sign_map` built elsewhere
+    pub fn set_ko_point(&mut self, point: Option<(usize, usize)>, color: i8) {
+        self.ko = point
+            .filter(|&(x, y)| x < self.size && y < self.size)
+            .map(|(x, y)| (y * self.size + x, color));
+    }
+
+    /// Place `color` at (x, y), or empty the point with 0, without playing a move
+    /// Like SGF setup properties, nothing is captured; the ko and the move history
+    /// are cleared, as earlier moves could no longer be taken back correctly
+    pub fn set_stone(&mut self, x: usize, y: usize, color: i8) -> Result<(), String> {
+        if x >= self.size || y >= self.size {
+            return Err(format!(
+                "Point ({}, {}) is off the {}x{} board",
+                x, y, self.size, self.size
+            ));
+        }
+        self.points[y * self.size + x] = color.signum();
+        self.ko = None;
+        self.history.clear();
+        Ok(())
+    }
+
+    /// Whether [`play`](Self::play) would accept `color` at (x, y); a pass always is
+    pub fn is_legal(&self, x: i32, y: i32, color: i8) -> bool {
+        if color != 1 && color != -1 {
+            return false;
+        }
+        if x < 0 || y < 0 {
+            return true;
+        }
+        let (x, y) = (x as usize, y as usize);
+        if x >= self.size || y >= self.size {
+            return false;
+        }
+        let p = y * self.size + x;
+        if self.points[p] != 0 || self.ko == Some((p, color)) {
+            return false;
+        }
+        // Legal if the stone has a liberty, captures an opponent chain in atari,
+        // or joins a friendly chain that keeps another liberty
+        self.neighbors(p).any(|n| match self.points[n] {
+            0 => true,
+            c if c == color => self.chain(n).1 >= 2,
+            _ => self.chain(n).1 == 1,
+        })
+    }
+
+    /// Play `color` (1 = Black, -1 = White) at (x, y), or pass if either is negative
+    /// Rejects off-board and occupied points, suicide and retaking a simple ko;
+    /// the board is unchanged when the move is rejected
+    pub fn play(&mut self, x: i32, y: i32, color: i8) -> Result<Captures, String> {
+        if color != 1 && color != -1 {
+            return Err(format!("Invalid color: {}", color));
+        }
+        let ko_before = self.ko;
+        if x < 0 || y < 0 {
+            self.ko = None;
+            self.history.push(Undo {
+                point: None,
+                color,
+                captured: Vec::new(),
+                ko_before,
+            });
+            return Ok(Captures::default());
+        }
+
+        let (x, y) = (x as usize, y as usize);
+        if x >= self.size || y >= self.size {
+            return Err(format!(
+                "Move ({}, {}) is off the {}x{} board",
+                x, y, self.size, self.size
+            ));
+        }
+        let p = y * self.size + x;
+        if self.points[p] != 0 {
+            return Err(format!("Point ({}, {}) is occupied", x, y));
+        }
+        if self.ko == Some((p, color)) {
+            return Err(format!("Point ({}, {}) retakes a ko", x, y));
+        }
+
+        self.points[p] = color;
+        let mut captured = Vec::new();
+        for n in self.neighbors(p) {
+            if self.points[n] == -color {
+                let (chain, liberties) = self.chain(n);
+                if liberties == 0 {
+                    for &stone in &chain {
+                        self.points[stone] = 0;
+                    }
+                    captured.extend(chain);
+                }
+            }
+        }
+
+        let (chain, liberties) = self.chain(p);
+        if liberties == 0 {
+            self.points[p] = 0;
+            return Err(format!("Move ({}, {}) is suicide", x, y));
+        }
+
+        // A lone stone that captured a single stone and sits in atari took a ko
+        self.ko = (captured.len() == 1 && chain.len() == 1 && liberties == 1)
+            .then(|| (captured[0], -color));
+        self.prisoners[usize::from(color != 1)] += captured.len();
+        let result = Captures {
+            points: captured
+                .iter()
+                .map(|&c| (c % self.size, c / self.size))
+                .collect(),
+            ko_point: self.ko_point(),
+        };
+        self.history.push(Undo {
+            point: Some(p),
+            color,
+            captured,
+            ko_before,
+        });
+        Ok(result)
+    }
+
+    /// Take back the last move, returning false if there is none
+    pub fn undo(&mut self) -> bool {
+        let Some(undo) = self.history.pop() else {
+            return false;
+        };
+        if let Some(p) = undo.point {
+            self.points[p] = 0;
+            for &stone in &undo.captured {
+                self.points[stone] = -undo.color;
+            }
+            self.prisoners[usize::from(undo.color != 1)] -= undo.captured.len();
+        }
+        self.ko = undo.ko_before;
+        true
+    }
+
+    /// On-board orthogonal neighbors of point `p`
+    fn neighbors(&self, p: usize) -> impl Iterator<Item = usize> {
+        let size = self.size;
+        let (x, y) = (p % size, p / size);
+        [
+            (x.wrapping_sub(1), y),
+            (x + 1, y),
+            (x, y.wrapping_sub(1)),
+            (x, y + 1),
+        ]
+        .into_iter()
+        .filter(move |&(nx, ny)| nx < size && ny < size)
+        .map(move |(nx, ny)| ny * size + nx)
+    }
+
+    /// The chain containing the stone at `p` and its number of liberties
+    fn chain(&self, p: usize) -> (Vec<usize>, usize) {
+        let color = self.points[p];
+        let mut visited = vec![false; self.points.len()];
+        let mut chain = Vec::new();
+        let mut liberties = 0;
+        let mut stack = vec![p];
+        visited[p] = true;
+        while let Some(q) = stack.pop() {
+            chain.push(q);
+            for n in self.neighbors(q) {
+                if visited[n] {
+                    continue;
+                }
+                if self.points[n] == 0 {
+                    visited[n] = true;
+                    liberties += 1;
+                } else if self.points[n] == color {
+                    visited[n] = true;
+                    stack.push(n);
+                }
+            }
+        }
+        (chain, liberties)
+    }
+}
+
+/// Column letters of GTP vertices, which skip I
+pub const GTP_COLUMNS: &str = "ABCDEFGHJKLMNOPQRST";
+
+/// Parse a GTP vertex ("D4", any case) on a `size`x`size` board into (x, y), None for a pass
+pub fn parse_gtp_vertex(vertex: &str, size: usize) -> Result<Option<(usize, usize)>, String> {
+    if vertex.eq_ignore_ascii_case("pass") {
+        return Ok(None);
+    }
+    let mut chars = vertex.chars();
+    let column = chars.next().map(|c| c.to_ascii_uppercase());
+    let x = column
+        .and_then(|c| GTP_COLUMNS.find(c))
+        .ok_or("invalid coordinate")?;
+    let row: usize = chars.as_str().parse().map_err(|_| "invalid coordinate")?;
+    if x >= size || row == 0 || row > size {
+        return Err("invalid coordinate".to_string());
+    }
+    Ok(Some((x, size - row)))
+}
+
+/// Format (x, y) on a `size`x`size` board as a GTP vertex ("D4")
+pub fn format_gtp_vertex(x: usize, y: usize, size: usize) -> String {
+    format!("{}{}", GTP_COLUMNS.chars().nth(x).unwrap_or('?'), size - y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 5x5 board with a ko shape: Black to take at (2, 1)
+    /// ```text
+    /// . B W . .
+    /// B W . W .
+    /// . B W . .
+    /// ```
+    fn ko_board() -> GoBoard {
+        let mut sign_map = vec![vec![0i8; 5]; 5];
+        for (x, y) in [(1, 0), (0, 1), (1, 2)] {
+            sign_map[y][x] = 1;
+        }
+        for (x, y) in [(2, 0), (1, 1), (3, 1), (2, 2)] {
+            sign_map[y][x] = -1;
+        }
+        GoBoard::from_signmap(&sign_map).unwrap()
+    }
+
+    #[test]
+    fn captures_a_single_stone() {
+        let mut board = GoBoard::new(5).unwrap();
+        board.play(1, 1, -1).unwrap();
+        for (x, y) in [(0, 1), (2, 1), (1, 0)] {
+            board.play(x, y, 1).unwrap();
+        }
+        let captures = board.play(1, 2, 1).unwrap();
+        assert_eq!(captures.points, vec![(1, 1)]);
+        assert_eq!(captures.ko_point, None);
+        assert_eq!(board.to_signmap()[1][1], 0);
+        assert_eq!(board.prisoners(1), 1);
+        assert_eq!(board.prisoners(-1), 0);
+    }
+
+    #[test]
+    fn captures_a_whole_chain() {
+        let mut board = GoBoard::new(5).unwrap();
+        board.play(0, 0, -1).unwrap();
+        board.play(1, 0, -1).unwrap();
+        board.play(0, 1, 1).unwrap();
+        board.play(1, 1, 1).unwrap();
+        let mut captures = board.play(2, 0, 1).unwrap();
+        captures.points.sort();
+        assert_eq!(captures.points, vec![(0, 0), (1, 0)]);
+        assert_eq!(board.prisoners(1), 2);
+        assert_eq!(board.to_signmap()[0], vec![0, 0, 1, 0, 0]);
+    }
+
+    #[test]
+    fn rejects_suicide_and_leaves_the_board_unchanged() {
+        let mut board = GoBoard::new(5).unwrap();
+        board.play(1, 0, 1).unwrap();
+        board.play(0, 1, 1).unwrap();
+        let before = board.to_signmap();
+        assert!(board.play(0, 0, -1).unwrap_err().contains("suicide"));
+        assert_eq!(board.to_signmap(), before);
+        assert_eq!(board.move_count(), 2);
+    }
+
+    #[test]
+    fn filling_the_last_liberty_is_legal_when_it_captures() {
+        let mut board = ko_board();
+        // (2, 1) has no liberty of its own but takes the White stone at (1, 1)
+        let captures = board.play(2, 1, 1).unwrap();
+        assert_eq!(captures.points, vec![(1, 1)]);
+    }
+
+    #[test]
+    fn simple_ko_forbids_the_immediate_retake() {
+        let mut board = ko_board();
+        let captures = board.play(2, 1, 1).unwrap();
+        assert_eq!(captures.ko_point, Some((1, 1)));
+        assert_eq!(board.ko_point(), Some((1, 1)));
+        assert!(board.play(1, 1, -1).unwrap_err().contains("ko"));
+
+        // After a ko threat elsewhere White may retake
+        board.play(4, 4, -1).unwrap();
+        board.play(4, 3, 1).unwrap();
+        assert_eq!(board.ko_point(), None);
+        let captures = board.play(1, 1, -1).unwrap();
+        assert_eq!(captures.points, vec![(2, 1)]);
+    }
+
+    #[test]
+    fn is_legal_agrees_with_play_and_a_restored_ko() {
+        let mut board = ko_board();
+        board.play(2, 1, 1).unwrap();
+        let mut restored = GoBoard::from_signmap(&board.to_signmap()).unwrap();
+        assert!(restored.is_legal(1, 1, -1));
+        restored.set_ko_point(Some((1, 1)), -1);
+        assert!(!restored.is_legal(1, 1, -1));
+        assert!(restored.is_legal(-1, -1, -1));
+        assert!(restored.is_legal(4, 4, -1));
+
+        // A lone stone filling its last liberty is suicide
+        let mut corner = GoBoard::new(3).unwrap();
+        corner.set_stone(1, 0, -1).unwrap();
+        corner.set_stone(0, 1, -1).unwrap();
+        assert!(!corner.is_legal(0, 0, 1));
+        assert!(corner.is_legal(0, 0, -1));
+    }
+
+    #[test]
+    fn undo_restores_stones_prisoners_and_ko() {
+        let mut board = ko_board();
+        let start = board.to_signmap();
+        board.play(2, 1, 1).unwrap();
+        let after_take = board.to_signmap();
+        board.play(4, 4, -1).unwrap();
+        assert_eq!(board.ko_point(), None);
+
+        assert!(board.undo());
+        assert_eq!(board.ko_point(), Some((1, 1)));
+        assert_eq!(board.to_signmap(), after_take);
+
+        assert!(board.undo());
+        assert_eq!(board.to_signmap(), start);
+        assert_eq!(board.prisoners(1), 0);
+        assert_eq!(board.ko_point(), None);
+        assert!(!board.undo());
+    }
+
+    #[test]
+    fn from_signmap_rejects_a_ragged_map() {
+        let sign_map = vec![vec![0, 0, 0], vec![0, 0], vec![0, 0, 0]];
+        let error = GoBoard::from_signmap(&sign_map).unwrap_err();
+        assert!(error.contains("row 1 has 2 cells"), "{}", error);
+    }
+
+    #[test]
+    fn gtp_vertices_skip_i_and_count_rows_from_the_bottom() {
+        assert_eq!(parse_gtp_vertex("A19", 19), Ok(Some((0, 0))));
+        assert_eq!(parse_gtp_vertex("j1", 19), Ok(Some((8, 18))));
+        assert_eq!(parse_gtp_vertex("PASS", 9), Ok(None));
+        for bad in ["I5", "K10", "A0", "E", "", "D4x"] {
+            assert!(parse_gtp_vertex(bad, 9).is_err(), "{}", bad);
+        }
+        for (x, y) in [(0, 0), (8, 3), (18, 18)] {
+            let vertex = format_gtp_vertex(x, y, 19);
+            assert_eq!(parse_gtp_vertex(&vertex, 19), Ok(Some((x, y))));
+        }
+        assert_eq!(format_gtp_vertex(8, 0, 9), "J9");
+    }
+}
@@ -3,14 +3,27 @@
 //! These commands expose the Rust ONNX engine to the frontend,
 //! providing high-performance AI analysis for the desktop app.
 
-use crate::onnx_engine::{self, AnalysisOptions, AnalysisResult, ExecutionProviderInfo, ExecutionProviderPreference};
+use crate::board::{Captures, GoBoard};
+use crate::onnx_engine::{
+    self, AnalysisOptions, AnalysisResult, AutotuneResult, BenchmarkResult, CoreMlComputeUnits,
+    EngineStats, EnsembleAnalysis, ExecutionProviderInfo, ExecutionProviderPreference, FairKomi,
+    FeatureDump, FinalScore, GameAnalysis, GameAnalysisOptions, GenmoveResult, GradedMove,
+    HistoryMove, LoadedModel, MemoryInfo, ModelConfig, ModelLoadOptions, ModelMetadata, OnnxError,
+    OptimizationLevel, PositionAnalysis, PrincipalVariation, ProviderDiagnostic, ProviderSettings,
+    RuntimeInfo, SavedPosition, SearchedAnalysis, SelfPlayGame, TensorData,
+};
+use crate::sgf::{self, SgfPosition};
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine as Base64Engine};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
 use std::fs::{File, OpenOptions};
-use std::io::Write;
-use std::path::PathBuf;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Mutex;
-use tauri::Manager;
+use std::time::{Duration, Instant, SystemTime};
+use tauri::{Emitter, Manager};
 
 /// Input for batch analysis
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -21,109 +34,942 @@ pub struct BatchInput {
     pub options: AnalysisOptions,
 }
 
-/// State for chunked model upload
-static MODEL_UPLOAD_PATH: Mutex<Option<PathBuf>> = Mutex::new(None);
+/// A model stored in the app data cache
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CachedModelInfo {
+    /// Model ID (file name without the `.onnx` extension)
+    pub id: String,
+    /// Absolute path to the cached model
+    pub path: String,
+    /// File size in bytes
+    pub size_bytes: u64,
+    /// Last modification time in milliseconds since the Unix epoch
+    pub modified: i64,
+}
+
+/// Payload of the `onnx://upload-progress` event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadProgress {
+    pub bytes_written: u64,
+    pub total_bytes: Option<u64>,
+}
+
+/// Persisted model cache settings (`model-cache.json` in the app config dir)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ModelCacheConfig {
+    /// Maximum total size of the models directory, unlimited when None
+    cache_limit_bytes: Option<u64>,
+    /// Directory for upload temp files, the system temp dir when None
+    upload_temp_dir: Option<String>,
+}
+
+impl ModelCacheConfig {
+    fn load(app_handle: &tauri::AppHandle) -> Self {
+        Self::config_path(app_handle)
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(&self, app_handle: &tauri::AppHandle) -> Result<(), String> {
+        let path = Self::config_path(app_handle)
+            .ok_or_else(|| "Failed to get app config dir".to_string())?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create config dir: {}", e))?;
+        }
+        let contents = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        std::fs::write(path, contents)
+            .map_err(|e| format!("Failed to save model cache config: {}", e))
+    }
+
+    fn config_path(app_handle: &tauri::AppHandle) -> Option<PathBuf> {
+        app_handle
+            .path()
+            .app_config_dir()
+            .ok()
+            .map(|dir| dir.join("model-cache.json"))
+    }
+}
+
+/// Persisted engine settings (`engine-settings.json` in the app config dir)
+/// Only settings the user changed are stored, the rest keep their defaults
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EngineSettingsConfig {
+    preference: Option<ExecutionProviderPreference>,
+    device_index: Option<u32>,
+    intra_op_threads: Option<usize>,
+    inter_op_threads: Option<usize>,
+    optimization_level: Option<OptimizationLevel>,
+    session_count: Option<usize>,
+    max_batch_size: Option<usize>,
+    coreml_compute_units: Option<CoreMlComputeUnits>,
+    autosave_position: Option<bool>,
+}
+
+impl EngineSettingsConfig {
+    fn load(app_handle: &tauri::AppHandle) -> Self {
+        Self::config_path(app_handle)
+            .map(|path| Self::load_from(&path))
+            .unwrap_or_default()
+    }
+
+    fn save(&self, app_handle: &tauri::AppHandle) -> Result<(), String> {
+        let path = Self::config_path(app_handle)
+            .ok_or_else(|| "Failed to get app config dir".to_string())?;
+        self.save_to(&path)
+    }
+
+    /// Settings saved at `path`, the defaults if it is missing or unreadable
+    fn load_from(path: &Path) -> Self {
+        std::fs::read_to_string(path)
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    fn save_to(&self, path: &Path) -> Result<(), String> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create config dir: {}", e))?;
+        }
+        let contents = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        std::fs::write(path, contents).map_err(|e| format!("Failed to save engine settings: {}", e))
+    }
+
+    /// Load, change and save the settings in one go
+    fn update(app_handle: &tauri::AppHandle, change: impl FnOnce(&mut Self)) -> Result<(), String> {
+        let mut config = Self::load(app_handle);
+        change(&mut config);
+        config.save(app_handle)
+    }
+
+    fn config_path(app_handle: &tauri::AppHandle) -> Option<PathBuf> {
+        app_handle
+            .path()
+            .app_config_dir()
+            .ok()
+            .map(|dir| dir.join("engine-settings.json"))
+    }
+}
+
+/// Apply the persisted engine settings, called at startup before the first initialization
+pub fn restore_engine_settings(app_handle: &tauri::AppHandle) {
+    let config = EngineSettingsConfig::load(app_handle);
+    if let Some(preference) = config.preference {
+        onnx_engine::set_execution_provider_preference(preference);
+    }
+    if let Some(index) = config.device_index {
+        onnx_engine::set_device_index(index);
+    }
+    if let Some(threads) = config.intra_op_threads {
+        onnx_engine::set_intra_op_threads(threads);
+    }
+    if let Some(threads) = config.inter_op_threads {
+        onnx_engine::set_inter_op_threads(threads);
+    }
+    if let Some(level) = config.optimization_level {
+        onnx_engine::set_optimization_level(level);
+    }
+    if let Some(count) = config.session_count.filter(|&count| count > 0) {
+        onnx_engine::set_session_count(count);
+    }
+    if let Some(size) = config.max_batch_size.filter(|&size| size > 0) {
+        onnx_engine::set_max_batch_size(size);
+    }
+    if let Some(units) = config.coreml_compute_units {
+        onnx_engine::set_coreml_compute_units(units);
+    }
+    if config.autosave_position == Some(true) {
+        onnx_engine::set_autosave_path(last_position_path(app_handle));
+    }
+}
+
+/// Where the last analyzed position is autosaved (`last-position.json` in app data)
+fn last_position_path(app_handle: &tauri::AppHandle) -> Option<PathBuf> {
+    app_handle
+        .path()
+        .app_data_dir()
+        .ok()
+        .map(|dir| dir.join("last-position.json"))
+}
+
+/// Delete the autosaved position, called when the app exits cleanly
+pub fn clear_last_position() {
+    onnx_engine::clear_saved_position();
+}
+
+/// Turn autosaving the last analyzed position on or off, persisted across launches
+/// Off by default for privacy; turning it off deletes the saved position
+#[tauri::command]
+pub fn onnx_set_autosave_position(
+    enabled: bool,
+    app_handle: tauri::AppHandle,
+) -> Result<(), String> {
+    let path = if enabled {
+        let path = last_position_path(&app_handle)
+            .ok_or_else(|| "Failed to get app data dir".to_string())?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create app data dir: {}", e))?;
+        }
+        Some(path)
+    } else {
+        None
+    };
+    onnx_engine::set_autosave_path(path);
+    EngineSettingsConfig::update(&app_handle, |config| {
+        config.autosave_position = Some(enabled)
+    })
+}
+
+/// Get the position autosaved before the app last closed without a clean shutdown
+/// None if autosave is off or the last session ended cleanly
+#[tauri::command]
+pub fn onnx_get_last_position(app_handle: tauri::AppHandle) -> Option<SavedPosition> {
+    last_position_path(&app_handle).and_then(|path| onnx_engine::read_saved_position(&path))
+}
+
+/// Forward engine events that don't belong to a command, called at startup
+/// Emits `onnx://reinitialized` with the provider name when a provider change
+/// re-initializes the engine
+pub fn register_engine_events(app_handle: &tauri::AppHandle) {
+    let app_handle = app_handle.clone();
+    onnx_engine::set_reinit_listener(move |provider| {
+        let _ = app_handle.emit("onnx://reinitialized", provider);
+    });
+}
+
+/// Payload of the `onnx://game-progress` event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GameProgress {
+    pub analyzed: usize,
+    pub total: usize,
+}
+
+/// Payload of the `onnx://batch-result` event
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchResult<'a> {
+    pub request_id: &'a str,
+    /// Position of the result in the request's inputs
+    pub index: usize,
+    pub result: &'a AnalysisResult,
+}
+
+/// Payload of the `onnx://batch-done` event
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchDone {
+    pub request_id: String,
+    /// Number of results emitted
+    pub analyzed: usize,
+    /// Set if the batch failed or was cancelled
+    pub error: Option<OnnxError>,
+}
+
+/// Payload of the `onnx://ponder-update` event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PonderUpdate {
+    /// Number of symmetries averaged into `result`
+    pub symmetries: usize,
+    pub result: AnalysisResult,
+}
+
+/// State for a chunked model upload
+struct UploadState {
+    path: PathBuf,
+    bytes_written: u64,
+    total_bytes: Option<u64>,
+    last_progress_emit: Option<Instant>,
+}
+
+/// In-progress uploads keyed by upload session ID
+static MODEL_UPLOADS: Mutex<Option<HashMap<String, UploadState>>> = Mutex::new(None);
+
+/// Counter used to generate upload session IDs
+static NEXT_UPLOAD_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Uploads whose temp file hasn't been written for this long are abandoned
+const ABANDONED_UPLOAD_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// Run a closure with the upload map, creating it on first use
+fn with_uploads<T>(f: impl FnOnce(&mut HashMap<String, UploadState>) -> T) -> T {
+    let mut uploads = MODEL_UPLOADS.lock().unwrap();
+    f(uploads.get_or_insert_with(HashMap::new))
+}
 
-/// Get the temp file path for model upload
-fn get_model_temp_path() -> PathBuf {
-    std::env::temp_dir().join(format!("kaya-model-{}.onnx", std::process::id()))
+/// Remove uploads whose temp files are older than an hour
+fn cleanup_abandoned_uploads() {
+    with_uploads(|uploads| {
+        uploads.retain(|id, upload| {
+            let age = std::fs::metadata(&upload.path)
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|t| SystemTime::now().duration_since(t).ok());
+            let abandoned = age.is_none_or(|age| age >= ABANDONED_UPLOAD_AGE);
+            if abandoned {
+                log::info!("[OnnxUpload] Removing abandoned upload {}", id);
+                let _ = std::fs::remove_file(&upload.path);
+            }
+            !abandoned
+        });
+    });
+}
+
+/// Minimum interval between progress events (~10/second)
+const PROGRESS_EVENT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Check whether a progress event is due, updating the last-emit time if so
+/// The final event (`done`) is always emitted
+fn progress_event_due(last_emit: &mut Option<Instant>, done: bool) -> bool {
+    let due = done || last_emit.is_none_or(|t| t.elapsed() >= PROGRESS_EVENT_INTERVAL);
+    if due {
+        *last_emit = Some(Instant::now());
+    }
+    due
+}
+
+/// Get the directory upload temp files are written to
+fn get_upload_temp_dir(app_handle: &tauri::AppHandle) -> PathBuf {
+    ModelCacheConfig::load(app_handle)
+        .upload_temp_dir
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir)
+}
+
+/// Get the temp file path for a model upload session
+fn get_model_temp_path(upload_id: &str, app_handle: &tauri::AppHandle) -> PathBuf {
+    get_upload_temp_dir(app_handle).join(format!(
+        "kaya-model-{}-{}.onnx",
+        std::process::id(),
+        upload_id
+    ))
+}
+
+/// Delete upload temp files left behind by earlier runs, called at startup
+/// Files from another live instance are kept unless they look abandoned
+pub fn cleanup_stale_upload_files(app_handle: &tauri::AppHandle) {
+    let own_prefix = format!("kaya-model-{}-", std::process::id());
+    let mut dirs = vec![std::env::temp_dir()];
+    let configured = get_upload_temp_dir(app_handle);
+    if !dirs.contains(&configured) {
+        dirs.push(configured);
+    }
+    for entry in dirs
+        .iter()
+        .filter_map(|dir| std::fs::read_dir(dir).ok())
+        .flatten()
+        .flatten()
+    {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !name.starts_with("kaya-model-") || !name.ends_with(".onnx") {
+            continue;
+        }
+        let age = entry
+            .metadata()
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| SystemTime::now().duration_since(t).ok());
+        // A file with this process ID is from an earlier run that had the same ID
+        let stale =
+            name.starts_with(&own_prefix) || age.is_none_or(|age| age >= ABANDONED_UPLOAD_AGE);
+        if stale {
+            log::info!("[OnnxUpload] Removing stale temp file {}", name);
+            let _ = std::fs::remove_file(entry.path());
+        }
+    }
+}
+
+/// Get the model cache directory under app data
+fn get_models_dir(app_handle: &tauri::AppHandle) -> Result<PathBuf, String> {
+    let app_data = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to get app data dir: {}", e))?;
+    Ok(app_data.join("models"))
 }
 
 /// Start a chunked model upload
-/// Returns the temp file path where chunks will be written
+/// `total_bytes` is the expected size, used as the progress denominator
+/// Returns the upload session ID to pass to subsequent chunk/finish calls
 #[tauri::command]
-pub async fn onnx_start_upload() -> Result<String, String> {
-    let path = get_model_temp_path();
-    
+pub async fn onnx_start_upload(
+    total_bytes: Option<u64>,
+    app_handle: tauri::AppHandle,
+) -> Result<String, String> {
+    cleanup_abandoned_uploads();
+
+    let upload_id = format!("upload-{}", NEXT_UPLOAD_ID.fetch_add(1, Ordering::SeqCst));
+    let path = get_model_temp_path(&upload_id, &app_handle);
+
     // Create/truncate the file
-    File::create(&path)
-        .map_err(|e| format!("Failed to create temp file: {}", e))?;
-    
+    File::create(&path).map_err(|e| format!("Failed to create temp file: {}", e))?;
+
     // Store the path for subsequent chunks
-    let mut upload_path = MODEL_UPLOAD_PATH.lock().unwrap();
-    *upload_path = Some(path.clone());
-    
-    Ok(path.to_string_lossy().to_string())
+    with_uploads(|uploads| {
+        uploads.insert(
+            upload_id.clone(),
+            UploadState {
+                path,
+                bytes_written: 0,
+                total_bytes,
+                last_progress_emit: None,
+            },
+        );
+    });
+
+    Ok(upload_id)
 }
 
 /// Upload a chunk of the model (base64 encoded for efficient IPC)
 /// Using base64 because JSON array serialization of bytes is very slow
+/// Emits throttled `onnx://upload-progress` events
 #[tauri::command]
-pub async fn onnx_upload_chunk(chunk_base64: String) -> Result<(), String> {
-    let path = {
-        let upload_path = MODEL_UPLOAD_PATH.lock().unwrap();
-        upload_path.clone().ok_or("No upload in progress")?
-    };
-    
+pub async fn onnx_upload_chunk(
+    upload_id: String,
+    chunk_base64: String,
+    app_handle: tauri::AppHandle,
+) -> Result<(), String> {
+    let path = with_uploads(|uploads| uploads.get(&upload_id).map(|u| u.path.clone()))
+        .ok_or_else(|| format!("No upload in progress with ID {}", upload_id))?;
+
     // Decode base64 and write in a blocking task to not block the runtime
-    tokio::task::spawn_blocking(move || {
+    let written = tokio::task::spawn_blocking(move || {
         let chunk_bytes = BASE64
             .decode(&chunk_base64)
             .map_err(|e| format!("Failed to decode base64 chunk: {}", e))?;
-        
+
         let mut file = OpenOptions::new()
             .append(true)
             .open(&path)
             .map_err(|e| format!("Failed to open temp file: {}", e))?;
-        
+
         file.write_all(&chunk_bytes)
             .map_err(|e| format!("Failed to write chunk: {}", e))?;
-        
-        Ok::<(), String>(())
+
+        Ok::<u64, String>(chunk_bytes.len() as u64)
     })
     .await
-    .map_err(|e| format!("Task failed: {}", e))?
+    .map_err(|e| format!("Task failed: {}", e))??;
+
+    let progress = with_uploads(|uploads| {
+        uploads.get_mut(&upload_id).and_then(|u| {
+            u.bytes_written += written;
+            let done = u.total_bytes.is_some_and(|total| u.bytes_written >= total);
+            progress_event_due(&mut u.last_progress_emit, done).then_some(UploadProgress {
+                bytes_written: u.bytes_written,
+                total_bytes: u.total_bytes,
+            })
+        })
+    });
+    if let Some(progress) = progress {
+        let _ = app_handle.emit("onnx://upload-progress", progress);
+    }
+
+    Ok(())
+}
+
+/// Cancel an in-progress upload and delete its temp file
+/// Cancels every in-progress upload when `upload_id` is None
+/// Returns false if there was nothing to cancel
+#[tauri::command]
+pub async fn onnx_cancel_upload(upload_id: Option<String>) -> Result<bool, String> {
+    let cancelled: Vec<UploadState> = with_uploads(|uploads| match upload_id {
+        Some(id) => uploads.remove(&id).into_iter().collect(),
+        None => uploads.drain().map(|(_, upload)| upload).collect(),
+    });
+
+    for upload in &cancelled {
+        if let Err(e) = std::fs::remove_file(&upload.path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                return Err(format!("Failed to delete temp file: {}", e));
+            }
+        }
+    }
+
+    Ok(!cancelled.is_empty())
+}
+
+/// Compute the hex-encoded SHA-256 of a file
+fn compute_sha256(path: &Path) -> Result<String, String> {
+    let mut file = File::open(path).map_err(|e| format!("Failed to open {:?}: {}", path, e))?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 1024 * 1024];
+    loop {
+        let read = file
+            .read(&mut buffer)
+            .map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Check that a model ID can name files in the models dir: it is used as a file
+/// name prefix, so path separators and `..` could reach files outside it
+fn validate_model_id(model_id: &str) -> Result<(), String> {
+    // ':' would be a drive prefix on Windows, replacing the models dir when joined
+    let valid = !model_id.is_empty()
+        && !model_id.contains(['/', '\\', ':', '\0'])
+        && !model_id.contains("..");
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("Invalid model ID: {:?}", model_id))
+    }
+}
+
+/// Get the path of a cached model by ID, failing if the ID is invalid or the model isn't cached
+fn find_cached_model(models_dir: &Path, model_id: &str) -> Result<PathBuf, String> {
+    validate_model_id(model_id)?;
+    let cached_path = get_cached_model_path(models_dir, model_id);
+    if !cached_path.exists() {
+        return Err(format!("Model not cached: {}", model_id));
+    }
+    Ok(cached_path)
+}
+
+/// Get the path of a cached model (`<id>.onnx`)
+fn get_cached_model_path(models_dir: &Path, model_id: &str) -> PathBuf {
+    models_dir.join(format!("{}.onnx", model_id))
+}
+
+/// Get the checksum sidecar path for a cached model (`<id>.sha256`)
+fn get_checksum_path(models_dir: &Path, model_id: &str) -> PathBuf {
+    models_dir.join(format!("{}.sha256", model_id))
+}
+
+/// Get the companion config sidecar path for a cached model (`<id>.config.json`)
+fn get_config_path(models_dir: &Path, model_id: &str) -> PathBuf {
+    models_dir.join(format!("{}.config.json", model_id))
+}
+
+/// Store the companion config of a cached model next to it
+fn store_model_config(
+    models_dir: &Path,
+    model_id: &str,
+    config: &ModelConfig,
+) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(config)
+        .map_err(|e| format!("Failed to serialize model config: {}", e))?;
+    std::fs::write(get_config_path(models_dir, model_id), json)
+        .map_err(|e| format!("Failed to write model config: {}", e))
+}
+
+/// Read the stored companion config of a cached model, if it has one
+fn read_stored_config(models_dir: &Path, model_id: &str) -> Result<Option<ModelConfig>, String> {
+    let path = get_config_path(models_dir, model_id);
+    if !path.exists() {
+        return Ok(None);
+    }
+    onnx_engine::read_model_config(&path).map(Some)
+}
+
+/// Read the stored checksum of a cached model, if it has one
+fn read_stored_checksum(models_dir: &Path, model_id: &str) -> Option<String> {
+    std::fs::read_to_string(get_checksum_path(models_dir, model_id))
+        .ok()
+        .map(|checksum| checksum.trim().to_string())
+}
+
+/// Move an uploaded model into the app data cache and record its checksum
+/// External data files the model references are copied from `source_dir`
+fn save_uploaded_model(
+    temp_path: &Path,
+    model_id: &str,
+    sha256: &str,
+    source_dir: Option<&Path>,
+    app_handle: &tauri::AppHandle,
+) -> Result<PathBuf, String> {
+    validate_model_id(model_id)?;
+    let models_dir = get_models_dir(app_handle)?;
+    std::fs::create_dir_all(&models_dir)
+        .map_err(|e| format!("Failed to create models dir: {}", e))?;
+
+    let cached_path = get_cached_model_path(&models_dir, model_id);
+    let external_data = plan_external_data_copies(temp_path, source_dir, &models_dir)?;
+
+    // Move temp file to cache location
+    std::fs::rename(temp_path, &cached_path)
+        .or_else(|_| {
+            // If rename fails (cross-device), copy and delete
+            std::fs::copy(temp_path, &cached_path)?;
+            std::fs::remove_file(temp_path)
+        })
+        .map_err(|e| format!("Failed to cache model: {}", e))?;
+
+    for (source, target) in external_data {
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create models dir: {}", e))?;
+        }
+        std::fs::copy(&source, &target)
+            .map_err(|e| format!("Failed to cache external data {:?}: {}", source, e))?;
+    }
+
+    std::fs::write(get_checksum_path(&models_dir, model_id), sha256)
+        .map_err(|e| format!("Failed to write model checksum: {}", e))?;
+
+    if let Some(limit) = ModelCacheConfig::load(app_handle).cache_limit_bytes {
+        evict_cached_models(&models_dir, limit, &cached_path)?;
+    }
+
+    Ok(cached_path)
+}
+
+/// External data files to copy into the cache for a model, as (source, target) pairs
+/// ORT resolves external data relative to the model, so the files keep their names
+/// next to it in the cache, where cached models may share them. A file already
+/// cached with the same contents is reused; one with different contents is an error,
+/// as replacing it would break the model using it. Everything is checked before the
+/// cache is touched.
+fn plan_external_data_copies(
+    model_path: &Path,
+    source_dir: Option<&Path>,
+    models_dir: &Path,
+) -> Result<Vec<(PathBuf, PathBuf)>, String> {
+    let mut copies = Vec::new();
+    for relative in onnx_engine::external_data_files(model_path)? {
+        let source = source_dir
+            .map(|dir| dir.join(&relative))
+            .filter(|source| source.is_file())
+            .ok_or_else(|| {
+                format!(
+                    "Missing external data file {:?} referenced by the model",
+                    relative
+                )
+            })?;
+        let target = models_dir.join(&relative);
+        if !target.exists() {
+            copies.push((source, target));
+        } else if compute_sha256(&target)? != compute_sha256(&source)? {
+            return Err(format!(
+                "External data file {:?} conflicts with another cached model",
+                relative
+            ));
+        }
+    }
+    Ok(copies)
+}
+
+/// External data files referenced by any model left in the models directory,
+/// optimized graphs included
+fn external_data_in_use(models_dir: &Path) -> std::collections::HashSet<PathBuf> {
+    std::fs::read_dir(models_dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().is_some_and(|ext| ext == "onnx"))
+                .flat_map(|path| onnx_engine::external_data_files(&path).unwrap_or_default())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Total size in bytes of all files in the models directory
+fn get_models_dir_size(models_dir: &Path) -> u64 {
+    std::fs::read_dir(models_dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter_map(|entry| entry.metadata().ok())
+                .filter(|metadata| metadata.is_file())
+                .map(|metadata| metadata.len())
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+/// Delete a cached model along with its optimized graph, checksum and config sidecars
+/// Its external data files are deleted too, unless another cached model still uses them
+fn remove_cached_model(models_dir: &Path, model_id: &str) -> Result<(), String> {
+    let cached_path = get_cached_model_path(models_dir, model_id);
+    let external_data = onnx_engine::external_data_files(&cached_path).unwrap_or_default();
+    std::fs::remove_file(&cached_path)
+        .map_err(|e| format!("Failed to delete cached model: {}", e))?;
+    onnx_engine::remove_optimized_model(&cached_path);
+    let _ = std::fs::remove_file(get_checksum_path(models_dir, model_id));
+    let _ = std::fs::remove_file(get_config_path(models_dir, model_id));
+
+    let in_use = external_data_in_use(models_dir);
+    for relative in external_data
+        .iter()
+        .filter(|relative| !in_use.contains(*relative))
+    {
+        let _ = std::fs::remove_file(models_dir.join(relative));
+    }
+    Ok(())
+}
+
+/// Delete least-recently-modified models until the directory fits in `limit` bytes
+/// Never deletes `keep` (the model just written) or the currently loaded model
+fn evict_cached_models(models_dir: &Path, limit: u64, keep: &Path) -> Result<(), String> {
+    let mut total = get_models_dir_size(models_dir);
+    if total <= limit {
+        return Ok(());
+    }
+
+    let loaded = onnx_engine::get_loaded_model_path();
+    let mut candidates: Vec<(String, PathBuf, SystemTime)> = std::fs::read_dir(models_dir)
+        .map_err(|e| format!("Failed to read models dir: {}", e))?
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let id = name.strip_suffix(".onnx")?;
+            if id.ends_with(".opt") {
+                return None;
+            }
+            let modified = entry.metadata().and_then(|m| m.modified()).ok()?;
+            Some((id.to_string(), entry.path(), modified))
+        })
+        .filter(|(_, path, _)| path != keep && loaded.as_deref() != Some(path.as_path()))
+        .collect();
+    candidates.sort_by_key(|(_, _, modified)| *modified);
+
+    for (id, _, _) in candidates {
+        if total <= limit {
+            break;
+        }
+        remove_cached_model(models_dir, &id)?;
+        log::info!("[ModelCache] Evicted {} to stay under the cache limit", id);
+        total = get_models_dir_size(models_dir);
+    }
+
+    Ok(())
+}
+
+/// Board size to run the warm-up inference at, None when warm-up is disabled
+/// Defaults to 19x19 when no board size is given
+fn warmup_board_size(warmup: Option<bool>, board_size: Option<u32>) -> Option<usize> {
+    warmup
+        .unwrap_or(true)
+        .then(|| board_size.unwrap_or(19) as usize)
+}
+
+/// Read a model's companion config, if a path to one was given
+fn read_config(config_path: Option<&str>) -> Result<Option<ModelConfig>, String> {
+    config_path
+        .map(|path| onnx_engine::read_model_config(Path::new(path)))
+        .transpose()
 }
 
 /// Finish the upload and initialize the ONNX engine from the temp file
 /// Optionally caches the model with a given ID for faster future loads
+/// If `expected_sha256` is given, the upload is rejected on checksum mismatch
+/// `source_path` is the original model file, whose directory holds any external
+/// data files the model references; they are cached along with it
+/// Runs a warm-up inference unless `warmup` is false
+/// `config_path` is the model's companion config JSON, if it ships with one
 #[tauri::command]
-pub async fn onnx_finish_upload(model_id: Option<String>, app_handle: tauri::AppHandle) -> Result<(), String> {
-    let temp_path = {
-        let mut upload_path = MODEL_UPLOAD_PATH.lock().unwrap();
-        upload_path.take().ok_or("No upload in progress")?
-    };
-    
+pub async fn onnx_finish_upload(
+    upload_id: String,
+    model_id: Option<String>,
+    expected_sha256: Option<String>,
+    source_path: Option<String>,
+    warmup: Option<bool>,
+    config_path: Option<String>,
+    app_handle: tauri::AppHandle,
+) -> Result<(), String> {
+    if let Some(id) = &model_id {
+        validate_model_id(id)?;
+    }
+    let temp_path = with_uploads(|uploads| uploads.remove(&upload_id).map(|u| u.path))
+        .ok_or_else(|| format!("No upload in progress with ID {}", upload_id))?;
+
+    // Hash the assembled file so truncated uploads never reach the cache
+    let hash_path = temp_path.clone();
+    let sha256 = tokio::task::spawn_blocking(move || compute_sha256(&hash_path))
+        .await
+        .map_err(|e| format!("Task failed: {}", e))??;
+
+    if let Some(expected) = expected_sha256 {
+        if !expected.eq_ignore_ascii_case(&sha256) {
+            let _ = std::fs::remove_file(&temp_path);
+            return Err(format!(
+                "Model checksum mismatch: expected {}, got {}",
+                expected, sha256
+            ));
+        }
+    }
+
     // If model_id provided, cache the model in app data directory
-    let final_path = if let Some(id) = model_id {
-        let app_data = app_handle.path().app_data_dir()
-            .map_err(|e| format!("Failed to get app data dir: {}", e))?;
-        let models_dir = app_data.join("models");
-        std::fs::create_dir_all(&models_dir)
-            .map_err(|e| format!("Failed to create models dir: {}", e))?;
-        
-        let cached_path = models_dir.join(format!("{}.onnx", id));
-        
-        // Move temp file to cache location
-        std::fs::rename(&temp_path, &cached_path)
-            .or_else(|_| {
-                // If rename fails (cross-device), copy and delete
-                std::fs::copy(&temp_path, &cached_path)?;
-                std::fs::remove_file(&temp_path)
-            })
-            .map_err(|e| format!("Failed to cache model: {}", e))?;
-        
-        cached_path
+    let cache_optimized = model_id.is_some();
+    let final_path = if let Some(id) = &model_id {
+        let source_dir = source_path
+            .as_deref()
+            .and_then(|path| Path::new(path).parent());
+        save_uploaded_model(&temp_path, id, &sha256, source_dir, &app_handle)?
     } else {
         temp_path
     };
-    
+
     let path_str = final_path.to_string_lossy().to_string();
-    
+
+    let models_dir = get_models_dir(&app_handle)?;
     tokio::task::spawn_blocking(move || {
-        onnx_engine::initialize_engine_from_path(&path_str)
+        let config = read_config(config_path.as_deref())?;
+        // Keep the config with the cached model, so loading it from the cache reuses it
+        if let (Some(id), Some(config)) = (&model_id, &config) {
+            store_model_config(&models_dir, id, config)?;
+        }
+        let options = ModelLoadOptions {
+            warmup: warmup_board_size(warmup, None),
+            cache_optimized,
+            model_id,
+            sha256: Some(sha256),
+            config,
+        };
+        onnx_engine::initialize_engine_from_path(&path_str, options)
     })
     .await
     .map_err(|e| format!("Task failed: {}", e))?
 }
 
+/// Check that a file starts like a serialized ONNX ModelProto
+/// The first field is `ir_version` (field 1, varint), which is a small positive number
+fn is_onnx_model_file(path: &Path) -> Result<bool, String> {
+    let mut header = [0u8; 2];
+    let mut file = File::open(path).map_err(|e| format!("Failed to open {:?}: {}", path, e))?;
+    if file.read_exact(&mut header).is_err() {
+        return Ok(false);
+    }
+    Ok(header[0] == 0x08 && (1..0x80).contains(&header[1]))
+}
+
+/// Download a model from a URL straight into the model cache
+/// Emits throttled `onnx://upload-progress` events based on Content-Length
+/// Resumes from a leftover `<id>.onnx.part` file via an HTTP Range request
+/// Returns the cached model path; not available on Android
+#[tauri::command]
+pub async fn onnx_download_model(
+    url: String,
+    model_id: String,
+    app_handle: tauri::AppHandle,
+) -> Result<String, String> {
+    #[cfg(not(target_os = "android"))]
+    {
+        download_model(&url, &model_id, &app_handle)
+            .await
+            .map(|path| path.to_string_lossy().to_string())
+    }
+
+    #[cfg(target_os = "android")]
+    {
+        let _ = (url, model_id, app_handle);
+        Err("Native model download is not supported on Android".to_string())
+    }
+}
+
+#[cfg(not(target_os = "android"))]
+async fn download_model(
+    url: &str,
+    model_id: &str,
+    app_handle: &tauri::AppHandle,
+) -> Result<PathBuf, String> {
+    use futures::StreamExt;
+
+    validate_model_id(model_id)?;
+    let models_dir = get_models_dir(app_handle)?;
+    std::fs::create_dir_all(&models_dir)
+        .map_err(|e| format!("Failed to create models dir: {}", e))?;
+    let part_path = models_dir.join(format!("{}.onnx.part", model_id));
+
+    let resume_from = std::fs::metadata(&part_path).map(|m| m.len()).unwrap_or(0);
+    let mut request = reqwest::Client::new().get(url);
+    if resume_from > 0 {
+        request = request.header(reqwest::header::RANGE, format!("bytes={}-", resume_from));
+    }
+
+    let response = request
+        .send()
+        .await
+        .map_err(|e| format!("Failed to download model: {}", e))?;
+    let status = response.status();
+    if status == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+        // The partial file is stale or larger than the remote one, start over next time
+        let _ = std::fs::remove_file(&part_path);
+        return Err("Failed to resume model download, please retry".to_string());
+    }
+    if !status.is_success() {
+        return Err(format!("Failed to download model: HTTP {}", status));
+    }
+
+    // Servers that ignore the Range header send the whole file with 200 OK
+    let resumed = resume_from > 0 && status == reqwest::StatusCode::PARTIAL_CONTENT;
+    let mut bytes_written = if resumed { resume_from } else { 0 };
+    let total_bytes = response.content_length().map(|len| len + bytes_written);
+    if resumed {
+        log::info!("[Download] Resuming {} from byte {}", model_id, resume_from);
+    }
+
+    let mut file = if resumed {
+        OpenOptions::new().append(true).open(&part_path)
+    } else {
+        File::create(&part_path)
+    }
+    .map_err(|e| format!("Failed to open download file: {}", e))?;
+
+    let mut last_progress_emit = None;
+    let mut stream = response.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|e| format!("Model download interrupted: {}", e))?;
+        file.write_all(&chunk)
+            .map_err(|e| format!("Failed to write download file: {}", e))?;
+        bytes_written += chunk.len() as u64;
+
+        let done = total_bytes.is_some_and(|total| bytes_written >= total);
+        if progress_event_due(&mut last_progress_emit, done) {
+            let _ = app_handle.emit(
+                "onnx://upload-progress",
+                UploadProgress {
+                    bytes_written,
+                    total_bytes,
+                },
+            );
+        }
+    }
+    drop(file);
+
+    if total_bytes.is_some_and(|total| bytes_written < total) {
+        return Err(format!(
+            "Model download incomplete: got {} of {} bytes",
+            bytes_written,
+            total_bytes.unwrap_or_default()
+        ));
+    }
+
+    if !is_onnx_model_file(&part_path)? {
+        let _ = std::fs::remove_file(&part_path);
+        return Err("Downloaded file is not a valid ONNX model".to_string());
+    }
+
+    let hash_path = part_path.clone();
+    let sha256 = tokio::task::spawn_blocking(move || compute_sha256(&hash_path))
+        .await
+        .map_err(|e| format!("Task failed: {}", e))??;
+
+    save_uploaded_model(&part_path, model_id, &sha256, None, app_handle)
+}
+
 /// Check if a model is cached and return its path
 #[tauri::command]
-pub async fn onnx_get_cached_model(model_id: String, app_handle: tauri::AppHandle) -> Result<Option<String>, String> {
-    let app_data = app_handle.path().app_data_dir()
-        .map_err(|e| format!("Failed to get app data dir: {}", e))?;
-    let cached_path = app_data.join("models").join(format!("{}.onnx", model_id));
-    
+pub async fn onnx_get_cached_model(
+    model_id: String,
+    app_handle: tauri::AppHandle,
+) -> Result<Option<String>, String> {
+    validate_model_id(&model_id)?;
+    let cached_path = get_cached_model_path(&get_models_dir(&app_handle)?, &model_id);
+
     if cached_path.exists() {
         Ok(Some(cached_path.to_string_lossy().to_string()))
     } else {
@@ -131,16 +977,80 @@ pub async fn onnx_get_cached_model(model_id: String, app_handle: tauri::AppHandl
     }
 }
 
+/// List all models in the app data cache
+/// Optimized graph sidecars (`.opt.onnx`) are not listed
+#[tauri::command]
+pub async fn onnx_list_cached_models(
+    app_handle: tauri::AppHandle,
+) -> Result<Vec<CachedModelInfo>, String> {
+    let models_dir = get_models_dir(&app_handle)?;
+    let entries = match std::fs::read_dir(&models_dir) {
+        Ok(entries) => entries,
+        // No models cached yet
+        Err(_) => return Ok(vec![]),
+    };
+
+    let mut models = vec![];
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        let Some(id) = name.strip_suffix(".onnx") else {
+            continue;
+        };
+        if id.ends_with(".opt") {
+            continue;
+        }
+        let metadata = entry
+            .metadata()
+            .map_err(|e| format!("Failed to read metadata for {}: {}", name, e))?;
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_millis() as i64);
+        models.push(CachedModelInfo {
+            id: id.to_string(),
+            path: path.to_string_lossy().to_string(),
+            size_bytes: metadata.len(),
+            modified,
+        });
+    }
+    models.sort_by(|a, b| a.id.cmp(&b.id));
+    Ok(models)
+}
+
+/// Recompute a cached model's SHA-256 and compare it with the stored checksum
+/// Returns false if the model is corrupt
+#[tauri::command]
+pub async fn onnx_verify_cached_model(
+    model_id: String,
+    app_handle: tauri::AppHandle,
+) -> Result<bool, String> {
+    let models_dir = get_models_dir(&app_handle)?;
+    let cached_path = find_cached_model(&models_dir, &model_id)?;
+
+    let expected = std::fs::read_to_string(get_checksum_path(&models_dir, &model_id))
+        .map_err(|e| format!("No stored checksum for model {}: {}", model_id, e))?;
+
+    let actual = tokio::task::spawn_blocking(move || compute_sha256(&cached_path))
+        .await
+        .map_err(|e| format!("Task failed: {}", e))??;
+
+    Ok(expected.trim().eq_ignore_ascii_case(&actual))
+}
+
 /// Delete a cached model from the app data directory
 #[tauri::command]
-pub async fn onnx_delete_cached_model(model_id: String, app_handle: tauri::AppHandle) -> Result<bool, String> {
-    let app_data = app_handle.path().app_data_dir()
-        .map_err(|e| format!("Failed to get app data dir: {}", e))?;
-    let cached_path = app_data.join("models").join(format!("{}.onnx", model_id));
-    
+pub async fn onnx_delete_cached_model(
+    model_id: String,
+    app_handle: tauri::AppHandle,
+) -> Result<bool, String> {
+    validate_model_id(&model_id)?;
+    let models_dir = get_models_dir(&app_handle)?;
+    let cached_path = get_cached_model_path(&models_dir, &model_id);
+
     if cached_path.exists() {
-        std::fs::remove_file(&cached_path)
-            .map_err(|e| format!("Failed to delete cached model: {}", e))?;
+        remove_cached_model(&models_dir, &model_id)?;
         Ok(true)
     } else {
         // Model wasn't cached, nothing to delete
@@ -148,11 +1058,50 @@ pub async fn onnx_delete_cached_model(model_id: String, app_handle: tauri::AppHa
     }
 }
 
+/// Set the maximum total size of the model cache
+/// Least-recently-modified models are evicted when a new model is cached
+#[tauri::command]
+pub async fn onnx_set_cache_limit_bytes(
+    limit: u64,
+    app_handle: tauri::AppHandle,
+) -> Result<(), String> {
+    let mut config = ModelCacheConfig::load(&app_handle);
+    config.cache_limit_bytes = Some(limit);
+    config.save(&app_handle)
+}
+
+/// Set the directory upload temp files are written to, or None for the system temp dir
+/// Lets large models upload on systems with a small or read-only temp dir.
+/// The directory is created if needed and must be writable.
+#[tauri::command]
+pub async fn onnx_set_temp_dir(
+    path: Option<String>,
+    app_handle: tauri::AppHandle,
+) -> Result<(), String> {
+    if let Some(dir) = &path {
+        std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create temp dir: {}", e))?;
+        let probe = Path::new(dir).join(format!("kaya-write-test-{}", std::process::id()));
+        std::fs::write(&probe, b"").map_err(|e| format!("Temp dir is not writable: {}", e))?;
+        let _ = std::fs::remove_file(&probe);
+    }
+    let mut config = ModelCacheConfig::load(&app_handle);
+    config.upload_temp_dir = path;
+    config.save(&app_handle)
+}
+
+/// Get the total size in bytes of the model cache
+#[tauri::command]
+pub async fn onnx_get_cache_usage(app_handle: tauri::AppHandle) -> Result<u64, String> {
+    Ok(get_models_dir_size(&get_models_dir(&app_handle)?))
+}
+
 /// Initialize the ONNX engine with model bytes (raw Vec<u8>)
 /// Note: This may be slow for large models due to JSON serialization
+/// Runs a warm-up inference unless `warmup` is false
 #[tauri::command]
-pub async fn onnx_initialize(model_bytes: Vec<u8>) -> Result<(), String> {
-    tokio::task::spawn_blocking(move || onnx_engine::initialize_engine(&model_bytes))
+pub async fn onnx_initialize(model_bytes: Vec<u8>, warmup: Option<bool>) -> Result<(), String> {
+    let warmup = warmup_board_size(warmup, None);
+    tokio::task::spawn_blocking(move || onnx_engine::initialize_engine(&model_bytes, warmup))
         .await
         .map_err(|e| format!("Task failed: {}", e))?
 }
@@ -160,48 +1109,577 @@ pub async fn onnx_initialize(model_bytes: Vec<u8>) -> Result<(), String> {
 /// Initialize the ONNX engine with base64-encoded model bytes
 /// This is faster for large models as strings serialize more efficiently than byte arrays
 #[tauri::command]
-pub async fn onnx_initialize_base64(model_base64: String) -> Result<(), String> {
+pub async fn onnx_initialize_base64(
+    model_base64: String,
+    warmup: Option<bool>,
+) -> Result<(), String> {
+    let warmup = warmup_board_size(warmup, None);
     tokio::task::spawn_blocking(move || {
         let model_bytes = BASE64
             .decode(&model_base64)
             .map_err(|e| format!("Failed to decode base64: {}", e))?;
-        onnx_engine::initialize_engine(&model_bytes)
+        onnx_engine::initialize_engine(&model_bytes, warmup)
     })
     .await
     .map_err(|e| format!("Task failed: {}", e))?
 }
 
 /// Initialize the ONNX engine from a file path
+/// Runs a warm-up inference unless `warmup` is false, at `board_size` (default 19)
+/// so size-dependent kernels are selected for the board actually played
+/// Models from the app cache also get their optimized graph cached
+/// `config_path` is the model's companion config JSON, if it ships with one
 #[tauri::command]
-pub async fn onnx_initialize_from_path(model_path: String) -> Result<(), String> {
-    tokio::task::spawn_blocking(move || onnx_engine::initialize_engine_from_path(&model_path))
-        .await
-        .map_err(|e| format!("Task failed: {}", e))?
+pub async fn onnx_initialize_from_path(
+    model_path: String,
+    warmup: Option<bool>,
+    board_size: Option<u32>,
+    config_path: Option<String>,
+    app_handle: tauri::AppHandle,
+) -> Result<(), String> {
+    let warmup = warmup_board_size(warmup, board_size);
+    let models_dir = get_models_dir(&app_handle).ok();
+    let cache_optimized = models_dir
+        .as_ref()
+        .is_some_and(|dir| Path::new(&model_path).starts_with(dir));
+    // Models picked from the cache by path still report their ID and checksum
+    let (model_id, sha256) = match &models_dir {
+        Some(dir) if cache_optimized => {
+            let model_id = Path::new(&model_path)
+                .file_name()
+                .and_then(|name| name.to_str()?.strip_suffix(".onnx"))
+                .map(str::to_string);
+            let sha256 = model_id
+                .as_deref()
+                .and_then(|id| read_stored_checksum(dir, id));
+            (model_id, sha256)
+        }
+        _ => (None, None),
+    };
+    tokio::task::spawn_blocking(move || {
+        // A cached model without an explicit config gets the one stored with it
+        let config = match (read_config(config_path.as_deref())?, &models_dir, &model_id) {
+            (Some(config), _, _) => Some(config),
+            (None, Some(dir), Some(id)) if cache_optimized => read_stored_config(dir, id)?,
+            (None, _, _) => None,
+        };
+        let options = ModelLoadOptions {
+            warmup,
+            cache_optimized,
+            model_id,
+            sha256,
+            config,
+        };
+        onnx_engine::initialize_engine_from_path(&model_path, options)
+    })
+    .await
+    .map_err(|e| format!("Task failed: {}", e))?
 }
 
-/// Analyze a single position
+/// Initialize the ONNX engine from a model in the app cache, by ID
+/// Fails if the model isn't cached; the optimized graph is cached as well
+/// Warms up like `onnx_initialize_from_path`
 #[tauri::command]
-pub async fn onnx_analyze(
-    sign_map: Vec<Vec<i8>>,
-    options: AnalysisOptions,
-) -> Result<AnalysisResult, String> {
-    tokio::task::spawn_blocking(move || onnx_engine::analyze_position(sign_map, options))
+pub async fn onnx_initialize_from_cache(
+    model_id: String,
+    warmup: Option<bool>,
+    board_size: Option<u32>,
+    app_handle: tauri::AppHandle,
+) -> Result<(), String> {
+    let warmup = warmup_board_size(warmup, board_size);
+    let models_dir = get_models_dir(&app_handle)?;
+    let cached_path = find_cached_model(&models_dir, &model_id)?;
+    let sha256 = read_stored_checksum(&models_dir, &model_id);
+    tokio::task::spawn_blocking(move || {
+        let options = ModelLoadOptions {
+            warmup,
+            cache_optimized: true,
+            config: read_stored_config(&models_dir, &model_id)?,
+            model_id: Some(model_id),
+            sha256,
+        };
+        onnx_engine::initialize_engine_from_path(&cached_path.to_string_lossy(), options)
+    })
+    .await
+    .map_err(|e| format!("Task failed: {}", e))?
+}
+
+/// Load a cached model, by ID, into a standby slot without switching to it
+/// The active model keeps analyzing meanwhile; the standby sessions use the current
+/// provider preference, the config stored with the model, and are warmed up like
+/// `onnx_initialize_from_cache`
+/// Switch with `onnx_activate_preloaded`
+#[tauri::command]
+pub async fn onnx_preload_model(
+    model_id: String,
+    warmup: Option<bool>,
+    board_size: Option<u32>,
+    app_handle: tauri::AppHandle,
+) -> Result<(), String> {
+    let warmup = warmup_board_size(warmup, board_size);
+    let models_dir = get_models_dir(&app_handle)?;
+    let cached_path = find_cached_model(&models_dir, &model_id)?;
+    let sha256 = read_stored_checksum(&models_dir, &model_id);
+    tokio::task::spawn_blocking(move || {
+        let options = ModelLoadOptions {
+            warmup,
+            cache_optimized: true,
+            config: read_stored_config(&models_dir, &model_id)?,
+            model_id: Some(model_id),
+            sha256,
+        };
+        onnx_engine::preload_engine_from_path(&cached_path.to_string_lossy(), options)
+    })
+    .await
+    .map_err(|e| format!("Task failed: {}", e))?
+}
+
+/// Switch to the model loaded by `onnx_preload_model`
+/// Fails if no model is preloaded
+#[tauri::command]
+pub async fn onnx_activate_preloaded() -> Result<(), String> {
+    tokio::task::spawn_blocking(onnx_engine::activate_preloaded_engine)
+        .await
+        .map_err(|e| format!("Task failed: {}", e))?
+}
+
+/// Inspect a model file's inputs and outputs without loading it
+/// Lets the UI warn about models incompatible with the current board
+#[tauri::command]
+pub async fn onnx_inspect_model(path: String) -> Result<ModelMetadata, String> {
+    tokio::task::spawn_blocking(move || onnx_engine::inspect_model(Path::new(&path)))
+        .await
+        .map_err(|e| format!("Task failed: {}", e))?
+}
+
+/// Replay an SGF's main line up to `move_number` moves (all moves if None)
+/// Returns the board and analysis options, ready to pass to `onnx_analyze`
+#[tauri::command]
+pub async fn load_sgf_position(
+    sgf: String,
+    move_number: Option<usize>,
+) -> Result<SgfPosition, String> {
+    let (sign_map, options) = sgf::sgf_to_signmap(&sgf, move_number)?;
+    Ok(SgfPosition { sign_map, options })
+}
+
+/// Analyze a single position
+/// Fails with a tagged `OnnxError`, as do the other analyze commands
+#[tauri::command]
+pub async fn onnx_analyze(
+    sign_map: Vec<Vec<i8>>,
+    options: AnalysisOptions,
+) -> Result<AnalysisResult, OnnxError> {
+    tokio::task::spawn_blocking(move || onnx_engine::analyze_position(sign_map, options))
+        .await
+        .map_err(|e| OnnxError::Inference(format!("Task failed: {}", e)))?
+}
+
+/// Analyze a single position sent as a flat row-major buffer of `board_size`^2 cells
+/// Cheaper to serialize than the nested `sign_map` of `onnx_analyze`, e.g. when scrubbing a game
+#[tauri::command]
+pub async fn onnx_analyze_flat(
+    cells: Vec<i8>,
+    board_size: u32,
+    options: AnalysisOptions,
+) -> Result<AnalysisResult, OnnxError> {
+    tokio::task::spawn_blocking(move || {
+        let sign_map = onnx_engine::unflatten_sign_maps(&cells, board_size as usize, 1)?
+            .pop()
+            .unwrap_or_default();
+        onnx_engine::analyze_position(sign_map, options)
+    })
+    .await
+    .map_err(|e| OnnxError::Inference(format!("Task failed: {}", e)))?
+}
+
+/// Play `moves` on `sign_map` with the engine's rules (captures, suicide, simple ko)
+/// and analyze the resulting position, returned along with the analysis
+/// Fails on the first illegal move, naming its 1-based number
+#[tauri::command]
+pub async fn onnx_analyze_after_moves(
+    sign_map: Vec<Vec<i8>>,
+    moves: Vec<HistoryMove>,
+    options: AnalysisOptions,
+) -> Result<PositionAnalysis, OnnxError> {
+    tokio::task::spawn_blocking(move || onnx_engine::analyze_after_moves(sign_map, moves, options))
+        .await
+        .map_err(|e| OnnxError::Inference(format!("Task failed: {}", e)))?
+}
+
+/// Run the loaded model on raw tensors keyed by input name, returning its outputs by name
+/// Skips featurization and result processing, for running arbitrary ONNX models
+#[tauri::command]
+pub async fn onnx_run_raw(
+    inputs: HashMap<String, TensorData>,
+) -> Result<HashMap<String, TensorData>, OnnxError> {
+    tokio::task::spawn_blocking(move || onnx_engine::run_raw(inputs))
+        .await
+        .map_err(|e| OnnxError::Inference(format!("Task failed: {}", e)))?
+}
+
+/// Load a model into an ensemble slot (0-2), alongside the main engine
+#[tauri::command]
+pub async fn onnx_initialize_slot(slot: u8, path: String) -> Result<(), String> {
+    tokio::task::spawn_blocking(move || onnx_engine::initialize_slot(slot, &path))
+        .await
+        .map_err(|e| format!("Task failed: {}", e))?
+}
+
+/// Unload the model in an ensemble slot
+#[tauri::command]
+pub fn onnx_dispose_slot(slot: u8) -> Result<(), String> {
+    onnx_engine::dispose_slot(slot)
+}
+
+/// Analyze a position with every loaded model slot, averaging their outputs
+/// Returns the combined analysis along with each slot's own result
+#[tauri::command]
+pub async fn onnx_analyze_ensemble(
+    sign_map: Vec<Vec<i8>>,
+    options: AnalysisOptions,
+) -> Result<EnsembleAnalysis, OnnxError> {
+    tokio::task::spawn_blocking(move || onnx_engine::analyze_ensemble(sign_map, options))
+        .await
+        .map_err(|e| OnnxError::Inference(format!("Task failed: {}", e)))?
+}
+
+/// Analyze a single position on the invoking thread, without the Tokio blocking pool
+/// Synchronous commands run on the main thread, so the UI is blocked for the
+/// whole inference. Meant for latency benchmarks and embedders; the app itself
+/// should use `onnx_analyze`.
+#[tauri::command]
+pub fn onnx_analyze_sync(
+    sign_map: Vec<Vec<i8>>,
+    options: AnalysisOptions,
+) -> Result<AnalysisResult, OnnxError> {
+    onnx_engine::analyze_position(sign_map, options)
+}
+
+/// Analyze a position with a one-ply lookahead over the top `playouts` policy moves
+/// Returns both the raw policy ordering and the searched ordering, oriented by `options.orientTo`
+#[tauri::command]
+pub async fn onnx_analyze_searched(
+    sign_map: Vec<Vec<i8>>,
+    options: AnalysisOptions,
+    playouts: usize,
+) -> Result<SearchedAnalysis, String> {
+    tokio::task::spawn_blocking(move || onnx_engine::analyze_searched(sign_map, options, playouts))
+        .await
+        .map_err(|e| format!("Task failed: {}", e))?
+}
+
+/// Pick a single move to play in a position, for bots and play-against-AI
+/// `randomize_top_k` samples among the top K legal moves for variety
+/// With `options.resign_threshold` set, the result may be a resignation instead of a move
+/// The win rate and score lead are Black's, ignoring `options.orientTo`
+#[tauri::command]
+pub async fn onnx_genmove(
+    sign_map: Vec<Vec<i8>>,
+    options: AnalysisOptions,
+    randomize_top_k: Option<usize>,
+) -> Result<GenmoveResult, String> {
+    tokio::task::spawn_blocking(move || onnx_engine::genmove(sign_map, options, randomize_top_k))
+        .await
+        .map_err(|e| format!("Task failed: {}", e))?
+}
+
+/// Play a game of the engine against itself from `start_sign_map`, for sample games
+/// Stops after two consecutive passes or `max_moves` moves; `randomize_top_k`
+/// (default 3) samples among the top legal moves so games aren't identical
+#[tauri::command]
+pub async fn onnx_selfplay(
+    start_sign_map: Vec<Vec<i8>>,
+    options: AnalysisOptions,
+    max_moves: usize,
+    randomize_top_k: Option<usize>,
+) -> Result<SelfPlayGame, String> {
+    let randomize_top_k = randomize_top_k.or(Some(3));
+    tokio::task::spawn_blocking(move || {
+        onnx_engine::selfplay(start_sign_map, options, max_moves, randomize_top_k)
+    })
+    .await
+    .map_err(|e| format!("Task failed: {}", e))?
+}
+
+/// Analyze a whole game in one call, replaying `moves` from an empty board
+/// Emits `onnx://game-progress` events as sub-batches complete
+#[tauri::command]
+pub async fn onnx_analyze_game(
+    moves: Vec<HistoryMove>,
+    options: GameAnalysisOptions,
+    request_id: Option<String>,
+    app_handle: tauri::AppHandle,
+) -> Result<GameAnalysis, OnnxError> {
+    tokio::task::spawn_blocking(move || {
+        let cancel = onnx_engine::CancelToken::register(request_id)?;
+        onnx_engine::analyze_game(&moves, &options, &cancel, |analyzed, total| {
+            let _ = app_handle.emit("onnx://game-progress", GameProgress { analyzed, total });
+        })
+    })
+    .await
+    .map_err(|e| OnnxError::Inference(format!("Task failed: {}", e)))?
+}
+
+/// Estimate the final score of a finished game, including dead stones
+/// Uses area or territory counting according to `options.rules`; raise
+/// `options.ownership_threshold` to only count confidently owned points
+#[tauri::command]
+pub async fn onnx_estimate_final_score(
+    sign_map: Vec<Vec<i8>>,
+    options: AnalysisOptions,
+) -> Result<FinalScore, String> {
+    tokio::task::spawn_blocking(move || onnx_engine::estimate_final_score(sign_map, options))
+        .await
+        .map_err(|e| format!("Task failed: {}", e))?
+}
+
+/// Build the model inputs for a position without running inference, to debug featurization
+/// Returns per-channel sums of the spatial input unless `full_tensors` is set, which
+/// also returns every value (19x19 boards: about 8000 floats)
+#[tauri::command]
+pub async fn onnx_debug_featurize(
+    sign_map: Vec<Vec<i8>>,
+    options: AnalysisOptions,
+    full_tensors: Option<bool>,
+) -> Result<FeatureDump, OnnxError> {
+    let full = full_tensors.unwrap_or(false);
+    tokio::task::spawn_blocking(move || onnx_engine::debug_featurize(sign_map, options, full))
+        .await
+        .map_err(|e| OnnxError::Inference(format!("Task failed: {}", e)))?
+}
+
+/// Estimate the komi that makes the position even, sampling win rates across `range`
+/// Returns the interpolated fair komi and the win rate curve
+#[tauri::command]
+pub async fn onnx_estimate_fair_komi(
+    sign_map: Vec<Vec<i8>>,
+    options: AnalysisOptions,
+    range: (f32, f32),
+) -> Result<FairKomi, String> {
+    tokio::task::spawn_blocking(move || onnx_engine::estimate_fair_komi(sign_map, options, range))
+        .await
+        .map_err(|e| format!("Task failed: {}", e))?
+}
+
+/// Per-point ownership change caused by playing `move` (x, y), from Black's perspective
+/// whatever `options.orientTo` says; fails if the move is illegal
+#[tauri::command]
+pub async fn onnx_move_impact(
+    sign_map: Vec<Vec<i8>>,
+    r#move: (i32, i32),
+    options: AnalysisOptions,
+) -> Result<Vec<Vec<f32>>, String> {
+    tokio::task::spawn_blocking(move || onnx_engine::move_impact(sign_map, r#move, options))
+        .await
+        .map_err(|e| format!("Task failed: {}", e))?
+}
+
+/// Grade playing `move` (x, y, or -1, -1 to pass) as best, good, inaccuracy, mistake
+/// or blunder from the win rate it loses, with the engine's top move for comparison
+/// Fails if the move is illegal
+#[tauri::command]
+pub async fn onnx_grade_move(
+    sign_map: Vec<Vec<i8>>,
+    r#move: (i32, i32),
+    options: AnalysisOptions,
+) -> Result<GradedMove, String> {
+    tokio::task::spawn_blocking(move || onnx_engine::grade_move(sign_map, r#move, options))
+        .await
+        .map_err(|e| format!("Task failed: {}", e))?
+}
+
+/// Play out a greedy principal variation of up to `depth` plies
+/// Returns the moves and Black's win rate before each of them, ignoring `options.orientTo`
+#[tauri::command]
+pub async fn onnx_get_pv(
+    sign_map: Vec<Vec<i8>>,
+    options: AnalysisOptions,
+    depth: usize,
+) -> Result<PrincipalVariation, String> {
+    tokio::task::spawn_blocking(move || onnx_engine::get_pv(sign_map, options, depth))
         .await
         .map_err(|e| format!("Task failed: {}", e))?
 }
 
+/// Start pondering a position in the background
+/// Emits `onnx://ponder-update` events as the estimate is refined with more symmetries;
+/// starting a new ponder cancels the previous one
+#[tauri::command]
+pub async fn onnx_start_ponder(
+    sign_map: Vec<Vec<i8>>,
+    options: AnalysisOptions,
+    app_handle: tauri::AppHandle,
+) -> Result<(), String> {
+    onnx_engine::start_ponder(sign_map, options, move |symmetries, result| {
+        let _ = app_handle.emit("onnx://ponder-update", PonderUpdate { symmetries, result });
+    })
+}
+
+/// Stop the running ponder, if any
+#[tauri::command]
+pub async fn onnx_stop_ponder() -> Result<(), String> {
+    onnx_engine::stop_ponder();
+    Ok(())
+}
+
 /// Analyze multiple positions in a batch
+/// Pass a `request_id` to make the request cancellable with `onnx_cancel`
 #[tauri::command]
-pub async fn onnx_analyze_batch(inputs: Vec<BatchInput>) -> Result<Vec<AnalysisResult>, String> {
+pub async fn onnx_analyze_batch(
+    inputs: Vec<BatchInput>,
+    request_id: Option<String>,
+) -> Result<Vec<AnalysisResult>, OnnxError> {
     tokio::task::spawn_blocking(move || {
+        let cancel = onnx_engine::CancelToken::register(request_id)?;
         let batch: Vec<(Vec<Vec<i8>>, AnalysisOptions)> = inputs
             .into_iter()
             .map(|i| (i.sign_map, i.options))
             .collect();
-        onnx_engine::analyze_batch(batch)
+        onnx_engine::analyze_batch(batch, &cancel)
     })
     .await
-    .map_err(|e| format!("Task failed: {}", e))?
+    .map_err(|e| OnnxError::Inference(format!("Task failed: {}", e)))?
+}
+
+/// Analyze `count` positions sent as one flat row-major buffer of `count * board_size^2` cells
+/// `options` holds one entry per position, or a single entry shared by all of them
+/// Pass a `request_id` to make the request cancellable with `onnx_cancel`
+#[tauri::command]
+pub async fn onnx_analyze_batch_flat(
+    cells: Vec<i8>,
+    board_size: u32,
+    count: usize,
+    options: Vec<AnalysisOptions>,
+    request_id: Option<String>,
+) -> Result<Vec<AnalysisResult>, OnnxError> {
+    tokio::task::spawn_blocking(move || {
+        let cancel = onnx_engine::CancelToken::register(request_id)?;
+        let sign_maps = onnx_engine::unflatten_sign_maps(&cells, board_size as usize, count)?;
+        let options = match options.len() {
+            1 => vec![options[0].clone(); count],
+            n if n == count => options,
+            n => {
+                return Err(OnnxError::InvalidBoard(format!(
+                    "Expected 1 or {} options, got {}",
+                    count, n
+                )))
+            }
+        };
+        onnx_engine::analyze_batch(sign_maps.into_iter().zip(options).collect(), &cancel)
+    })
+    .await
+    .map_err(|e| OnnxError::Inference(format!("Task failed: {}", e)))?
+}
+
+/// Analyze many positions in auto-tuned sub-batches, streaming results as they finish
+/// Emits an `onnx://batch-result` event per position, then `onnx://batch-done`,
+/// also when the batch fails or is cancelled with `onnx_cancel`
+/// Fails without emitting anything if another request is running with the same `request_id`
+/// Returns the number of positions analyzed
+#[tauri::command]
+pub async fn onnx_analyze_batch_streaming(
+    inputs: Vec<BatchInput>,
+    request_id: String,
+    app_handle: tauri::AppHandle,
+) -> Result<usize, OnnxError> {
+    tokio::task::spawn_blocking(move || {
+        let cancel = onnx_engine::CancelToken::register(Some(request_id.clone()))?;
+        let batch: Vec<(Vec<Vec<i8>>, AnalysisOptions)> = inputs
+            .into_iter()
+            .map(|i| (i.sign_map, i.options))
+            .collect();
+        let mut analyzed = 0;
+        let outcome = onnx_engine::analyze_batch_streaming(batch, &cancel, |index, result| {
+            analyzed += 1;
+            let payload = BatchResult {
+                request_id: &request_id,
+                index,
+                result,
+            };
+            let _ = app_handle.emit("onnx://batch-result", payload);
+        });
+        let done = BatchDone {
+            request_id,
+            analyzed,
+            error: outcome.as_ref().err().cloned(),
+        };
+        let _ = app_handle.emit("onnx://batch-done", done);
+        outcome
+    })
+    .await
+    .map_err(|e| OnnxError::Inference(format!("Task failed: {}", e)))?
+}
+
+/// Cancel a running batch or game analysis started with this `request_id`
+/// The request stops before its next sub-batch and fails with a `cancelled` error
+/// Returns false if no running request has this ID
+#[tauri::command]
+pub async fn onnx_cancel(request_id: String) -> Result<bool, String> {
+    Ok(onnx_engine::cancel_request(&request_id))
+}
+
+/// Analyze many positions, e.g. a whole game, in auto-tuned sub-batches
+/// Uses a default batch size until `onnx_autotune_batch` has run
+#[tauri::command]
+pub async fn onnx_analyze_batch_auto(
+    inputs: Vec<BatchInput>,
+    request_id: Option<String>,
+) -> Result<Vec<AnalysisResult>, OnnxError> {
+    tokio::task::spawn_blocking(move || {
+        let cancel = onnx_engine::CancelToken::register(request_id)?;
+        let batch: Vec<(Vec<Vec<i8>>, AnalysisOptions)> = inputs
+            .into_iter()
+            .map(|i| (i.sign_map, i.options))
+            .collect();
+        onnx_engine::analyze_batch_auto(batch, &cancel)
+    })
+    .await
+    .map_err(|e| OnnxError::Inference(format!("Task failed: {}", e)))?
+}
+
+/// Sweep batch sizes on dummy inputs and remember the one with the best throughput
+/// Returns the winner and the measured throughput for every batch size tried
+#[tauri::command]
+pub async fn onnx_autotune_batch() -> Result<AutotuneResult, String> {
+    tokio::task::spawn_blocking(onnx_engine::autotune_batch)
+        .await
+        .map_err(|e| format!("Task failed: {}", e))?
+}
+
+/// Benchmark the loaded model with `iterations` single inferences and batches of each
+/// of `batch_sizes` (default: 8)
+/// Reports median latencies and throughput so providers and batch sizes can be
+/// compared on the user's hardware
+#[tauri::command]
+pub async fn onnx_benchmark(
+    iterations: usize,
+    batch_sizes: Option<Vec<usize>>,
+) -> Result<BenchmarkResult, String> {
+    let batch_sizes =
+        batch_sizes.unwrap_or_else(|| onnx_engine::DEFAULT_BENCHMARK_BATCH_SIZES.to_vec());
+    tokio::task::spawn_blocking(move || onnx_engine::benchmark(iterations, &batch_sizes))
+        .await
+        .map_err(|e| format!("Task failed: {}", e))?
+}
+
+/// Get cumulative analysis counters: inferences, average latency, cache hits and failures
+#[tauri::command]
+pub fn onnx_get_stats() -> EngineStats {
+    onnx_engine::get_stats()
+}
+
+/// Reset the analysis counters to zero
+#[tauri::command]
+pub fn onnx_reset_stats() {
+    onnx_engine::reset_stats();
+}
+
+/// Number of analysis requests waiting for a free session
+/// Requests with a higher `priority` in their options are served first
+#[tauri::command]
+pub fn onnx_queue_depth() -> usize {
+    onnx_engine::queue_depth()
 }
 
 /// Dispose the ONNX engine
@@ -212,39 +1690,277 @@ pub async fn onnx_dispose() -> Result<(), String> {
         .map_err(|e| format!("Task failed: {}", e))?
 }
 
+/// Abandon every upload, deleting its temp file, and return how many there were
+fn cancel_all_uploads() -> usize {
+    with_uploads(|uploads| {
+        let count = uploads.len();
+        for (id, upload) in uploads.drain() {
+            log::info!("[OnnxUpload] Cancelling upload {}", id);
+            let _ = std::fs::remove_file(&upload.path);
+        }
+        count
+    })
+}
+
+/// Reset the ONNX engine after it got into a bad state
+/// Cancels uploads and running requests, stops pondering, and disposes the engine,
+/// model slots and analysis cache; safe to call any number of times
+/// Emits `onnx://disposed` when done
+#[tauri::command]
+pub async fn onnx_reset(app_handle: tauri::AppHandle) -> Result<(), String> {
+    let uploads = cancel_all_uploads();
+    if uploads > 0 {
+        log::info!("[OnnxUpload] Cancelled {} uploads", uploads);
+    }
+    tokio::task::spawn_blocking(onnx_engine::reset_engine)
+        .await
+        .map_err(|e| format!("Task failed: {}", e))??;
+    let _ = app_handle.emit("onnx://disposed", ());
+    Ok(())
+}
+
+/// Check whether `player` (1 = Black, -1 = White) may play at (x, y)
+/// Enforces occupancy, suicide and simple ko (`ko_point` is the forbidden recapture)
+#[tauri::command]
+pub async fn go_is_legal_move(
+    sign_map: Vec<Vec<i8>>,
+    x: i32,
+    y: i32,
+    player: i8,
+    ko_point: Option<(i32, i32)>,
+) -> Result<bool, String> {
+    onnx_engine::validate_sign_map(&sign_map)?;
+    if player != 1 && player != -1 {
+        return Err(format!("Invalid player {}, expected 1 or -1", player));
+    }
+    Ok(onnx_engine::is_legal_move(
+        &sign_map, x, y, player, ko_point,
+    ))
+}
+
+/// Classify every point as pass-alive for Black (1), White (-1) or neither (0) with
+/// Benson's algorithm: stones the opponent can never capture and the territory they
+/// enclose, for rules-correct scoring without the model
+#[tauri::command]
+pub async fn go_pass_alive_regions(sign_map: Vec<Vec<i8>>) -> Result<Vec<Vec<i8>>, String> {
+    crate::benson::pass_alive_regions(&sign_map)
+}
+
+/// Boards created with `go_board_new`, keyed by board ID
+static GO_BOARDS: Mutex<Option<HashMap<u64, GoBoard>>> = Mutex::new(None);
+
+/// Counter used to generate board IDs
+static NEXT_BOARD_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Run a closure with the board map, creating it on first use
+fn with_boards<T>(f: impl FnOnce(&mut HashMap<u64, GoBoard>) -> T) -> T {
+    let mut boards = GO_BOARDS.lock().unwrap();
+    f(boards.get_or_insert_with(HashMap::new))
+}
+
+/// Run a closure with one board, failing if the ID is unknown
+fn with_board<T>(board_id: u64, f: impl FnOnce(&mut GoBoard) -> T) -> Result<T, String> {
+    with_boards(|boards| {
+        boards
+            .get_mut(&board_id)
+            .map(f)
+            .ok_or_else(|| format!("Unknown board: {}", board_id))
+    })
+}
+
+/// Snapshot of a board for the frontend
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GoBoardState {
+    pub sign_map: Vec<Vec<i8>>,
+    /// Point the player to move may not retake
+    pub ko_point: Option<(usize, usize)>,
+    /// Stones captured by Black
+    pub black_prisoners: usize,
+    /// Stones captured by White
+    pub white_prisoners: usize,
+    /// Moves played, passes included
+    pub move_count: usize,
+}
+
+/// Create a board, empty or holding the stones of `sign_map`, and return its ID
+/// Free it with `go_board_free` when done
+#[tauri::command]
+pub fn go_board_new(size: usize, sign_map: Option<Vec<Vec<i8>>>) -> Result<u64, String> {
+    let board = match sign_map {
+        Some(sign_map) if sign_map.len() != size => {
+            return Err(format!(
+                "Board has {} rows, expected {}",
+                sign_map.len(),
+                size
+            ))
+        }
+        Some(sign_map) => GoBoard::from_signmap(&sign_map)?,
+        None => GoBoard::new(size)?,
+    };
+    let board_id = NEXT_BOARD_ID.fetch_add(1, Ordering::SeqCst);
+    with_boards(|boards| boards.insert(board_id, board));
+    Ok(board_id)
+}
+
+/// Play `color` (1 = Black, -1 = White) at (x, y) on a board, or pass with x = y = -1
+/// Fails without changing the board on occupied points, suicide and ko retakes
+#[tauri::command]
+pub fn go_board_play(board_id: u64, x: i32, y: i32, color: i8) -> Result<Captures, String> {
+    with_board(board_id, |board| board.play(x, y, color))?
+}
+
+/// Take back the last move on a board, returning false if there is none
+#[tauri::command]
+pub fn go_board_undo(board_id: u64) -> Result<bool, String> {
+    with_board(board_id, GoBoard::undo)
+}
+
+/// Get the stones, ko point and prisoners of a board
+#[tauri::command]
+pub fn go_board_state(board_id: u64) -> Result<GoBoardState, String> {
+    with_board(board_id, |board| GoBoardState {
+        sign_map: board.to_signmap(),
+        ko_point: board.ko_point(),
+        black_prisoners: board.prisoners(1),
+        white_prisoners: board.prisoners(-1),
+        move_count: board.move_count(),
+    })
+}
+
+/// Free a board, returning false if the ID is unknown
+#[tauri::command]
+pub fn go_board_free(board_id: u64) -> bool {
+    with_boards(|boards| boards.remove(&board_id).is_some())
+}
+
 /// Check if the ONNX engine is initialized
 #[tauri::command]
 pub fn onnx_is_initialized() -> bool {
     onnx_engine::is_engine_initialized()
 }
 
+/// Get the model the engine was loaded from
+/// Returns None if no engine is loaded or it was loaded from bytes
+#[tauri::command]
+pub fn onnx_get_loaded_model() -> Option<LoadedModel> {
+    onnx_engine::get_loaded_model()
+}
+
+/// Force the CPU execution provider regardless of the stored preference
+/// Takes effect on the next engine initialization; disabling it also resets the
+/// crash count that turns safe mode on automatically
+#[tauri::command]
+pub fn onnx_force_cpu_mode(enabled: bool) {
+    onnx_engine::set_cpu_safe_mode(enabled);
+}
+
+/// Check whether CPU safe mode is active, from the launch flag, the command
+/// above or a crash loop during GPU session creation
+#[tauri::command]
+pub fn onnx_is_cpu_safe_mode() -> bool {
+    onnx_engine::is_cpu_safe_mode()
+}
+
+/// Set the log level: off, error, warn, info, debug or trace
+/// Per-inference timings are logged at debug
+#[tauri::command]
+pub fn set_log_level(level: String) -> Result<(), String> {
+    crate::logging::set_level(&level)
+}
+
+/// Get the current log level
+#[tauri::command]
+pub fn get_log_level() -> String {
+    crate::logging::get_level()
+}
+
+/// Start or stop appending logs to `kaya.log` in the app data directory
+/// Returns the log file path while enabled, for users to attach to bug reports
+#[tauri::command]
+pub fn set_log_to_file(
+    enabled: bool,
+    app_handle: tauri::AppHandle,
+) -> Result<Option<String>, String> {
+    let dir = if enabled {
+        Some(
+            app_handle
+                .path()
+                .app_data_dir()
+                .map_err(|e| format!("Failed to get app data dir: {}", e))?,
+        )
+    } else {
+        None
+    };
+    let path = crate::logging::set_log_dir(dir.as_deref())?;
+    Ok(path.map(|p| p.to_string_lossy().to_string()))
+}
+
+/// Set how many analysis results are kept for repeated positions, 0 disables the cache
+#[tauri::command]
+pub fn onnx_set_cache_capacity(capacity: usize) {
+    onnx_engine::set_analysis_cache_capacity(capacity);
+}
+
+/// Drop every cached analysis result
+#[tauri::command]
+pub fn onnx_clear_analysis_cache() {
+    onnx_engine::clear_analysis_cache();
+}
+
 /// Get information about the current execution provider
 #[tauri::command]
 pub fn onnx_get_provider_info() -> Option<ExecutionProviderInfo> {
     onnx_engine::get_provider_info()
 }
 
+/// Get the device memory usage of the active execution provider, for a VRAM gauge
+/// Device figures are None on CPU and on providers that can't be queried
+#[tauri::command]
+pub async fn onnx_get_memory_info() -> Result<Option<MemoryInfo>, String> {
+    tokio::task::spawn_blocking(onnx_engine::get_memory_info)
+        .await
+        .map_err(|e| format!("Task failed: {}", e))
+}
+
+/// Get the ONNX Runtime version, its compiled-in execution providers and the
+/// build profile, for the diagnostics shown in bug reports
+#[tauri::command]
+pub async fn onnx_get_runtime_info() -> Result<RuntimeInfo, String> {
+    tokio::task::spawn_blocking(onnx_engine::get_runtime_info)
+        .await
+        .map_err(|e| format!("Task failed: {}", e))?
+}
+
 /// Get available execution providers for this platform
 #[tauri::command]
-pub fn onnx_get_available_providers() -> Vec<ExecutionProviderInfo> {
-    onnx_engine::get_available_providers()
+pub async fn onnx_get_available_providers() -> Result<Vec<ExecutionProviderInfo>, String> {
+    // Device names come from nvidia-smi, so keep the listing off the main thread
+    tokio::task::spawn_blocking(onnx_engine::get_available_providers)
+        .await
+        .map_err(|e| format!("Task failed: {}", e))
 }
 
-/// Set the preferred execution provider
+/// Set the preferred execution provider, persisted across launches
 /// Note: This takes effect on the next engine initialization
 #[tauri::command]
-pub fn onnx_set_provider_preference(preference: String) -> Result<(), String> {
+pub fn onnx_set_provider_preference(
+    preference: String,
+    app_handle: tauri::AppHandle,
+) -> Result<(), String> {
     let pref = match preference.as_str() {
         "auto" => ExecutionProviderPreference::Auto,
         "cuda" => ExecutionProviderPreference::Cuda,
+        "tensorrt" => ExecutionProviderPreference::TensorRt,
         "coreml" => ExecutionProviderPreference::CoreMl,
         "directml" => ExecutionProviderPreference::DirectMl,
+        "rocm" => ExecutionProviderPreference::Rocm,
         "nnapi" => ExecutionProviderPreference::Nnapi,
         "cpu" => ExecutionProviderPreference::Cpu,
         _ => return Err(format!("Unknown execution provider: {}", preference)),
     };
     onnx_engine::set_execution_provider_preference(pref);
-    Ok(())
+    EngineSettingsConfig::update(&app_handle, |config| config.preference = Some(pref))
 }
 
 /// Get the current execution provider preference
@@ -253,9 +1969,457 @@ pub fn onnx_get_provider_preference() -> String {
     match onnx_engine::get_execution_provider_preference() {
         ExecutionProviderPreference::Auto => "auto",
         ExecutionProviderPreference::Cuda => "cuda",
+        ExecutionProviderPreference::TensorRt => "tensorrt",
         ExecutionProviderPreference::CoreMl => "coreml",
         ExecutionProviderPreference::DirectMl => "directml",
+        ExecutionProviderPreference::Rocm => "rocm",
         ExecutionProviderPreference::Nnapi => "nnapi",
         ExecutionProviderPreference::Cpu => "cpu",
-    }.to_string()
+    }
+    .to_string()
+}
+
+/// Set the number of sessions in the analysis pool, persisted across launches
+/// Note: This takes effect on the next engine initialization
+#[tauri::command]
+pub fn onnx_set_session_count(count: usize, app_handle: tauri::AppHandle) -> Result<(), String> {
+    if count == 0 {
+        return Err("Session count must be at least 1".to_string());
+    }
+    onnx_engine::set_session_count(count);
+    EngineSettingsConfig::update(&app_handle, |config| config.session_count = Some(count))
+}
+
+/// Get the number of sessions used for the analysis pool
+#[tauri::command]
+pub fn onnx_get_session_count() -> usize {
+    onnx_engine::get_session_count()
+}
+
+/// Set the most positions run in one inference call, persisted across launches
+/// Larger batch requests are split into windows of this size, bounding peak memory
+#[tauri::command]
+pub fn onnx_set_max_batch(n: usize, app_handle: tauri::AppHandle) -> Result<(), String> {
+    if n == 0 {
+        return Err("Max batch size must be at least 1".to_string());
+    }
+    onnx_engine::set_max_batch_size(n);
+    EngineSettingsConfig::update(&app_handle, |config| config.max_batch_size = Some(n))
+}
+
+/// Get the most positions run in one inference call
+#[tauri::command]
+pub fn onnx_get_max_batch() -> usize {
+    onnx_engine::get_max_batch_size()
+}
+
+/// Set the number of threads used within a single operator (0 = let ORT decide)
+/// Note: This takes effect on the next engine initialization
+#[tauri::command]
+pub fn onnx_set_intra_op_threads(
+    threads: usize,
+    app_handle: tauri::AppHandle,
+) -> Result<(), String> {
+    onnx_engine::set_intra_op_threads(threads);
+    EngineSettingsConfig::update(&app_handle, |config| {
+        config.intra_op_threads = Some(threads)
+    })
+}
+
+/// Set the number of threads used across independent operators (0 = let ORT decide)
+/// Note: This takes effect on the next engine initialization
+#[tauri::command]
+pub fn onnx_set_inter_op_threads(
+    threads: usize,
+    app_handle: tauri::AppHandle,
+) -> Result<(), String> {
+    onnx_engine::set_inter_op_threads(threads);
+    EngineSettingsConfig::update(&app_handle, |config| {
+        config.inter_op_threads = Some(threads)
+    })
+}
+
+/// Select the GPU used by the CUDA, TensorRT, ROCm and DirectML providers, persisted
+/// across launches along with the provider preference
+/// The index is checked against the NVIDIA GPU count when `nvidia-smi` is available
+/// Note: This takes effect on the next engine initialization
+#[tauri::command]
+pub fn onnx_set_device_index(index: u32, app_handle: tauri::AppHandle) -> Result<(), String> {
+    let preference = onnx_engine::get_execution_provider_preference();
+    let is_nvidia = matches!(
+        preference,
+        ExecutionProviderPreference::Cuda | ExecutionProviderPreference::TensorRt
+    );
+    if is_nvidia {
+        if let Some(names) = onnx_engine::nvidia_device_names() {
+            if index as usize >= names.len() {
+                return Err(format!(
+                    "GPU {} not found, {} NVIDIA GPU(s) available",
+                    index,
+                    names.len()
+                ));
+            }
+        }
+    }
+    onnx_engine::set_device_index(index);
+    EngineSettingsConfig::update(&app_handle, |config| config.device_index = Some(index))
+}
+
+/// Get the GPU index used by providers that support device selection
+#[tauri::command]
+pub fn onnx_get_device_index() -> u32 {
+    onnx_engine::get_provider_settings().device_index
+}
+
+/// Set the graph optimization level: "disabled", "basic", "extended" or "all" (default),
+/// persisted across launches
+/// Dropping to "basic" works around crashes with some DirectML drivers
+/// Note: This takes effect on the next engine initialization
+#[tauri::command]
+pub fn onnx_set_optimization_level(
+    level: String,
+    app_handle: tauri::AppHandle,
+) -> Result<(), String> {
+    let level = match level.as_str() {
+        "disabled" => OptimizationLevel::Disabled,
+        "basic" => OptimizationLevel::Basic,
+        "extended" => OptimizationLevel::Extended,
+        "all" => OptimizationLevel::All,
+        _ => return Err(format!("Unknown optimization level: {}", level)),
+    };
+    onnx_engine::set_optimization_level(level);
+    EngineSettingsConfig::update(&app_handle, |config| {
+        config.optimization_level = Some(level)
+    })
+}
+
+/// Get the current graph optimization level
+#[tauri::command]
+pub fn onnx_get_optimization_level() -> String {
+    onnx_engine::get_provider_settings()
+        .optimization_level
+        .name()
+        .to_string()
+}
+
+/// Set the hardware the CoreML provider may use: "all" (default), "cpuAndGpu",
+/// "cpuAndNeuralEngine" or "cpuOnly"
+/// The Neural Engine is usually fastest, but operators it doesn't support fall
+/// back to the CPU; only meaningful when the CoreML provider is selected
+/// Note: This takes effect on the next engine initialization
+#[tauri::command]
+pub fn onnx_set_coreml_compute_units(
+    units: String,
+    app_handle: tauri::AppHandle,
+) -> Result<(), String> {
+    let units = match units.as_str() {
+        "all" => CoreMlComputeUnits::All,
+        "cpuAndGpu" => CoreMlComputeUnits::CpuAndGpu,
+        "cpuAndNeuralEngine" => CoreMlComputeUnits::CpuAndNeuralEngine,
+        "cpuOnly" => CoreMlComputeUnits::CpuOnly,
+        _ => return Err(format!("Unknown CoreML compute units: {}", units)),
+    };
+    onnx_engine::set_coreml_compute_units(units);
+    EngineSettingsConfig::update(&app_handle, |config| {
+        config.coreml_compute_units = Some(units)
+    })
+}
+
+/// Get the hardware the CoreML provider may use
+#[tauri::command]
+pub fn onnx_get_coreml_compute_units() -> String {
+    onnx_engine::get_provider_settings()
+        .coreml_compute_units
+        .name()
+        .to_string()
+}
+
+/// Get the outcome of the last session creation attempt with each provider tried,
+/// so a silent fallback to CPU can be explained (e.g. a CUDA driver mismatch)
+#[tauri::command]
+pub fn onnx_get_provider_diagnostics() -> Vec<ProviderDiagnostic> {
+    onnx_engine::get_provider_diagnostics()
+}
+
+/// Get the current provider settings, including thread counts
+#[tauri::command]
+pub fn onnx_get_provider_settings() -> ProviderSettings {
+    onnx_engine::get_provider_settings()
+}
+
+/// Set the directory where TensorRT caches its built engines
+/// Note: This takes effect on the next engine initialization
+#[tauri::command]
+pub fn onnx_set_tensorrt_cache_dir(path: String) -> Result<(), String> {
+    std::fs::create_dir_all(&path)
+        .map_err(|e| format!("Failed to create TensorRT cache dir: {}", e))?;
+    onnx_engine::set_tensorrt_cache_dir(Some(path));
+    Ok(())
+}
+
+/// Delete all cached optimized graphs so they are rebuilt on next load
+/// Returns the number of files removed
+#[tauri::command]
+pub async fn onnx_clear_optimized_cache(app_handle: tauri::AppHandle) -> Result<usize, String> {
+    let models_dir = get_models_dir(&app_handle)?;
+    let entries = match std::fs::read_dir(&models_dir) {
+        Ok(entries) => entries,
+        // No models cached yet, nothing to clear
+        Err(_) => return Ok(0),
+    };
+
+    let mut removed = 0;
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.ends_with(".opt.onnx") || name.ends_with(".opt.json") {
+            std::fs::remove_file(entry.path())
+                .map_err(|e| format!("Failed to delete {}: {}", name, e))?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Empty directory under the system temp dir, unique to this test and process
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("kaya-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Length-delimited protobuf field (lengths below 128 only)
+    fn len_field(number: u8, payload: &[u8]) -> Vec<u8> {
+        let mut field = vec![number << 3 | 2, payload.len() as u8];
+        field.extend_from_slice(payload);
+        field
+    }
+
+    /// ONNX model whose only initializer keeps its weights in `location`
+    fn model_with_external_data(location: &str) -> Vec<u8> {
+        let entry = [len_field(1, b"location"), len_field(2, location.as_bytes())].concat();
+        let initializer = len_field(13, &entry);
+        let graph = len_field(5, &initializer);
+        len_field(7, &graph)
+    }
+
+    #[test]
+    fn shared_external_data_outlives_the_first_model_removed() {
+        let dir = scratch_dir("shared-external-data");
+        for id in ["a", "b"] {
+            std::fs::write(
+                get_cached_model_path(&dir, id),
+                model_with_external_data("w.bin"),
+            )
+            .unwrap();
+        }
+        std::fs::write(dir.join("w.bin"), [1, 2, 3]).unwrap();
+
+        remove_cached_model(&dir, "a").unwrap();
+        assert!(dir.join("w.bin").exists());
+        assert!(!get_cached_model_path(&dir, "a").exists());
+        remove_cached_model(&dir, "b").unwrap();
+        assert!(!dir.join("w.bin").exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn stored_model_config_round_trips_and_goes_with_the_model() {
+        let dir = scratch_dir("model-config");
+        assert!(read_stored_config(&dir, "net").unwrap().is_none());
+
+        let config = ModelConfig {
+            komi_scale: Some(15.0),
+            rules: Some(onnx_engine::Rules::Japanese),
+            ..ModelConfig::default()
+        };
+        store_model_config(&dir, "net", &config).unwrap();
+        let stored = read_stored_config(&dir, "net").unwrap().unwrap();
+        assert_eq!(stored.komi_scale, Some(15.0));
+        assert_eq!(stored.rules, Some(onnx_engine::Rules::Japanese));
+
+        std::fs::write(
+            get_cached_model_path(&dir, "net"),
+            model_with_external_data("w.bin"),
+        )
+        .unwrap();
+        remove_cached_model(&dir, "net").unwrap();
+        assert!(!get_config_path(&dir, "net").exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn cached_external_data_is_reused_only_when_identical() {
+        let dir = scratch_dir("external-data-conflict");
+        let (source_dir, models_dir) = (dir.join("upload"), dir.join("models"));
+        std::fs::create_dir_all(&source_dir).unwrap();
+        std::fs::create_dir_all(&models_dir).unwrap();
+        let model = source_dir.join("new.onnx");
+        std::fs::write(&model, model_with_external_data("w.bin")).unwrap();
+        std::fs::write(source_dir.join("w.bin"), [1, 2, 3]).unwrap();
+
+        let copies = plan_external_data_copies(&model, Some(&source_dir), &models_dir).unwrap();
+        assert_eq!(
+            copies,
+            vec![(source_dir.join("w.bin"), models_dir.join("w.bin"))]
+        );
+
+        std::fs::write(models_dir.join("w.bin"), [1, 2, 3]).unwrap();
+        let copies = plan_external_data_copies(&model, Some(&source_dir), &models_dir).unwrap();
+        assert!(copies.is_empty());
+
+        // Same length, different weights: another model's file must not be replaced
+        std::fs::write(models_dir.join("w.bin"), [3, 2, 1]).unwrap();
+        let error = plan_external_data_copies(&model, Some(&source_dir), &models_dir).unwrap_err();
+        assert!(error.contains("conflicts"));
+
+        assert!(plan_external_data_copies(&model, None, &models_dir).is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn cached_models_are_found_by_id_inside_the_models_dir_only() {
+        let root = scratch_dir("find-cached-model");
+        let models_dir = root.join("models");
+        std::fs::create_dir_all(&models_dir).unwrap();
+        std::fs::write(get_cached_model_path(&models_dir, "b18-s9"), b"model").unwrap();
+        std::fs::write(root.join("outside.onnx"), b"model").unwrap();
+
+        assert_eq!(
+            find_cached_model(&models_dir, "b18-s9").unwrap(),
+            models_dir.join("b18-s9.onnx")
+        );
+        assert!(find_cached_model(&models_dir, "b28")
+            .unwrap_err()
+            .contains("not cached"));
+        for id in [
+            "../outside",
+            "..",
+            "",
+            "sub/b18-s9",
+            "sub\\b18-s9",
+            "C:b18-s9",
+            "/tmp/b18-s9",
+        ] {
+            let error = find_cached_model(&models_dir, id).unwrap_err();
+            assert!(error.starts_with("Invalid model ID"), "{}: {}", id, error);
+        }
+        assert!(validate_model_id("kata1-b18c384nbt.v2").is_ok());
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn cancelling_an_upload_deletes_its_temp_file() {
+        let dir = scratch_dir("cancel-upload");
+        let start = |id: &str| {
+            let path = dir.join(format!("{}.onnx.tmp", id));
+            File::create(&path)
+                .unwrap()
+                .write_all(b"first chunk")
+                .unwrap();
+            with_uploads(|uploads| {
+                uploads.insert(
+                    id.to_string(),
+                    UploadState {
+                        path: path.clone(),
+                        bytes_written: 11,
+                        total_bytes: Some(100),
+                        last_progress_emit: None,
+                    },
+                )
+            });
+            path
+        };
+        let cancel = |id: Option<&str>| {
+            futures::executor::block_on(onnx_cancel_upload(id.map(str::to_string)))
+        };
+
+        let path = start("test-cancel-upload");
+        assert_eq!(cancel(Some("test-cancel-upload")), Ok(true));
+        assert!(!path.exists());
+        assert!(with_uploads(
+            |uploads| !uploads.contains_key("test-cancel-upload")
+        ));
+        assert_eq!(cancel(Some("test-cancel-upload")), Ok(false));
+
+        // The file may already be gone, e.g. cleaned up by the OS
+        let path = start("test-cancel-missing-file");
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(cancel(Some("test-cancel-missing-file")), Ok(true));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn eviction_removes_the_oldest_models_but_never_the_new_one() {
+        let dir = scratch_dir("evict-cached-models");
+        let epoch = SystemTime::now() - Duration::from_secs(3600);
+        // "a" is the model just written, keeping the old mtime of the file it was copied from
+        for (age, id) in ["a", "b", "c", "d"].into_iter().enumerate() {
+            let file = File::create(get_cached_model_path(&dir, id)).unwrap();
+            file.set_len(100).unwrap();
+            file.set_modified(epoch + Duration::from_secs(age as u64 * 60))
+                .unwrap();
+        }
+        let cached = |id| get_cached_model_path(&dir, id).exists();
+
+        evict_cached_models(&dir, 1000, &get_cached_model_path(&dir, "a")).unwrap();
+        assert!(["a", "b", "c", "d"].into_iter().all(cached));
+
+        evict_cached_models(&dir, 250, &get_cached_model_path(&dir, "a")).unwrap();
+        assert!(cached("a") && cached("d"));
+        assert!(!cached("b") && !cached("c"));
+        assert_eq!(get_models_dir_size(&dir), 200);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn engine_settings_survive_a_save_and_reload() {
+        let path = scratch_dir("engine-settings")
+            .join("config")
+            .join("engine-settings.json");
+        // Nothing saved yet: every setting keeps its default
+        assert!(EngineSettingsConfig::load_from(&path).preference.is_none());
+
+        let config = EngineSettingsConfig {
+            preference: Some(ExecutionProviderPreference::DirectMl),
+            device_index: Some(1),
+            intra_op_threads: Some(6),
+            optimization_level: Some(OptimizationLevel::Basic),
+            coreml_compute_units: Some(CoreMlComputeUnits::CpuAndGpu),
+            autosave_position: Some(true),
+            ..Default::default()
+        };
+        config.save_to(&path).unwrap();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(
+            contents.contains("\"preference\": \"directml\""),
+            "{}",
+            contents
+        );
+
+        let loaded = EngineSettingsConfig::load_from(&path);
+        assert_eq!(
+            loaded.preference,
+            Some(ExecutionProviderPreference::DirectMl)
+        );
+        assert_eq!(loaded.device_index, Some(1));
+        assert_eq!(loaded.intra_op_threads, Some(6));
+        assert_eq!(loaded.inter_op_threads, None);
+        assert_eq!(loaded.optimization_level, Some(OptimizationLevel::Basic));
+        assert_eq!(
+            loaded.coreml_compute_units,
+            Some(CoreMlComputeUnits::CpuAndGpu)
+        );
+        assert_eq!(loaded.autosave_position, Some(true));
+
+        // A corrupt file falls back to the defaults instead of failing startup
+        std::fs::write(&path, "{ not json").unwrap();
+        assert!(EngineSettingsConfig::load_from(&path)
+            .device_index
+            .is_none());
+    }
 }
@@ -0,0 +1,389 @@
+//! GTP (Go Text Protocol) front end for the ONNX engine.
+//!
+//! Launched with `kaya --gtp <model.onnx>`, this lets external GUIs such as
+//! Sabaki or Lizzie drive the native engine over stdin/stdout. Moves come
+//! straight from the policy head, so there is no search and no time control.
+
+use crate::board::{format_gtp_vertex, parse_gtp_vertex, GoBoard, GTP_COLUMNS};
+use crate::onnx_engine::{self, AnalysisOptions, HistoryMove, ModelLoadOptions, SearchedMove};
+use std::io::{BufRead, Write};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::Duration;
+
+/// Candidates `kata-analyze` evaluates with their own value, see [`onnx_engine::analyze_searched`]
+const KATA_ANALYZE_CANDIDATES: usize = 10;
+
+const COMMANDS: &[&str] = &[
+    "protocol_version",
+    "name",
+    "version",
+    "known_command",
+    "list_commands",
+    "quit",
+    "boardsize",
+    "clear_board",
+    "komi",
+    "play",
+    "genmove",
+    "kata-analyze",
+];
+
+/// A running `kata-analyze` stream
+struct AnalysisStream {
+    /// Time between info lines, None to report once
+    interval: Option<Duration>,
+    /// Info line repeated at every interval until the next command
+    info: String,
+}
+
+/// Board state tracked between GTP commands
+struct GtpState {
+    board: GoBoard,
+    komi: f32,
+    history: Vec<HistoryMove>,
+    next_color: i8,
+}
+
+impl GtpState {
+    fn new(size: usize) -> Result<Self, String> {
+        Ok(Self {
+            board: GoBoard::new(size)?,
+            komi: AnalysisOptions::default().komi,
+            history: Vec::new(),
+            next_color: 1,
+        })
+    }
+
+    fn size(&self) -> usize {
+        self.board.size()
+    }
+
+    fn options(&self, color: i8) -> AnalysisOptions {
+        AnalysisOptions {
+            komi: self.komi,
+            next_to_play: Some(if color == 1 { "B" } else { "W" }.to_string()),
+            history: self.history.clone(),
+            ko_point: self.board.ko_point().map(|(x, y)| (x as i32, y as i32)),
+            ..AnalysisOptions::default()
+        }
+    }
+
+    /// Candidate moves for `color`, each with the value of the position it leads to
+    fn analyze(&self, color: i8) -> Result<Vec<SearchedMove>, String> {
+        let searched = onnx_engine::analyze_searched(
+            self.board.to_signmap(),
+            self.options(color),
+            KATA_ANALYZE_CANDIDATES,
+        )?;
+        Ok(searched.searched_moves)
+    }
+
+    /// Play a move, `None` being a pass
+    fn play(&mut self, color: i8, point: Option<(usize, usize)>) -> Result<(), String> {
+        let (x, y) = point.map_or((-1, -1), |(x, y)| (x as i32, y as i32));
+        self.board
+            .play(x, y, color)
+            .map_err(|_| "illegal move".to_string())?;
+        self.history.push(HistoryMove { color, x, y });
+        self.next_color = -color;
+        Ok(())
+    }
+
+    fn parse_vertex(&self, vertex: &str) -> Result<Option<(usize, usize)>, String> {
+        parse_gtp_vertex(vertex, self.size())
+    }
+
+    fn format_vertex(&self, point: Option<(usize, usize)>) -> String {
+        match point {
+            Some((x, y)) => format_gtp_vertex(x, y, self.size()),
+            None => "pass".to_string(),
+        }
+    }
+}
+
+fn parse_color(color: &str) -> Result<i8, String> {
+    match color.to_ascii_lowercase().as_str() {
+        "b" | "black" => Ok(1),
+        "w" | "white" => Ok(-1),
+        _ => Err("invalid color".to_string()),
+    }
+}
+
+/// Arguments of `kata-analyze`: `[player] [interval] [key value]...`, the interval in centiseconds
+/// Returns the color to analyze for and the reporting interval, None to report once
+/// The interval may also be given as the `interval` key; other keys are ignored
+fn parse_kata_analyze_args(args: &[&str], default_color: i8) -> (i8, Option<Duration>) {
+    let mut args = args.iter().peekable();
+    let color = match args.peek().and_then(|arg| parse_color(arg).ok()) {
+        Some(color) => {
+            args.next();
+            color
+        }
+        None => default_color,
+    };
+    let mut interval = None;
+    let mut positional = true;
+    while let Some(arg) = args.next() {
+        let centiseconds = match arg.parse::<u64>() {
+            Ok(value) if positional => Some(value),
+            _ if *arg == "interval" => args.next().and_then(|value| value.parse().ok()),
+            _ => {
+                args.next();
+                None
+            }
+        };
+        positional = false;
+        if let Some(centiseconds) = centiseconds {
+            interval = (centiseconds > 0).then(|| Duration::from_millis(centiseconds * 10));
+        }
+    }
+    (color, interval)
+}
+
+/// Format candidate moves as a single `kata-analyze` info line
+/// Each candidate reports the value of the position after it, from `color`'s perspective;
+/// the moves are one network evaluation each, so every one counts a single visit
+fn format_kata_analysis(state: &GtpState, moves: &[SearchedMove], color: i8) -> String {
+    moves
+        .iter()
+        .filter_map(|searched| {
+            let point = state.parse_vertex(&searched.move_str).ok()?;
+            Some((state.format_vertex(point), searched))
+        })
+        .enumerate()
+        .map(|(order, (vertex, searched))| {
+            let winrate = if color == 1 { searched.win_rate } else { 1.0 - searched.win_rate };
+            let score_lead = searched.score_lead * color as f32;
+            format!(
+                "info move {} visits 1 winrate {:.6} scoreMean {:.3} scoreLead {:.3} prior {:.6} order {} pv {}",
+                vertex, winrate, score_lead, score_lead, searched.prior, order, vertex
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Handle one command, returning its response text
+/// `stream` is set when the command leaves an analysis stream open
+fn handle_command(
+    state: &mut GtpState,
+    command: &str,
+    args: &[&str],
+    stream: &mut Option<AnalysisStream>,
+) -> Result<String, String> {
+    match command {
+        "protocol_version" => Ok("2".to_string()),
+        "name" => Ok("Kaya".to_string()),
+        "version" => Ok(env!("CARGO_PKG_VERSION").to_string()),
+        "known_command" => Ok(args
+            .first()
+            .is_some_and(|c| COMMANDS.contains(c))
+            .to_string()),
+        "list_commands" => Ok(COMMANDS.join("\n")),
+        "quit" => Ok(String::new()),
+        "boardsize" => {
+            let size: usize = args
+                .first()
+                .and_then(|s| s.parse().ok())
+                .ok_or("boardsize not an integer")?;
+            if !(2..=GTP_COLUMNS.len()).contains(&size) {
+                return Err("unacceptable size".to_string());
+            }
+            *state = GtpState {
+                komi: state.komi,
+                ..GtpState::new(size)?
+            };
+            Ok(String::new())
+        }
+        "clear_board" => {
+            *state = GtpState {
+                komi: state.komi,
+                ..GtpState::new(state.size())?
+            };
+            Ok(String::new())
+        }
+        "komi" => {
+            state.komi = args
+                .first()
+                .and_then(|s| s.parse().ok())
+                .ok_or("komi not a float")?;
+            Ok(String::new())
+        }
+        "play" => {
+            let [color, vertex, ..] = args else {
+                return Err("invalid color or coordinate".to_string());
+            };
+            let color = parse_color(color)?;
+            let point = state.parse_vertex(vertex)?;
+            state.play(color, point)?;
+            Ok(String::new())
+        }
+        "genmove" => {
+            let color = parse_color(args.first().ok_or("invalid color")?)?;
+            // Same move choice as the app's bot, which only passes once nothing is left to play
+            // or the game is decided
+            let chosen =
+                onnx_engine::genmove(state.board.to_signmap(), state.options(color), None)?;
+            let point = (!chosen.is_pass).then_some((chosen.x as usize, chosen.y as usize));
+            state.play(color, point)?;
+            Ok(state.format_vertex(point))
+        }
+        "kata-analyze" => {
+            let (color, interval) = parse_kata_analyze_args(args, state.next_color);
+            let moves = state.analyze(color)?;
+            let info = format_kata_analysis(state, &moves, color);
+            *stream = Some(AnalysisStream { interval, info });
+            Ok(String::new())
+        }
+        _ => Err("unknown command".to_string()),
+    }
+}
+
+/// Load the model and serve GTP on stdin/stdout until `quit` or end of input
+pub fn run(model_path: &str) -> Result<(), String> {
+    let options = ModelLoadOptions {
+        warmup: Some(19),
+        ..ModelLoadOptions::default()
+    };
+    onnx_engine::initialize_engine_from_path(model_path, options)?;
+    log::info!("[GTP] Engine ready");
+
+    let mut state = GtpState::new(19)?;
+    let mut stdout = std::io::stdout();
+    let mut stream: Option<AnalysisStream> = None;
+
+    // Read stdin on its own thread so an analysis stream can report while waiting for input
+    let (sender, lines) = mpsc::channel();
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    loop {
+        let received = match stream.as_ref().and_then(|s| s.interval) {
+            Some(interval) => lines.recv_timeout(interval),
+            None => lines.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        let line = match received {
+            Ok(line) => line.map_err(|e| format!("Failed to read stdin: {}", e))?,
+            Err(RecvTimeoutError::Timeout) => {
+                if let Some(stream) = &stream {
+                    writeln!(stdout, "{}", stream.info).map_err(|e| e.to_string())?;
+                    stdout.flush().map_err(|e| e.to_string())?;
+                }
+                continue;
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        let line = line.split('#').next().unwrap_or("").trim().to_string();
+
+        // Any input ends a running analysis stream
+        if stream.take().is_some() {
+            writeln!(stdout).map_err(|e| e.to_string())?;
+        }
+        if line.is_empty() {
+            stdout.flush().map_err(|e| e.to_string())?;
+            continue;
+        }
+
+        let mut words: Vec<&str> = line.split_whitespace().collect();
+        let id = words
+            .first()
+            .filter(|w| w.parse::<u64>().is_ok())
+            .map(|w| w.to_string())
+            .unwrap_or_default();
+        if !id.is_empty() {
+            words.remove(0);
+        }
+        let Some((&command, args)) = words.split_first() else {
+            continue;
+        };
+
+        let result = handle_command(&mut state, command, args, &mut stream);
+        let response = match (result, &stream) {
+            (Ok(_), Some(stream)) => format!("={}\n{}\n", id, stream.info),
+            (Ok(text), None) if text.is_empty() => format!("={}\n\n", id),
+            (Ok(text), None) => format!("={} {}\n\n", id, text),
+            (Err(e), _) => format!("?{} {}\n\n", id, e),
+        };
+        write!(stdout, "{}", response).map_err(|e| e.to_string())?;
+        stdout.flush().map_err(|e| e.to_string())?;
+
+        if command == "quit" {
+            break;
+        }
+    }
+
+    onnx_engine::dispose_engine()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kata_analyze_reads_player_and_interval() {
+        assert_eq!(parse_kata_analyze_args(&[], 1), (1, None));
+        assert_eq!(
+            parse_kata_analyze_args(&["W", "50"], 1),
+            (-1, Some(Duration::from_millis(500)))
+        );
+        assert_eq!(
+            parse_kata_analyze_args(&["interval", "20"], -1),
+            (-1, Some(Duration::from_millis(200)))
+        );
+        // Other key-value options are skipped along with their values
+        assert_eq!(
+            parse_kata_analyze_args(&["b", "maxmoves", "5", "interval", "10"], -1),
+            (1, Some(Duration::from_millis(100)))
+        );
+        assert_eq!(parse_kata_analyze_args(&["B", "0"], -1), (1, None));
+    }
+
+    #[test]
+    fn kata_analysis_reports_each_candidate_from_the_players_side() {
+        let state = GtpState::new(9).unwrap();
+        let searched = |move_str: &str, win_rate, score_lead| SearchedMove {
+            move_str: move_str.to_string(),
+            prior: 0.25,
+            win_rate,
+            score_lead,
+        };
+        let moves = [searched("E5", 0.3, -2.0), searched("C3", 0.4, -1.0)];
+
+        let info = format_kata_analysis(&state, &moves, -1);
+        let lines: Vec<&str> = info.split(" info ").collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("info move E5 visits 1 winrate 0.700000 scoreMean 2.000"));
+        assert!(lines[1].starts_with("move C3 visits 1 winrate 0.600000 scoreMean 1.000"));
+        assert!(lines[1].contains("order 1 pv C3"));
+    }
+
+    #[test]
+    fn play_tracks_captures_and_rejects_the_ko_retake() {
+        let mut state = GtpState::new(5).unwrap();
+        for (color, vertex) in [
+            ("b", "B5"),
+            ("w", "C5"),
+            ("b", "A4"),
+            ("w", "B4"),
+            ("b", "B3"),
+            ("w", "D4"),
+            ("b", "pass"),
+            ("w", "C3"),
+        ] {
+            let point = state.parse_vertex(vertex).unwrap();
+            state.play(parse_color(color).unwrap(), point).unwrap();
+        }
+        // Black takes the ko, White may not take back at once
+        state.play(1, state.parse_vertex("C4").unwrap()).unwrap();
+        assert_eq!(state.board.to_signmap()[1][1], 0);
+        let retake = state.parse_vertex("B4").unwrap();
+        assert_eq!(state.play(-1, retake).unwrap_err(), "illegal move");
+        assert_eq!(state.options(-1).ko_point, Some((1, 1)));
+        assert_eq!(state.next_color, -1);
+    }
+}
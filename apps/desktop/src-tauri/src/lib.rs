@@ -1,17 +1,45 @@
 // Main application library - shared between desktop and mobile
 
-#[cfg(desktop)]
-use tauri::Manager;
 #[cfg(desktop)]
 use tauri::Emitter;
+#[cfg(desktop)]
+use tauri::Manager;
 
+mod benson;
+mod board;
 mod commands;
+#[cfg(desktop)]
+mod gtp;
+mod logging;
 mod onnx_engine;
+mod onnx_proto;
+mod sgf;
 #[cfg(desktop)]
 mod window_state;
 
+/// Whether this launch asked for CPU safe mode, with `--safe-mode` or `KAYA_SAFE_MODE=1`
+fn safe_mode_requested() -> bool {
+    std::env::args().any(|arg| arg == "--safe-mode")
+        || std::env::var("KAYA_SAFE_MODE").is_ok_and(|value| value == "1")
+}
+
+/// Serve the ONNX engine over GTP on stdin/stdout instead of starting the GUI
+#[cfg(desktop)]
+pub fn run_gtp(model_path: &str) -> Result<(), String> {
+    logging::init();
+    if safe_mode_requested() {
+        onnx_engine::set_cpu_safe_mode(true);
+    }
+    gtp::run(model_path)
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    logging::init();
+    if safe_mode_requested() {
+        onnx_engine::set_cpu_safe_mode(true);
+    }
+
     let builder = tauri::Builder::default()
         .plugin(tauri_plugin_clipboard_manager::init())
         .plugin(tauri_plugin_dialog::init())
@@ -21,19 +49,95 @@ pub fn run() {
             commands::onnx_start_upload,
             commands::onnx_upload_chunk,
             commands::onnx_finish_upload,
+            commands::onnx_cancel_upload,
+            commands::onnx_download_model,
             commands::onnx_get_cached_model,
+            commands::onnx_list_cached_models,
+            commands::onnx_verify_cached_model,
             commands::onnx_delete_cached_model,
+            commands::onnx_set_cache_limit_bytes,
+            commands::onnx_set_temp_dir,
+            commands::onnx_get_cache_usage,
             commands::onnx_initialize,
             commands::onnx_initialize_base64,
             commands::onnx_initialize_from_path,
+            commands::onnx_initialize_from_cache,
+            commands::onnx_preload_model,
+            commands::onnx_activate_preloaded,
+            commands::onnx_inspect_model,
+            commands::load_sgf_position,
+            commands::go_is_legal_move,
+            commands::go_pass_alive_regions,
+            commands::go_board_new,
+            commands::go_board_play,
+            commands::go_board_undo,
+            commands::go_board_state,
+            commands::go_board_free,
             commands::onnx_analyze,
+            commands::onnx_analyze_flat,
+            commands::onnx_analyze_after_moves,
+            commands::onnx_run_raw,
+            commands::onnx_set_autosave_position,
+            commands::onnx_get_last_position,
+            commands::onnx_analyze_sync,
+            commands::onnx_initialize_slot,
+            commands::onnx_dispose_slot,
+            commands::onnx_analyze_ensemble,
             commands::onnx_analyze_batch,
+            commands::onnx_analyze_batch_flat,
+            commands::onnx_analyze_searched,
+            commands::onnx_genmove,
+            commands::onnx_selfplay,
+            commands::onnx_estimate_final_score,
+            commands::onnx_move_impact,
+            commands::onnx_grade_move,
+            commands::onnx_estimate_fair_komi,
+            commands::onnx_get_pv,
+            commands::onnx_debug_featurize,
+            commands::onnx_start_ponder,
+            commands::onnx_stop_ponder,
+            commands::onnx_analyze_batch_auto,
+            commands::onnx_analyze_batch_streaming,
+            commands::onnx_cancel,
+            commands::onnx_analyze_game,
+            commands::onnx_autotune_batch,
+            commands::onnx_benchmark,
             commands::onnx_dispose,
+            commands::onnx_reset,
+            commands::onnx_queue_depth,
+            commands::onnx_get_stats,
+            commands::onnx_reset_stats,
             commands::onnx_is_initialized,
+            commands::onnx_get_loaded_model,
+            commands::onnx_force_cpu_mode,
+            commands::onnx_is_cpu_safe_mode,
+            commands::set_log_level,
+            commands::get_log_level,
+            commands::set_log_to_file,
+            commands::onnx_set_cache_capacity,
+            commands::onnx_clear_analysis_cache,
             commands::onnx_get_provider_info,
+            commands::onnx_get_memory_info,
+            commands::onnx_get_runtime_info,
             commands::onnx_get_available_providers,
             commands::onnx_set_provider_preference,
             commands::onnx_get_provider_preference,
+            commands::onnx_set_session_count,
+            commands::onnx_get_session_count,
+            commands::onnx_set_max_batch,
+            commands::onnx_get_max_batch,
+            commands::onnx_set_intra_op_threads,
+            commands::onnx_set_inter_op_threads,
+            commands::onnx_get_provider_settings,
+            commands::onnx_get_provider_diagnostics,
+            commands::onnx_set_device_index,
+            commands::onnx_get_device_index,
+            commands::onnx_set_optimization_level,
+            commands::onnx_get_optimization_level,
+            commands::onnx_set_coreml_compute_units,
+            commands::onnx_get_coreml_compute_units,
+            commands::onnx_set_tensorrt_cache_dir,
+            commands::onnx_clear_optimized_cache,
         ]);
 
     // Desktop-only plugins
@@ -45,6 +149,24 @@ pub fn run() {
         .plugin(tauri_plugin_window_state::Builder::default().build());
 
     let builder = builder.setup(|app| {
+        // Restore the provider, device and thread settings before anything initializes
+        commands::restore_engine_settings(app.handle());
+        commands::cleanup_stale_upload_files(app.handle());
+        commands::register_engine_events(app.handle());
+
+        // Turns on CPU safe mode if GPU session creation keeps crashing the app
+        #[cfg(desktop)]
+        if let Ok(app_data) = app.path().app_data_dir() {
+            onnx_engine::set_crash_marker_path(app_data.join("session-init.marker"));
+        }
+
+        // Keep TensorRT engine caches under app data so they survive relaunches
+        #[cfg(desktop)]
+        if let Ok(app_data) = app.path().app_data_dir() {
+            let cache_dir = app_data.join("tensorrt-cache");
+            onnx_engine::set_tensorrt_cache_dir(Some(cache_dir.to_string_lossy().to_string()));
+        }
+
         // Restore window state for the current monitor setup (desktop only)
         #[cfg(desktop)]
         if let Some(window) = app.get_webview_window("main") {
@@ -56,13 +178,8 @@ pub fn run() {
             use tauri::menu::{Menu, MenuItem, PredefinedMenuItem, Submenu};
             let handle = app.handle();
 
-            let show_about = MenuItem::with_id(
-                handle,
-                "show_about",
-                "About Kaya",
-                true,
-                None::<&str>,
-            )?;
+            let show_about =
+                MenuItem::with_id(handle, "show_about", "About Kaya", true, None::<&str>)?;
 
             let check_update = MenuItem::with_id(
                 handle,
@@ -132,10 +249,10 @@ pub fn run() {
                     window_state::save_window_state_from_window(window, window.app_handle());
                 }
             }
-            // Also save on move/resize for more frequent persistence
+            // Also save on move/resize for more frequent persistence, debounced while dragging
             if let tauri::WindowEvent::Moved(_) | tauri::WindowEvent::Resized(_) = event {
                 if window.label() == "main" {
-                    window_state::save_window_state_from_window(window, window.app_handle());
+                    window_state::save_window_state_debounced(window, window.app_handle());
                 }
             }
         }
@@ -147,6 +264,12 @@ pub fn run() {
     });
 
     builder
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|_app, event| {
+            // A clean exit has nothing to recover, so drop the autosaved position
+            if let tauri::RunEvent::Exit = event {
+                commands::clear_last_position();
+            }
+        });
 }
@@ -0,0 +1,113 @@
+//! Leveled logging for the engine and commands.
+//!
+//! Messages go through the `log` facade and are written to stderr and,
+//! when enabled, appended to `kaya.log` in the app data directory so users
+//! can attach it to bug reports. The level starts at `KAYA_LOG` (default
+//! `info`) and can be changed at runtime.
+
+use log::{LevelFilter, Log, Metadata, Record};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Log files past this size are truncated when logging to them is enabled
+const MAX_LOG_FILE_BYTES: u64 = 10 * 1024 * 1024;
+
+/// Name of the log file inside the app data directory
+pub const LOG_FILE_NAME: &str = "kaya.log";
+
+/// Logger writing to stderr and the optional log file
+struct KayaLogger {
+    file: Mutex<Option<File>>,
+}
+
+static LOGGER: KayaLogger = KayaLogger {
+    file: Mutex::new(None),
+};
+
+impl Log for KayaLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let elapsed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let line = format!(
+            "{}.{:03} {:<5} {}",
+            elapsed.as_secs(),
+            elapsed.subsec_millis(),
+            record.level(),
+            record.args()
+        );
+        eprintln!("{}", line);
+        if let Some(file) = self.file.lock().unwrap().as_mut() {
+            let _ = writeln!(file, "{}", line);
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(file) = self.file.lock().unwrap().as_mut() {
+            let _ = file.flush();
+        }
+    }
+}
+
+/// Install the logger, at the level from `KAYA_LOG` or info
+/// Calling it again is harmless
+pub fn init() {
+    let level = std::env::var("KAYA_LOG")
+        .ok()
+        .and_then(|level| LevelFilter::from_str(&level).ok())
+        .unwrap_or(LevelFilter::Info);
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(level);
+    }
+}
+
+/// Set the most verbose level that gets logged
+/// Accepts off, error, warn, info, debug or trace
+pub fn set_level(level: &str) -> Result<(), String> {
+    let level = LevelFilter::from_str(level).map_err(|_| {
+        format!(
+            "Invalid log level: {} (expected off, error, warn, info, debug or trace)",
+            level
+        )
+    })?;
+    log::set_max_level(level);
+    Ok(())
+}
+
+/// Get the current log level
+pub fn get_level() -> String {
+    log::max_level().to_string().to_lowercase()
+}
+
+/// Start appending log messages to `dir`/kaya.log, or stop with None
+/// Returns the path of the log file
+pub fn set_log_dir(dir: Option<&Path>) -> Result<Option<PathBuf>, String> {
+    let Some(dir) = dir else {
+        *LOGGER.file.lock().unwrap() = None;
+        return Ok(None);
+    };
+    std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create log directory: {}", e))?;
+    let path = dir.join(LOG_FILE_NAME);
+    let too_big = std::fs::metadata(&path).is_ok_and(|m| m.len() > MAX_LOG_FILE_BYTES);
+    let file = OpenOptions::new()
+        .create(true)
+        .append(!too_big)
+        .write(true)
+        .truncate(too_big)
+        .open(&path)
+        .map_err(|e| format!("Failed to open log file: {}", e))?;
+    *LOGGER.file.lock().unwrap() = Some(file);
+    log::info!("[Logging] Writing logs to {:?}", path);
+    Ok(Some(path))
+}
@@ -2,5 +2,19 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 fn main() {
+    // `kaya --gtp <model.onnx>` runs a GTP engine for external GUIs instead of the app
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(index) = args.iter().position(|arg| arg == "--gtp") {
+        let Some(model_path) = args.get(index + 1) else {
+            eprintln!("Usage: kaya --gtp <model.onnx>");
+            std::process::exit(2);
+        };
+        if let Err(e) = kaya::run_gtp(model_path) {
+            eprintln!("[GTP] {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     kaya::run();
 }
@@ -1,24 +1,29 @@
 //! Native ONNX Runtime engine for KataGo inference
 //!
 //! This module provides AI analysis using native ONNX Runtime
-//! with GPU acceleration via CUDA, CoreML, DirectML, or NNAPI (Android).
+//! with GPU acceleration via CUDA, ROCm, CoreML, DirectML, or NNAPI (Android).
 
+use crate::board::GoBoard;
 use half::f16;
-use ndarray::{Array2, Array4};
+use ndarray::{Array1, Array2, Array3, Array4, ArrayViewMut1, ArrayViewMut3, Axis};
+use ort::execution_providers::ExecutionProvider;
+#[cfg(target_os = "android")]
+use ort::execution_providers::NNAPIExecutionProvider;
 use ort::{
     execution_providers::{
-        CUDAExecutionProvider, CoreMLExecutionProvider, DirectMLExecutionProvider,
+        coreml::CoreMLComputeUnits, CUDAExecutionProvider, CoreMLExecutionProvider,
+        DirectMLExecutionProvider, ROCmExecutionProvider, TensorRTExecutionProvider,
     },
-    session::{builder::GraphOptimizationLevel, Session},
+    session::{builder::GraphOptimizationLevel, Session, SessionInputValue},
     value::Tensor,
 };
-#[cfg(target_os = "android")]
-use ort::execution_providers::NNAPIExecutionProvider;
 use serde::{Deserialize, Serialize};
-use std::path::Path;
-use std::sync::Mutex;
-#[cfg(target_os = "android")]
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::collections::{BTreeMap, HashMap};
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, RwLock};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
 /// Execution provider preference for ONNX Runtime
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
@@ -29,10 +34,14 @@ pub enum ExecutionProviderPreference {
     Auto,
     /// Force CUDA (NVIDIA GPU)
     Cuda,
+    /// Force TensorRT (NVIDIA GPU, optimized engines built on first run)
+    TensorRt,
     /// Force CoreML (Apple Silicon/Neural Engine)
     CoreMl,
     /// Force DirectML (Windows GPU)
     DirectMl,
+    /// Force ROCm (AMD GPU on Linux, needs a build with the `rocm` feature)
+    Rocm,
     /// Force NNAPI (Android Neural Networks API)
     Nnapi,
     /// Force CPU only
@@ -49,19 +58,451 @@ pub struct ExecutionProviderInfo {
     pub is_gpu: bool,
     /// Human-readable description
     pub description: String,
+    /// Whether initialization fell back from the preferred provider
+    #[serde(default)]
+    pub fallback_used: bool,
+    /// Whether the loaded model is int8 quantized
+    #[serde(default)]
+    pub is_quantized: bool,
+    /// GPU the provider runs on, for providers that support device selection
+    #[serde(default)]
+    pub device_index: Option<u32>,
+    /// Name of that GPU, when it can be queried
+    #[serde(default)]
+    pub device_name: Option<String>,
+    /// Label for the provider picker, including the GPU name when known
+    #[serde(default)]
+    pub display_name: String,
+    /// Whether the provider runs fp16 models natively
+    #[serde(default)]
+    pub supports_fp16: bool,
+    /// Whether this is the provider `Auto` selects on this machine
+    #[serde(default)]
+    pub recommended: bool,
+    /// Compute units CoreML may use, only set for the CoreML provider
+    #[serde(default)]
+    pub coreml_compute_units: Option<CoreMlComputeUnits>,
+    /// Why the last attempt to create a session with this provider failed, if it did
+    #[serde(default)]
+    pub last_init_error: Option<String>,
+}
+
+/// Error returned by the analysis functions
+///
+/// Serialized as `{ "kind": "notInitialized", "message": "Engine not initialized" }`
+/// so the frontend can branch on `kind` while older callers keep matching on `message`.
+#[derive(Debug, Clone)]
+pub enum OnnxError {
+    /// No model is loaded
+    NotInitialized,
+    /// The board sent for analysis is malformed
+    InvalidBoard(String),
+    /// The model's outputs don't have the expected shape, or the model doesn't
+    /// support the board size
+    ShapeMismatch(String),
+    /// The GPU execution provider failed while running the model, e.g. a lost
+    /// device or out of memory; switching to CPU may work around it
+    ProviderUnavailable(String),
+    /// The request was cancelled with [`cancel_request`]
+    Cancelled,
+    /// Any other inference failure
+    Inference(String),
+}
+
+impl OnnxError {
+    fn kind(&self) -> &'static str {
+        match self {
+            OnnxError::NotInitialized => "notInitialized",
+            OnnxError::InvalidBoard(_) => "invalidBoard",
+            OnnxError::ShapeMismatch(_) => "shapeMismatch",
+            OnnxError::ProviderUnavailable(_) => "providerUnavailable",
+            OnnxError::Cancelled => "cancelled",
+            OnnxError::Inference(_) => "inference",
+        }
+    }
+}
+
+impl std::fmt::Display for OnnxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OnnxError::NotInitialized => write!(f, "Engine not initialized"),
+            OnnxError::Cancelled => write!(f, "Cancelled"),
+            OnnxError::InvalidBoard(message)
+            | OnnxError::ShapeMismatch(message)
+            | OnnxError::ProviderUnavailable(message)
+            | OnnxError::Inference(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl Serialize for OnnxError {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+        let mut state = serializer.serialize_struct("OnnxError", 2)?;
+        state.serialize_field("kind", self.kind())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.end()
+    }
+}
+
+impl From<String> for OnnxError {
+    fn from(message: String) -> Self {
+        OnnxError::Inference(message)
+    }
+}
+
+impl From<OnnxError> for String {
+    fn from(error: OnnxError) -> Self {
+        error.to_string()
+    }
+}
+
+/// Version and build details of the bundled ONNX Runtime, for bug reports
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RuntimeInfo {
+    /// Version of this app
+    pub app_version: String,
+    /// ONNX Runtime version, e.g. "1.22.0"; only "1.22.x" when the build info lacks it
+    pub ort_version: String,
+    /// ONNX Runtime's own build info string (branch, commit, build type and flags)
+    pub ort_build_info: String,
+    /// Execution providers compiled into the ONNX Runtime binary
+    pub compiled_providers: Vec<String>,
+    /// "debug" or "release"
+    pub build_profile: String,
+}
+
+/// Device memory usage of the active execution provider
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MemoryInfo {
+    /// Name of the active execution provider
+    pub provider: String,
+    /// Memory in use on the device, None when it can't be queried (e.g. CPU)
+    pub device_used_bytes: Option<u64>,
+    /// Total memory of the device, None when it can't be queried (e.g. CPU)
+    pub device_total_bytes: Option<u64>,
+}
+
+/// ORT graph optimization level
+///
+/// Lower levels work around driver bugs triggered by aggressive fusions,
+/// e.g. on some DirectML drivers, at the cost of slower inference.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum OptimizationLevel {
+    /// No graph optimizations
+    Disabled,
+    /// Semantics-preserving rewrites such as constant folding
+    Basic,
+    /// Basic plus node fusions
+    Extended,
+    /// Every optimization, including layout transformations
+    #[default]
+    All,
+}
+
+impl OptimizationLevel {
+    /// Name used by the settings commands and optimized model stamps
+    pub fn name(self) -> &'static str {
+        match self {
+            OptimizationLevel::Disabled => "disabled",
+            OptimizationLevel::Basic => "basic",
+            OptimizationLevel::Extended => "extended",
+            OptimizationLevel::All => "all",
+        }
+    }
+
+    fn to_ort(self) -> GraphOptimizationLevel {
+        match self {
+            OptimizationLevel::Disabled => GraphOptimizationLevel::Disable,
+            OptimizationLevel::Basic => GraphOptimizationLevel::Level1,
+            OptimizationLevel::Extended => GraphOptimizationLevel::Level2,
+            OptimizationLevel::All => GraphOptimizationLevel::Level3,
+        }
+    }
+}
+
+/// Hardware the CoreML provider may run the model on
+///
+/// The Neural Engine is usually fastest and most power-efficient, but CoreML
+/// falls back to the CPU for operators it doesn't support there, which can make
+/// some models slower than with the GPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum CoreMlComputeUnits {
+    /// Let CoreML choose among the CPU, GPU and Neural Engine
+    #[default]
+    All,
+    CpuAndGpu,
+    CpuAndNeuralEngine,
+    CpuOnly,
+}
+
+impl CoreMlComputeUnits {
+    /// Name used by the settings commands
+    pub fn name(self) -> &'static str {
+        match self {
+            CoreMlComputeUnits::All => "all",
+            CoreMlComputeUnits::CpuAndGpu => "cpuAndGpu",
+            CoreMlComputeUnits::CpuAndNeuralEngine => "cpuAndNeuralEngine",
+            CoreMlComputeUnits::CpuOnly => "cpuOnly",
+        }
+    }
+
+    fn to_ort(self) -> CoreMLComputeUnits {
+        match self {
+            CoreMlComputeUnits::All => CoreMLComputeUnits::All,
+            CoreMlComputeUnits::CpuAndGpu => CoreMLComputeUnits::CPUAndGPU,
+            CoreMlComputeUnits::CpuAndNeuralEngine => CoreMLComputeUnits::CPUAndNeuralEngine,
+            CoreMlComputeUnits::CpuOnly => CoreMLComputeUnits::CPUOnly,
+        }
+    }
+}
+
+/// Session configuration applied on the next engine initialization
+///
+/// Kept separate from the engine so it survives a dispose/reinit cycle.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderSettings {
+    /// Preferred execution provider
+    pub preference: ExecutionProviderPreference,
+    /// Threads used within a single operator (0 = let ORT decide)
+    pub intra_op_threads: usize,
+    /// Threads used to run independent operators in parallel (0 = let ORT decide)
+    pub inter_op_threads: usize,
+    /// Directory where TensorRT stores its built engines between launches
+    pub tensorrt_cache_dir: Option<String>,
+    /// Graph optimization level
+    #[serde(default)]
+    pub optimization_level: OptimizationLevel,
+    /// GPU used by the CUDA, TensorRT, ROCm and DirectML providers
+    #[serde(default)]
+    pub device_index: u32,
+    /// Hardware the CoreML provider may use
+    #[serde(default)]
+    pub coreml_compute_units: CoreMlComputeUnits,
 }
 
-/// Global preference for execution provider
-static EP_PREFERENCE: Mutex<ExecutionProviderPreference> = Mutex::new(ExecutionProviderPreference::Auto);
+impl ProviderSettings {
+    const DEFAULT: Self = Self {
+        preference: ExecutionProviderPreference::Auto,
+        // On Android, we use fewer threads to be more battery-friendly
+        intra_op_threads: if cfg!(target_os = "android") { 2 } else { 4 },
+        inter_op_threads: 0,
+        tensorrt_cache_dir: None,
+        optimization_level: OptimizationLevel::All,
+        device_index: 0,
+        coreml_compute_units: CoreMlComputeUnits::All,
+    };
+}
+
+impl Default for ProviderSettings {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Global provider settings
+static PROVIDER_SETTINGS: Mutex<ProviderSettings> = Mutex::new(ProviderSettings::DEFAULT);
+
+/// Get the current provider settings
+pub fn get_provider_settings() -> ProviderSettings {
+    PROVIDER_SETTINGS.lock().unwrap().clone()
+}
 
 /// Get the current execution provider preference
 pub fn get_execution_provider_preference() -> ExecutionProviderPreference {
-    *EP_PREFERENCE.lock().unwrap()
+    PROVIDER_SETTINGS.lock().unwrap().preference
 }
 
 /// Set the execution provider preference
+/// A loaded engine is marked stale and re-initialized with the new provider
+/// on the next analysis, see [`reinit_if_stale`]
 pub fn set_execution_provider_preference(pref: ExecutionProviderPreference) {
-    *EP_PREFERENCE.lock().unwrap() = pref;
+    let previous = std::mem::replace(&mut PROVIDER_SETTINGS.lock().unwrap().preference, pref);
+    if previous != pref && is_engine_initialized() {
+        log::info!(
+            "[OnnxEngine] Provider preference changed to {}, re-initializing on next analysis",
+            preference_to_name(pref)
+        );
+        ENGINE_STALE.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Set when the provider preference changes while an engine is loaded
+static ENGINE_STALE: AtomicBool = AtomicBool::new(false);
+
+/// Held while a stale engine is re-initialized, so concurrent analyses do it once
+static REINIT_LOCK: Mutex<()> = Mutex::new(());
+
+/// Called with the new provider name after an automatic re-initialization
+type ReinitListener = Box<dyn Fn(&str) + Send>;
+static REINIT_LISTENER: Mutex<Option<ReinitListener>> = Mutex::new(None);
+
+/// Set the callback run after the engine is automatically re-initialized
+pub fn set_reinit_listener(listener: impl Fn(&str) + Send + 'static) {
+    *REINIT_LISTENER.lock().unwrap() = Some(Box::new(listener));
+}
+
+/// Re-initialize the engine from its model file if the provider preference changed
+/// since it was loaded; analyses already running finish on the old sessions
+/// Engines loaded from bytes can't be reloaded and keep their provider
+/// A failed re-initialization leaves the engine stale, so the next analysis retries
+fn reinit_if_stale() -> Result<(), OnnxError> {
+    if !ENGINE_STALE.load(Ordering::SeqCst) {
+        return Ok(());
+    }
+    let _guard = REINIT_LOCK.lock().unwrap();
+    // Another analysis may have re-initialized while this one waited for the lock
+    if !ENGINE_STALE.load(Ordering::SeqCst) {
+        return Ok(());
+    }
+    let Ok(pool) = active_pool() else {
+        ENGINE_STALE.store(false, Ordering::SeqCst);
+        return Ok(());
+    };
+    let Some(model_path) = pool.model_path.clone() else {
+        log::warn!("[OnnxEngine] Model was loaded from bytes, reload it to switch provider");
+        ENGINE_STALE.store(false, Ordering::SeqCst);
+        return Ok(());
+    };
+    let options = ModelLoadOptions {
+        warmup: None,
+        cache_optimized: pool.model_id.is_some(),
+        model_id: pool.model_id.clone(),
+        sha256: pool.model_sha256.clone(),
+        config: pool.config.clone(),
+    };
+    drop(pool);
+
+    log::info!(
+        "[OnnxEngine] Re-initializing {:?} for the new provider preference",
+        model_path
+    );
+    // Clears ENGINE_STALE once the new engine is in place
+    initialize_engine_from_path(&model_path.to_string_lossy(), options)?;
+    let provider = active_pool()?.get_provider_name().to_string();
+    if let Some(listener) = REINIT_LISTENER.lock().unwrap().as_ref() {
+        listener(&provider);
+    }
+    Ok(())
+}
+
+/// Set the intra-op thread count (0 = let ORT decide)
+pub fn set_intra_op_threads(threads: usize) {
+    PROVIDER_SETTINGS.lock().unwrap().intra_op_threads = threads;
+}
+
+/// Set the inter-op thread count (0 = let ORT decide)
+pub fn set_inter_op_threads(threads: usize) {
+    PROVIDER_SETTINGS.lock().unwrap().inter_op_threads = threads;
+}
+
+/// Set the directory used for the TensorRT engine cache
+pub fn set_tensorrt_cache_dir(dir: Option<String>) {
+    PROVIDER_SETTINGS.lock().unwrap().tensorrt_cache_dir = dir;
+}
+
+/// Set the graph optimization level
+pub fn set_optimization_level(level: OptimizationLevel) {
+    PROVIDER_SETTINGS.lock().unwrap().optimization_level = level;
+}
+
+/// Set the GPU used by providers that support device selection
+pub fn set_device_index(index: u32) {
+    PROVIDER_SETTINGS.lock().unwrap().device_index = index;
+}
+
+/// Set the hardware the CoreML provider may use
+pub fn set_coreml_compute_units(units: CoreMlComputeUnits) {
+    PROVIDER_SETTINGS.lock().unwrap().coreml_compute_units = units;
+}
+
+/// Safe mode: force the CPU provider regardless of the stored preference
+static CPU_SAFE_MODE: AtomicBool = AtomicBool::new(false);
+
+/// Crash marker, present while a GPU session is being created
+/// It holds the number of consecutive creations that never returned, so a
+/// driver crash that takes the process down is still noticed on relaunch.
+static CRASH_MARKER_PATH: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Consecutive crashes during session creation after which safe mode turns on
+const SAFE_MODE_CRASH_LIMIT: u32 = 2;
+
+/// Enable or disable CPU safe mode, taking effect on the next initialization
+/// Disabling it also resets the crash count
+pub fn set_cpu_safe_mode(enabled: bool) {
+    CPU_SAFE_MODE.store(enabled, Ordering::SeqCst);
+    if !enabled {
+        if let Some(path) = CRASH_MARKER_PATH.lock().unwrap().as_ref() {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Whether CPU safe mode is active
+pub fn is_cpu_safe_mode() -> bool {
+    CPU_SAFE_MODE.load(Ordering::SeqCst)
+}
+
+/// Set where the crash marker lives, turning on safe mode after a crash loop
+pub fn set_crash_marker_path(path: PathBuf) {
+    let crashes = read_crash_count(&path);
+    if crashes >= SAFE_MODE_CRASH_LIMIT {
+        log::warn!(
+            "[OnnxEngine] Session creation crashed {} times in a row, enabling CPU safe mode",
+            crashes
+        );
+        CPU_SAFE_MODE.store(true, Ordering::SeqCst);
+    }
+    *CRASH_MARKER_PATH.lock().unwrap() = Some(path);
+}
+
+fn read_crash_count(path: &Path) -> u32 {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|contents| contents.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+/// Requested number of sessions in the pool (0 = platform default)
+static SESSION_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Default pool size: number of physical cores, capped at 4
+fn default_session_count() -> usize {
+    num_cpus::get_physical().clamp(1, 4)
+}
+
+/// Get the number of sessions created on the next initialization
+pub fn get_session_count() -> usize {
+    match SESSION_COUNT.load(Ordering::SeqCst) {
+        0 => default_session_count(),
+        n => n,
+    }
+}
+
+/// Set the number of sessions created on the next initialization
+pub fn set_session_count(count: usize) {
+    SESSION_COUNT.store(count, Ordering::SeqCst);
+}
+
+/// Default cap on the positions sent to the model in one inference call
+const DEFAULT_MAX_BATCH_SIZE: usize = 64;
+
+/// Cap on the positions featurized and run at once, bounding peak memory of large batches
+static MAX_BATCH_SIZE: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_BATCH_SIZE);
+
+/// Get the most positions run in one inference call
+pub fn get_max_batch_size() -> usize {
+    MAX_BATCH_SIZE.load(Ordering::SeqCst)
+}
+
+/// Set the most positions run in one inference call; larger batches are split
+pub fn set_max_batch_size(size: usize) {
+    MAX_BATCH_SIZE.store(size.max(1), Ordering::SeqCst);
 }
 
 /// Convert preference to a display name
@@ -69,8 +510,10 @@ fn preference_to_name(pref: ExecutionProviderPreference) -> String {
     match pref {
         ExecutionProviderPreference::Auto => "auto".to_string(),
         ExecutionProviderPreference::Cuda => "cuda".to_string(),
+        ExecutionProviderPreference::TensorRt => "tensorrt".to_string(),
         ExecutionProviderPreference::CoreMl => "coreml".to_string(),
         ExecutionProviderPreference::DirectMl => "directml".to_string(),
+        ExecutionProviderPreference::Rocm => "rocm".to_string(),
         ExecutionProviderPreference::Nnapi => "nnapi".to_string(),
         ExecutionProviderPreference::Cpu => "cpu".to_string(),
     }
@@ -90,57 +533,65 @@ fn ensure_ort_initialized() -> Result<(), String> {
     // On Android, native libraries from jniLibs are loaded into the app's native library directory.
     // The exact path varies by Android version and installation type.
     // We try multiple common paths.
-    
+
     let package_name = "com.kaya.desktop";
-    
+
     // Common paths where Android places native libraries
     let paths_to_try = [
         // Modern Android (API 24+) with split APKs
-        format!("/data/app/~~*/{}*/lib/arm64/libonnxruntime.so", package_name),
+        format!(
+            "/data/app/~~*/{}*/lib/arm64/libonnxruntime.so",
+            package_name
+        ),
         // Standard app data path
         format!("/data/data/{}/lib/libonnxruntime.so", package_name),
-        // Alternative app installation path  
-        format!("/data/app/{}-*/lib/arm64-v8a/libonnxruntime.so", package_name),
+        // Alternative app installation path
+        format!(
+            "/data/app/{}-*/lib/arm64-v8a/libonnxruntime.so",
+            package_name
+        ),
         // Direct library name (let the system find it)
         "libonnxruntime.so".to_string(),
     ];
-    
+
     // First, try to find the library in known locations
     for path_pattern in &paths_to_try {
         // For patterns with wildcards, we need to use glob or skip
         if path_pattern.contains('*') {
             continue; // Skip glob patterns for now
         }
-        
+
         let path = std::path::Path::new(path_pattern);
         if path.exists() {
-            eprintln!("[OnnxEngine] Loading ONNX Runtime from: {}", path_pattern);
+            log::info!("[OnnxEngine] Loading ONNX Runtime from: {}", path_pattern);
             match ort::init_from(path_pattern).commit() {
                 Ok(_) => return Ok(()),
                 Err(e) => {
-                    eprintln!("[OnnxEngine] Failed to load from {}: {}", path_pattern, e);
+                    log::warn!("[OnnxEngine] Failed to load from {}: {}", path_pattern, e);
                     continue;
                 }
             }
         }
     }
-    
+
     // If no explicit path works, try the library name directly.
     // This relies on the JNI loader having already loaded the library or it being in LD_LIBRARY_PATH.
-    eprintln!("[OnnxEngine] Attempting to load ONNX Runtime via system loader (libonnxruntime.so)");
+    log::info!(
+        "[OnnxEngine] Attempting to load ONNX Runtime via system loader (libonnxruntime.so)"
+    );
     match ort::init_from("libonnxruntime.so").commit() {
         Ok(_) => return Ok(()),
         Err(e) => {
-            eprintln!("[OnnxEngine] Failed to load libonnxruntime.so: {}", e);
+            log::warn!("[OnnxEngine] Failed to load libonnxruntime.so: {}", e);
         }
     }
-    
+
     // Last resort: initialize without specifying a path
-    eprintln!("[OnnxEngine] Attempting default ONNX Runtime initialization");
+    log::info!("[OnnxEngine] Attempting default ONNX Runtime initialization");
     ort::init()
         .commit()
         .map_err(|e| format!("Failed to initialize ONNX Runtime: {}", e))?;
-    
+
     Ok(())
 }
 
@@ -152,73 +603,103 @@ fn ensure_ort_initialized() -> Result<(), String> {
 
 use ort::session::builder::SessionBuilder;
 
-/// Configure execution providers based on preference and platform
-fn configure_execution_providers(
-    builder: SessionBuilder,
-    preference: ExecutionProviderPreference,
-) -> Result<SessionBuilder, String> {
+/// Ordered list of concrete providers to try for a preference
+///
+/// GPU providers fall back to CPU, so a missing or broken driver
+/// degrades to slower analysis instead of failing initialization.
+fn fallback_chain(preference: ExecutionProviderPreference) -> Vec<ExecutionProviderPreference> {
+    use ExecutionProviderPreference::*;
     match preference {
-        ExecutionProviderPreference::Auto => {
-            // Platform-specific auto configuration
+        Auto => {
             #[cfg(target_os = "android")]
-            {
-                builder
-                    .with_execution_providers([NNAPIExecutionProvider::default().build()])
-                    .map_err(|e| format!("Failed to set NNAPI execution provider: {}", e))
-            }
+            let chain = vec![Nnapi, Cpu];
             #[cfg(target_os = "macos")]
-            {
-                builder
-                    .with_execution_providers([CoreMLExecutionProvider::default().build()])
-                    .map_err(|e| format!("Failed to set CoreML execution provider: {}", e))
-            }
+            let chain = vec![CoreMl, Cpu];
             #[cfg(target_os = "windows")]
-            {
-                builder
-                    .with_execution_providers([
-                        DirectMLExecutionProvider::default().build(),
-                        CUDAExecutionProvider::default().build(),
-                    ])
-                    .map_err(|e| format!("Failed to set execution providers: {}", e))
-            }
-            #[cfg(target_os = "linux")]
-            {
-                builder
-                    .with_execution_providers([CUDAExecutionProvider::default().build()])
-                    .map_err(|e| format!("Failed to set CUDA execution provider: {}", e))
-            }
-            #[cfg(not(any(target_os = "android", target_os = "macos", target_os = "windows", target_os = "linux")))]
-            {
-                Ok(builder)
-            }
+            let chain = vec![DirectMl, Cuda, Cpu];
+            #[cfg(all(target_os = "linux", feature = "rocm"))]
+            let chain = vec![Cuda, Rocm, Cpu];
+            #[cfg(all(target_os = "linux", not(feature = "rocm")))]
+            let chain = vec![Cuda, Cpu];
+            #[cfg(not(any(
+                target_os = "android",
+                target_os = "macos",
+                target_os = "windows",
+                target_os = "linux"
+            )))]
+            let chain = vec![Cpu];
+            chain
+        }
+        Cpu => vec![Cpu],
+        TensorRt => vec![TensorRt, Cuda, Cpu],
+        gpu => vec![gpu, Cpu],
+    }
+}
+
+/// Register a single concrete execution provider on the builder
+///
+/// Registration errors are returned instead of being silently ignored
+/// by ORT, so the caller can move on to the next provider in the chain.
+fn configure_execution_provider(
+    builder: SessionBuilder,
+    provider: ExecutionProviderPreference,
+    settings: &ProviderSettings,
+) -> Result<SessionBuilder, String> {
+    match provider {
+        ExecutionProviderPreference::Auto => {
+            Err("Auto must be resolved to a concrete provider".to_string())
         }
         ExecutionProviderPreference::Cuda => {
+            let cuda =
+                CUDAExecutionProvider::default().with_device_id(settings.device_index as i32);
             builder
-                .with_execution_providers([CUDAExecutionProvider::default().build()])
+                .with_execution_providers([cuda.build().error_on_failure()])
                 .map_err(|e| format!("Failed to set CUDA execution provider: {}", e))
         }
+        ExecutionProviderPreference::TensorRt => {
+            let mut tensorrt =
+                TensorRTExecutionProvider::default().with_device_id(settings.device_index as i32);
+            if let Some(dir) = &settings.tensorrt_cache_dir {
+                std::fs::create_dir_all(dir)
+                    .map_err(|e| format!("Failed to create TensorRT cache dir: {}", e))?;
+                tensorrt = tensorrt.with_engine_cache(true).with_engine_cache_path(dir);
+            }
+            builder
+                .with_execution_providers([tensorrt.build().error_on_failure()])
+                .map_err(|e| format!("Failed to set TensorRT execution provider: {}", e))
+        }
         ExecutionProviderPreference::CoreMl => {
+            let coreml = CoreMLExecutionProvider::default()
+                .with_compute_units(settings.coreml_compute_units.to_ort());
             builder
-                .with_execution_providers([CoreMLExecutionProvider::default().build()])
+                .with_execution_providers([coreml.build().error_on_failure()])
                 .map_err(|e| format!("Failed to set CoreML execution provider: {}", e))
         }
         ExecutionProviderPreference::DirectMl => {
+            let directml =
+                DirectMLExecutionProvider::default().with_device_id(settings.device_index as i32);
             builder
-                .with_execution_providers([DirectMLExecutionProvider::default().build()])
+                .with_execution_providers([directml.build().error_on_failure()])
                 .map_err(|e| format!("Failed to set DirectML execution provider: {}", e))
         }
-        #[cfg(target_os = "android")]
-        ExecutionProviderPreference::Nnapi => {
+        ExecutionProviderPreference::Rocm if !cfg!(feature = "rocm") => {
+            Err("ROCm support is not compiled in, build with the `rocm` feature".to_string())
+        }
+        ExecutionProviderPreference::Rocm => {
+            let rocm =
+                ROCmExecutionProvider::default().with_device_id(settings.device_index as i32);
             builder
-                .with_execution_providers([NNAPIExecutionProvider::default().build()])
-                .map_err(|e| format!("Failed to set NNAPI execution provider: {}", e))
+                .with_execution_providers([rocm.build().error_on_failure()])
+                .map_err(|e| format!("Failed to set ROCm execution provider: {}", e))
         }
+        #[cfg(target_os = "android")]
+        ExecutionProviderPreference::Nnapi => builder
+            .with_execution_providers([NNAPIExecutionProvider::default()
+                .build()
+                .error_on_failure()])
+            .map_err(|e| format!("Failed to set NNAPI execution provider: {}", e)),
         #[cfg(not(target_os = "android"))]
-        ExecutionProviderPreference::Nnapi => {
-            // NNAPI is only available on Android, fall back to CPU
-            eprintln!("[OnnxEngine] NNAPI is only available on Android, using CPU");
-            Ok(builder)
-        }
+        ExecutionProviderPreference::Nnapi => Err("NNAPI is only available on Android".to_string()),
         ExecutionProviderPreference::Cpu => {
             // No GPU providers, CPU is the default fallback
             Ok(builder)
@@ -226,6 +707,219 @@ fn configure_execution_providers(
     }
 }
 
+/// Build a session for one concrete provider, loading the model with `load`
+fn build_session<F>(
+    provider: ExecutionProviderPreference,
+    settings: &ProviderSettings,
+    load: &F,
+) -> Result<Session, String>
+where
+    F: Fn(SessionBuilder) -> Result<Session, String>,
+{
+    let builder =
+        Session::builder().map_err(|e| format!("Failed to create session builder: {}", e))?;
+
+    let builder = configure_execution_provider(builder, provider, settings)?;
+
+    // Common optimizations
+    // A thread count of 0 lets ORT pick its own default
+    let builder = builder
+        .with_optimization_level(settings.optimization_level.to_ort())
+        .map_err(|e| format!("Failed to set optimization level: {}", e))?
+        .with_intra_threads(settings.intra_op_threads)
+        .map_err(|e| format!("Failed to set intra threads: {}", e))?
+        .with_inter_threads(settings.inter_op_threads)
+        .map_err(|e| format!("Failed to set inter threads: {}", e))?;
+
+    load(builder)
+}
+
+/// Outcome of the last attempt to create a session with one execution provider
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderDiagnostic {
+    /// Provider name, as in [`ExecutionProviderInfo::name`]
+    pub name: String,
+    /// Whether the last attempt succeeded
+    pub succeeded: bool,
+    /// Error of the last attempt, None if it succeeded
+    pub last_init_error: Option<String>,
+    /// When the last attempt finished, in milliseconds since the Unix epoch
+    pub attempted_at_ms: u64,
+}
+
+/// Last session creation attempt per provider, filled in by [`build_session_with_fallback`]
+static PROVIDER_DIAGNOSTICS: Mutex<BTreeMap<String, ProviderDiagnostic>> =
+    Mutex::new(BTreeMap::new());
+
+/// Record the outcome of creating a session with `provider`
+fn record_provider_attempt(provider: ExecutionProviderPreference, error: Option<&String>) {
+    let name = preference_to_name(provider);
+    let attempted_at_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64);
+    PROVIDER_DIAGNOSTICS.lock().unwrap().insert(
+        name.clone(),
+        ProviderDiagnostic {
+            name,
+            succeeded: error.is_none(),
+            last_init_error: error.cloned(),
+            attempted_at_ms,
+        },
+    );
+}
+
+/// Get the outcome of the last session creation attempt for each provider tried so far
+/// A provider that failed and was skipped for a fallback shows up with its error
+pub fn get_provider_diagnostics() -> Vec<ProviderDiagnostic> {
+    PROVIDER_DIAGNOSTICS
+        .lock()
+        .unwrap()
+        .values()
+        .cloned()
+        .collect()
+}
+
+/// Error of the last failed session creation with the provider called `name`
+fn last_init_error(name: &str) -> Option<String> {
+    PROVIDER_DIAGNOSTICS
+        .lock()
+        .unwrap()
+        .get(name)
+        .and_then(|diagnostic| diagnostic.last_init_error.clone())
+}
+
+/// Try each provider in the fallback chain until a session loads
+///
+/// Returns the session, the provider that loaded, and whether it was
+/// a fallback rather than the first choice.
+fn build_session_with_fallback<F>(
+    settings: &ProviderSettings,
+    load: F,
+) -> Result<(Session, ExecutionProviderPreference, bool), String>
+where
+    F: Fn(SessionBuilder) -> Result<Session, String>,
+{
+    let preference = if is_cpu_safe_mode() {
+        ExecutionProviderPreference::Cpu
+    } else {
+        settings.preference
+    };
+    let chain = fallback_chain(preference);
+    let mut last_error = String::from("No execution provider available");
+
+    // Count this attempt until it returns, the crash count only survives a crash.
+    // Safe mode leaves the count alone so it stays on across relaunches.
+    let marker = CRASH_MARKER_PATH
+        .lock()
+        .unwrap()
+        .clone()
+        .filter(|_| preference != ExecutionProviderPreference::Cpu);
+    if let Some(path) = &marker {
+        if let Some(parent) = path.parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+        let _ = std::fs::write(path, (read_crash_count(path) + 1).to_string());
+    }
+    let clear_marker = || {
+        if let Some(path) = &marker {
+            let _ = std::fs::remove_file(path);
+        }
+    };
+
+    for (i, &provider) in chain.iter().enumerate() {
+        let result = build_session(provider, settings, &load);
+        record_provider_attempt(provider, result.as_ref().err());
+        match result {
+            Ok(session) => {
+                if i > 0 {
+                    log::warn!(
+                        "[OnnxEngine] Fell back to {} execution provider",
+                        preference_to_name(provider)
+                    );
+                }
+                clear_marker();
+                return Ok((session, provider, i > 0));
+            }
+            Err(e) => {
+                log::warn!(
+                    "[OnnxEngine] Failed to initialize {} execution provider: {}",
+                    preference_to_name(provider),
+                    e
+                );
+                last_error = e;
+            }
+        }
+    }
+
+    clear_marker();
+    Err(last_error)
+}
+
+/// Stamp written next to an optimized graph describing how it was built
+///
+/// An optimized graph may contain provider-specific nodes, so it is only
+/// reused when the provider and optimization level still match.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OptimizedModelStamp {
+    /// Provider the graph was optimized for
+    provider: String,
+    /// Graph optimization level used
+    optimization_level: String,
+}
+
+/// Path of the optimized graph cached next to a model (`<id>.opt.onnx`)
+pub fn optimized_model_path(model_path: &Path) -> PathBuf {
+    model_path.with_extension("opt.onnx")
+}
+
+/// Path of the stamp describing an optimized graph (`<id>.opt.json`)
+fn optimized_stamp_path(model_path: &Path) -> PathBuf {
+    model_path.with_extension("opt.json")
+}
+
+/// Read the stamp of the cached optimized graph, if the graph exists
+/// and is at least as new as the source model
+fn read_optimized_stamp(model_path: &Path) -> Option<OptimizedModelStamp> {
+    let source_modified = std::fs::metadata(model_path)
+        .and_then(|m| m.modified())
+        .ok()?;
+    let opt_modified = std::fs::metadata(optimized_model_path(model_path))
+        .and_then(|m| m.modified())
+        .ok()?;
+    if opt_modified < source_modified {
+        return None;
+    }
+    let contents = std::fs::read_to_string(optimized_stamp_path(model_path)).ok()?;
+    serde_json::from_str(&contents).ok()
+}
+
+/// External data files a model references, relative to its directory
+/// Fails if a location would resolve outside that directory
+pub fn external_data_files(model_path: &Path) -> Result<Vec<PathBuf>, String> {
+    let info = crate::onnx_proto::read_model_info(model_path)?;
+    info.external_data
+        .iter()
+        .map(|location| {
+            let relative = PathBuf::from(location);
+            let contained = relative
+                .components()
+                .all(|c| matches!(c, std::path::Component::Normal(_)));
+            if !contained || location.is_empty() {
+                return Err(format!("Invalid external data location: {}", location));
+            }
+            Ok(relative)
+        })
+        .collect()
+}
+
+/// Remove the cached optimized graph and its stamp for a model
+pub fn remove_optimized_model(model_path: &Path) {
+    let _ = std::fs::remove_file(optimized_model_path(model_path));
+    let _ = std::fs::remove_file(optimized_stamp_path(model_path));
+}
+
 /// A move suggestion from the AI
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MoveSuggestion {
@@ -236,51 +930,595 @@ pub struct MoveSuggestion {
     pub probability: f32,
 }
 
-/// Analysis result for a board position
+/// Median inference latencies measured on a dummy 19x19 position
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct AnalysisResult {
-    /// Top move suggestions
-    pub move_suggestions: Vec<MoveSuggestion>,
-    /// Win rate from Black's perspective (0.0 to 1.0)
-    pub win_rate: f32,
-    /// Score lead from Black's perspective (positive = Black ahead)
-    pub score_lead: f32,
-    /// Current turn ('B' or 'W')
-    pub current_turn: String,
-    /// Ownership map (size*size, values -1 to 1 from Black's perspective)
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub ownership: Option<Vec<f32>>,
+pub struct BenchmarkResult {
+    pub single_ms: f64,
+    /// Median latency and throughput for each benchmarked batch size
+    pub batches: Vec<BatchThroughput>,
 }
 
-/// History move entry
+/// Batch sizes benchmarked when the caller doesn't choose
+pub const DEFAULT_BENCHMARK_BATCH_SIZES: [usize; 1] = [8];
+
+/// Throughput measured for one batch size during benchmarking or auto-tuning
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct HistoryMove {
-    /// Stone color: 1 = Black, -1 = White
-    pub color: i8,
-    /// X coordinate (0-18 for 19x19, -1 for pass)
-    pub x: i32,
-    /// Y coordinate (0-18 for 19x19, -1 for pass)
-    pub y: i32,
+#[serde(rename_all = "camelCase")]
+pub struct BatchThroughput {
+    pub batch_size: usize,
+    pub median_ms: f64,
+    /// Positions per second
+    pub inf_s: f64,
 }
 
-/// Analysis options
+/// Result of sweeping batch sizes with [`autotune_batch`]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct AnalysisOptions {
-    /// Komi value (default: 7.5)
-    #[serde(default = "default_komi")]
-    pub komi: f32,
-    /// Next player to move ('B' or 'W')
-    pub next_to_play: Option<String>,
-    /// Move history for history features
-    #[serde(default)]
-    pub history: Vec<HistoryMove>,
+pub struct AutotuneResult {
+    pub best_batch_size: usize,
+    pub results: Vec<BatchThroughput>,
 }
 
-fn default_komi() -> f32 {
-    7.5
-}
+/// Batch sizes tried by [`autotune_batch`], in increasing order
+const AUTOTUNE_BATCH_SIZES: [usize; 6] = [1, 2, 4, 8, 16, 32];
+
+/// Timed runs per batch size during auto-tuning
+const AUTOTUNE_ITERATIONS: usize = 5;
+
+/// Chunk size for [`analyze_batch_auto`] before auto-tuning has run
+const DEFAULT_AUTO_BATCH_SIZE: usize = 8;
+
+/// Final score estimated by [`estimate_final_score`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FinalScore {
+    pub black_score: f32,
+    /// Includes komi
+    pub white_score: f32,
+    /// Stones owned by the opponent, as (x, y)
+    pub dead_stones: Vec<(i32, i32)>,
+    /// Black's lead (black_score - white_score)
+    pub score_lead: f32,
+}
+
+/// Greedy continuation returned by [`get_pv`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PrincipalVariation {
+    /// Moves in GTP format ("D4", "PASS")
+    pub moves: Vec<String>,
+    /// Black's win rate in the position before each move
+    pub win_rates: Vec<f32>,
+}
+
+/// Maximum number of plies played out by [`get_pv`]
+const MAX_PV_DEPTH: usize = 50;
+
+/// Symmetries averaged when estimating the final score
+const FINAL_SCORE_SYMMETRIES: usize = 8;
+
+/// A candidate move re-evaluated by [`analyze_searched`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchedMove {
+    /// Move in GTP format (e.g., "D4", "PASS")
+    #[serde(rename = "move")]
+    pub move_str: String,
+    /// Policy probability of the move in the root position
+    pub prior: f32,
+    /// Win rate after the move, from the value head, oriented like the root result
+    pub win_rate: f32,
+    /// Score lead after the move, oriented like the root result
+    pub score_lead: f32,
+}
+
+/// Result of [`analyze_searched`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchedAnalysis {
+    /// Raw analysis of the root position, with moves in policy order
+    pub policy: AnalysisResult,
+    /// Expanded candidates, best first for the player to move
+    pub searched_moves: Vec<SearchedMove>,
+    /// Win rate backed up from the best candidate, oriented like the root result
+    pub win_rate: f32,
+    /// Score lead backed up from the best candidate, oriented like the root result
+    pub score_lead: f32,
+}
+
+impl SearchedAnalysis {
+    /// Re-orient the root result and every backed-up value from Black's perspective
+    /// to `orient_to`'s, where the side to move is the one at the root
+    fn orient(&mut self, orient_to: Option<i8>) -> Result<(), OnnxError> {
+        self.policy.orient(orient_to)?;
+        let player = if self.policy.current_turn == "B" {
+            1
+        } else {
+            -1
+        };
+        if flips_black_view(orient_to, player) {
+            self.win_rate = 1.0 - self.win_rate;
+            self.score_lead = -self.score_lead;
+            for searched in &mut self.searched_moves {
+                searched.win_rate = 1.0 - searched.win_rate;
+                searched.score_lead = -searched.score_lead;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Black's win rate at one komi, sampled by [`estimate_fair_komi`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KomiSample {
+    pub komi: f32,
+    pub win_rate: f32,
+}
+
+/// Result of [`estimate_fair_komi`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FairKomi {
+    /// Komi at which Black's win rate crosses 50%, None if it doesn't within the range
+    pub fair_komi: Option<f32>,
+    /// Win rate curve, in increasing komi order
+    pub samples: Vec<KomiSample>,
+}
+
+/// Maximum number of komi values analyzed by [`estimate_fair_komi`]
+const MAX_KOMI_SAMPLES: usize = 16;
+
+/// Options for [`analyze_game`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GameAnalysisOptions {
+    #[serde(default = "default_board_size")]
+    pub board_size: usize,
+    #[serde(default = "default_komi")]
+    pub komi: f32,
+}
+
+fn default_board_size() -> usize {
+    19
+}
+
+/// Analysis of every position in a game
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GameAnalysis {
+    /// One result for the starting position plus one after each move, all from
+    /// Black's perspective whoever is to move
+    pub results: Vec<AnalysisResult>,
+    /// Change in Black's win rate caused by each move
+    pub win_rate_deltas: Vec<f32>,
+}
+
+/// A single move chosen by [`genmove`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GenmoveResult {
+    /// Board coordinates of the move (-1 for a pass or resignation)
+    pub x: i32,
+    pub y: i32,
+    pub is_pass: bool,
+    /// The side to move resigns instead of playing, see `AnalysisOptions::resign_threshold`
+    #[serde(default)]
+    pub resign: bool,
+    /// Whether the side to move's win rate is below the resign threshold in this
+    /// position; callers count these in a row to pass back as `resign_streak`
+    #[serde(default)]
+    pub below_resign_threshold: bool,
+    /// Win rate from Black's perspective (0.0 to 1.0)
+    pub win_rate: f32,
+    /// Score lead from Black's perspective
+    pub score_lead: f32,
+}
+
+/// Quality of a played move, judged by how much win rate it lost
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MoveGrade {
+    /// The engine's top move
+    Best,
+    Good,
+    Inaccuracy,
+    Mistake,
+    Blunder,
+}
+
+impl MoveGrade {
+    /// Grade a move from the win rate it lost for the player who made it
+    pub fn from_win_rate_loss(loss: f32, options: &AnalysisOptions) -> Self {
+        if loss >= options.blunder_threshold {
+            MoveGrade::Blunder
+        } else if loss >= options.mistake_threshold {
+            MoveGrade::Mistake
+        } else if loss >= options.inaccuracy_threshold {
+            MoveGrade::Inaccuracy
+        } else {
+            MoveGrade::Good
+        }
+    }
+}
+
+/// A move graded by [`grade_move`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GradedMove {
+    pub grade: MoveGrade,
+    /// Change in the mover's win rate (negative = the move lost win rate)
+    pub win_rate_delta: f32,
+    /// Change in the mover's score lead, in points
+    pub score_lead_delta: f32,
+    /// The engine's top move in the position before ("D4", "PASS")
+    pub best_move: String,
+}
+
+/// A game played by [`selfplay`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SelfPlayGame {
+    /// Moves played, in order (passes as x = y = -1)
+    pub moves: Vec<HistoryMove>,
+    pub final_board: Vec<Vec<i8>>,
+    /// Win rate of the final position, from Black's perspective
+    pub win_rate: f32,
+    /// Score lead of the final position, from Black's perspective
+    pub score_lead: f32,
+    /// Whether the game ended with two consecutive passes rather than at the move limit
+    pub finished: bool,
+}
+
+/// Consecutive evaluations below the resign threshold, this one included, before genmove resigns
+const RESIGN_STREAK: usize = 3;
+
+/// Win rate beyond which (or below one minus which) genmove treats the game as decided
+const GENMOVE_DECIDED_WIN_RATE: f32 = 0.95;
+
+/// The model file behind the active engine
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoadedModel {
+    /// Cache ID, if the model was loaded from the app cache
+    pub id: Option<String>,
+    pub path: String,
+    /// Hex-encoded SHA-256 of the file, if known
+    pub sha256: Option<String>,
+    /// Companion config the model was loaded with, see [`read_model_config`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config: Option<ModelConfig>,
+}
+
+/// Companion config JSON shipped alongside some models
+/// Only the fields below are read; unknown keys are ignored
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelConfig {
+    /// Divisor applied to komi for the network's komi input, overrides the model's metadata
+    #[serde(alias = "komi_scale")]
+    pub komi_scale: Option<f32>,
+    /// Spatial input channels the model expects
+    #[serde(alias = "num_bin_channels")]
+    pub num_bin_channels: Option<u32>,
+    /// Global input features the model expects
+    #[serde(alias = "num_global_features")]
+    pub num_global_features: Option<u32>,
+    /// Ruleset the model was trained for, for the frontend to suggest as the default
+    pub rules: Option<Rules>,
+}
+
+/// Spatial input channels and global input features built by the featurizer
+const NUM_BIN_CHANNELS: u32 = 22;
+const NUM_GLOBAL_FEATURES: u32 = 19;
+
+/// Read and validate a model's companion config JSON
+/// Fails if it describes an input layout the featurizer can't produce
+pub fn read_model_config(path: &Path) -> Result<ModelConfig, String> {
+    let json = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read model config {:?}: {}", path, e))?;
+    let config: ModelConfig = serde_json::from_str(&json)
+        .map_err(|e| format!("Invalid model config {:?}: {}", path, e))?;
+    if let Some(scale) = config.komi_scale.filter(|s| !s.is_finite() || *s <= 0.0) {
+        return Err(format!("Invalid komi scale in model config: {}", scale));
+    }
+    if let Some(channels) = config.num_bin_channels.filter(|&c| c != NUM_BIN_CHANNELS) {
+        return Err(format!(
+            "Model config expects {} input channels, only {} are supported",
+            channels, NUM_BIN_CHANNELS
+        ));
+    }
+    if let Some(features) = config
+        .num_global_features
+        .filter(|&f| f != NUM_GLOBAL_FEATURES)
+    {
+        return Err(format!(
+            "Model config expects {} global features, only {} are supported",
+            features, NUM_GLOBAL_FEATURES
+        ));
+    }
+    Ok(config)
+}
+
+/// Graph signature of a model file, read without creating a session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelMetadata {
+    pub input_names: Vec<String>,
+    pub output_names: Vec<String>,
+    /// None if the model accepts any board size (symbolic spatial dimensions)
+    pub expected_board_size: Option<u32>,
+    pub num_bin_channels: u32,
+    pub num_global_features: u32,
+    /// Whether the model is int8 quantized, see [`is_quantized_model`]
+    pub is_quantized: bool,
+    /// Komi scale recorded in the model's metadata, see [`model_komi_scale`]
+    pub komi_scale: Option<f32>,
+}
+
+/// Analysis result for a board position
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnalysisResult {
+    /// Top move suggestions
+    pub move_suggestions: Vec<MoveSuggestion>,
+    /// Win rate (0.0 to 1.0) from the perspective chosen by `AnalysisOptions::orient_to`,
+    /// the side to move by default
+    pub win_rate: f32,
+    /// Probability of a draw / no result (0.0 to 1.0), the same for both players
+    pub draw_rate: f32,
+    /// Score lead from the same perspective as `win_rate` (positive = that side ahead)
+    pub score_lead: f32,
+    /// Current turn ('B' or 'W')
+    pub current_turn: String,
+    /// Ownership map (size*size, values -1 to 1 from the same perspective as `win_rate`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ownership: Option<Vec<f32>>,
+    /// Time spent in the ONNX Runtime `run` call, in milliseconds
+    /// (for batches, the total divided evenly across results)
+    pub inference_ms: f64,
+    /// Time spent building the input tensors, symmetries included, in milliseconds
+    /// (for batches, the total for the whole batch)
+    #[serde(default)]
+    pub featurize_ms: f64,
+    /// Time spent decoding the outputs, symmetry averaging included, in milliseconds
+    /// (for batches, the total for the whole batch)
+    #[serde(default)]
+    pub postprocess_ms: f64,
+    /// Number of positions in the inference call that produced this result
+    pub batch_size: usize,
+    /// Whether the model produced NaN or infinite outputs, which were replaced
+    /// with safe values; results from such a model should not be trusted
+    #[serde(default)]
+    pub had_invalid_outputs: bool,
+    /// Expected error of the win rate over the next few moves (0.0 to 1.0),
+    /// high values mark unclear positions
+    #[serde(default)]
+    pub shortterm_value_error: f32,
+    /// Expected error of the score lead over the next few moves, in points
+    #[serde(default)]
+    pub shortterm_score_error: f32,
+    /// Whether the model has the short-term error head; both errors are 0 otherwise
+    #[serde(default)]
+    pub has_shortterm_errors: bool,
+    /// Win rate blended with the score lead where the win rate is saturated, from the
+    /// same perspective as `win_rate`; see [`score_to_winrate`]
+    /// Keeps varying in decided positions, where `win_rate` is stuck near 0 or 1
+    #[serde(default)]
+    pub effective_winrate: f32,
+    /// Number of board symmetries averaged into this result
+    /// With `time_budget_ms` set, the number that fit in the budget
+    #[serde(default = "default_symmetries")]
+    pub symmetries_used: usize,
+    /// Probability of every move, row-major with pass last and illegal moves at 0
+    /// Only filled in with `include_policy`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy: Option<Vec<f32>>,
+}
+
+impl AnalysisResult {
+    /// Re-orient win rate, score lead and ownership from Black's perspective to
+    /// `orient_to`'s: 1 for Black, -1 for White, 0 or None for the side to move
+    fn orient(&mut self, orient_to: Option<i8>) -> Result<(), OnnxError> {
+        validate_orient_to(orient_to)?;
+        let player = if self.current_turn == "B" { 1 } else { -1 };
+        if !flips_black_view(orient_to, player) {
+            return Ok(());
+        }
+        // Same flip as for the side to move in process_batch_results
+        self.win_rate = 1.0 - self.win_rate;
+        self.effective_winrate = 1.0 - self.effective_winrate;
+        self.score_lead = -self.score_lead;
+        if let Some(ownership) = self.ownership.as_mut() {
+            ownership.iter_mut().for_each(|v| *v = -*v);
+        }
+        Ok(())
+    }
+}
+
+/// Whether values from Black's perspective must be flipped to report them from
+/// `orient_to`'s, in a position where `player` is to move
+fn flips_black_view(orient_to: Option<i8>, player: i8) -> bool {
+    match orient_to {
+        Some(1) => false,
+        Some(-1) => true,
+        _ => player == -1,
+    }
+}
+
+/// Check an `AnalysisOptions::orient_to` value, before any inference is spent on a
+/// request whose results could not be oriented
+fn validate_orient_to(orient_to: Option<i8>) -> Result<(), OnnxError> {
+    match orient_to {
+        None | Some(-1..=1) => Ok(()),
+        Some(other) => Err(OnnxError::InvalidBoard(format!(
+            "Invalid orientation: {} (expected 1, -1 or 0)",
+            other
+        ))),
+    }
+}
+
+/// History move entry
+///
+/// Coordinates are 0-based from the top-left corner, matching `sign_map[y][x]`.
+/// A pass is encoded as x = y = -1.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryMove {
+    /// Stone color: 1 = Black, -1 = White
+    pub color: i8,
+    /// Column (0-18 for 19x19, -1 for pass)
+    pub x: i32,
+    /// Row (0-18 for 19x19, -1 for pass)
+    pub y: i32,
+}
+
+impl HistoryMove {
+    /// Whether this move is a pass
+    pub fn is_pass(&self) -> bool {
+        self.x < 0 || self.y < 0
+    }
+}
+
+/// Number of recent moves fed to the network as history features
+const HISTORY_MOVES: usize = 5;
+
+/// Ruleset of the game
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Rules {
+    #[default]
+    Chinese,
+    Japanese,
+    Korean,
+    Aga,
+    TrompTaylor,
+}
+
+impl Rules {
+    /// Whether the ruleset counts territory (and prisoners) instead of area
+    pub fn uses_territory_scoring(self) -> bool {
+        matches!(self, Rules::Japanese | Rules::Korean)
+    }
+}
+
+/// Analysis options
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnalysisOptions {
+    /// Komi value (default: 7.5)
+    #[serde(default = "default_komi")]
+    pub komi: f32,
+    /// Next player to move ('B' or 'W')
+    pub next_to_play: Option<String>,
+    /// Moves played so far, oldest first; only the last 5 are used as history features
+    #[serde(default)]
+    pub history: Vec<HistoryMove>,
+    /// Point the side to move may not play at because of simple ko, as (x, y)
+    /// Filled in when moves are replayed on a [`GoBoard`]; it is featurized and
+    /// respected by the legality checks
+    #[serde(default)]
+    pub ko_point: Option<(i32, i32)>,
+    /// Ruleset (default: Chinese)
+    #[serde(default)]
+    pub rules: Rules,
+    /// Number of board symmetries to average over, 1 to 8 (default: 1)
+    /// Only single-position analysis uses more than one symmetry
+    #[serde(default = "default_symmetries")]
+    pub symmetries: usize,
+    /// Time budget for single-position analysis in milliseconds, replacing `symmetries`
+    /// Symmetries run one at a time, up to 8, until the next one would overrun the
+    /// budget; at least one always runs, even past the budget
+    #[serde(default)]
+    pub time_budget_ms: Option<u32>,
+    /// Reproducible analysis: runs on a dedicated single-threaded CPU session with
+    /// deterministic kernels and only the identity symmetry, regardless of the
+    /// provider preference. Much slower, but identical inputs give bit-identical outputs.
+    #[serde(default)]
+    pub deterministic: bool,
+    /// Policy probability pass needs before genmove plays it in a decided game (default: 0.5)
+    /// Outside decided games genmove only passes when no legal move is left
+    #[serde(default = "default_pass_threshold")]
+    pub pass_threshold: f32,
+    /// Ownership magnitude above which a point counts as owned (default: 0.5)
+    /// Values near 1.0 only count very confident points, leaving more dame
+    #[serde(default = "default_ownership_threshold")]
+    pub ownership_threshold: f32,
+    /// Japanese-rules cleanup phase: 0 for normal play, 1 or 2 for the first or second encore
+    /// Only meaningful under territory rules
+    #[serde(default)]
+    pub encore_phase: u8,
+    /// Only decode move suggestions and win rate, for callers that don't need the rest
+    /// The model still runs every head, but the score lead is left at 0 and the
+    /// ownership map at None, which saves post-processing on large batches
+    #[serde(default)]
+    pub policy_only: bool,
+    /// Also return the probability of every move in [`AnalysisResult::policy`]
+    #[serde(default)]
+    pub include_policy: bool,
+    /// Divisor applied to komi for the network's komi input, None to use the value in the
+    /// model's metadata or else 20 (current KataGo nets)
+    /// Older nets scaled komi differently; a win rate that is off by a consistent amount
+    /// in even positions, whatever the komi, means this needs adjusting
+    #[serde(default)]
+    pub komi_scale: Option<f32>,
+    /// Win rate of the side to move (not Black) below which genmove may resign, None to never resign
+    /// A single noisy evaluation is not enough: genmove only resigns once the win rate has
+    /// stayed below the threshold for 3 of that side's moves in a row, see `resign_streak`
+    #[serde(default)]
+    pub resign_threshold: Option<f32>,
+    /// How many of the side to move's previous genmove calls in a row were below the resign
+    /// threshold, counted by the caller from `GenmoveResult::below_resign_threshold`
+    #[serde(default)]
+    pub resign_streak: usize,
+    /// Color whose perspective win rate, score lead and ownership are reported from:
+    /// 1 for Black, -1 for White, 0 for the side to move (default: the side to move)
+    /// A fixed color keeps a game's win-rate graph continuous across moves
+    #[serde(default)]
+    pub orient_to: Option<i8>,
+    /// Win rate a move must lose to be graded an inaccuracy (default: 0.05)
+    #[serde(default = "default_inaccuracy_threshold")]
+    pub inaccuracy_threshold: f32,
+    /// Win rate a move must lose to be graded a mistake (default: 0.1)
+    #[serde(default = "default_mistake_threshold")]
+    pub mistake_threshold: f32,
+    /// Win rate a move must lose to be graded a blunder (default: 0.2)
+    #[serde(default = "default_blunder_threshold")]
+    pub blunder_threshold: f32,
+    /// Scheduling priority when every session is busy, higher first (default: 0)
+    /// Give interactive requests a higher priority than background game analysis,
+    /// which also yields its session between sub-batches to outranking requests
+    #[serde(default)]
+    pub priority: u8,
+}
+
+fn default_komi() -> f32 {
+    7.5
+}
+
+fn default_pass_threshold() -> f32 {
+    0.5
+}
+
+fn default_ownership_threshold() -> f32 {
+    0.5
+}
+
+fn default_symmetries() -> usize {
+    1
+}
+
+fn default_inaccuracy_threshold() -> f32 {
+    0.05
+}
+
+fn default_mistake_threshold() -> f32 {
+    0.1
+}
+
+fn default_blunder_threshold() -> f32 {
+    0.2
+}
 
 impl Default for AnalysisOptions {
     fn default() -> Self {
@@ -288,744 +1526,5398 @@ impl Default for AnalysisOptions {
             komi: 7.5,
             next_to_play: None,
             history: vec![],
+            ko_point: None,
+            rules: Rules::default(),
+            symmetries: 1,
+            time_budget_ms: None,
+            deterministic: false,
+            pass_threshold: 0.5,
+            ownership_threshold: 0.5,
+            encore_phase: 0,
+            policy_only: false,
+            include_policy: false,
+            komi_scale: None,
+            resign_threshold: None,
+            resign_streak: 0,
+            orient_to: None,
+            inaccuracy_threshold: 0.05,
+            mistake_threshold: 0.1,
+            blunder_threshold: 0.2,
+            priority: 0,
+        }
+    }
+}
+
+/// Native ONNX engine state
+pub struct OnnxEngine {
+    session: Session,
+    board_size: usize,
+    /// The active execution provider name
+    provider_name: String,
+    /// Whether a fallback provider was used instead of the preferred one
+    fallback_used: bool,
+    /// Whether the model uses fp16 I/O tensors
+    is_fp16: bool,
+    /// Komi scale from the model's metadata, used when the options don't set one
+    komi_scale: Option<f32>,
+    /// Recently used feature templates, most recent last, see [`OnnxEngine::feature_template`]
+    feature_templates: Vec<Arc<FeatureTemplate>>,
+}
+
+/// Pool of sessions created from the same model
+///
+/// Each analysis request checks out an idle session, so independent
+/// requests run in parallel instead of queueing behind a single session.
+/// When every session is busy, waiting requests get the next free session
+/// highest priority first (see `AnalysisOptions::priority`).
+pub struct OnnxSessionPool {
+    state: Mutex<PoolState>,
+    available: Condvar,
+    provider_name: String,
+    fallback_used: bool,
+    is_quantized: bool,
+    /// GPU index the sessions were created with
+    device_index: u32,
+    /// Name of that GPU, queried once when the pool is created
+    device_name: Option<String>,
+    /// CoreML compute units the sessions were created with
+    coreml_compute_units: CoreMlComputeUnits,
+    /// Model file the sessions were loaded from, if any
+    model_path: Option<PathBuf>,
+    /// Cache ID and SHA-256 of that file, when the caller knows them
+    model_id: Option<String>,
+    model_sha256: Option<String>,
+    /// Best batch size found by auto-tuning (0 = not tuned yet)
+    optimal_batch_size: AtomicUsize,
+    /// Single-threaded CPU session for deterministic analysis, created on first use
+    deterministic: Mutex<Option<OnnxEngine>>,
+    /// Komi scale recorded in the model's metadata, see [`model_komi_scale`]
+    komi_scale: Option<f32>,
+    /// Board size the model is fixed to, None if it accepts any, see [`model_board_size`]
+    board_size: Option<u32>,
+    /// Companion config the model was loaded with
+    config: Option<ModelConfig>,
+}
+
+/// Sessions of a pool and the requests waiting for one
+struct PoolState<E = OnnxEngine> {
+    idle: Vec<E>,
+    /// Priority of each request blocked in [`OnnxSessionPool::checkout_with_priority`]
+    waiting: Vec<u8>,
+}
+
+impl<E> PoolState<E> {
+    /// One check by a waiting request: Cancelled once `cancelled`, an idle session once
+    /// no higher priority is waiting, otherwise None to keep waiting
+    /// A cancelled request never takes a session, so cancelling can't shrink the pool
+    fn poll(&mut self, priority: u8, cancelled: bool) -> Result<Option<E>, OnnxError> {
+        if cancelled {
+            self.stop_waiting(priority);
+            return Err(OnnxError::Cancelled);
+        }
+        if self.waiting.iter().any(|&p| p > priority) {
+            return Ok(None);
+        }
+        let engine = self.idle.pop();
+        if engine.is_some() {
+            self.stop_waiting(priority);
+        }
+        Ok(engine)
+    }
+
+    fn stop_waiting(&mut self, priority: u8) {
+        if let Some(i) = self.waiting.iter().position(|&p| p == priority) {
+            self.waiting.swap_remove(i);
         }
     }
 }
 
-/// Native ONNX engine state
-pub struct OnnxEngine {
-    session: Session,
-    board_size: usize,
-    /// The active execution provider name
-    provider_name: String,
-    /// Whether the model uses fp16 I/O tensors
-    is_fp16: bool,
+/// An engine borrowed for one request
+pub enum EngineGuard<'a> {
+    Pooled(PooledEngine<'a>),
+    Deterministic(MutexGuard<'a, Option<OnnxEngine>>),
+}
+
+/// A session checked out of the pool, returned when dropped
+pub struct PooledEngine<'a> {
+    pool: &'a OnnxSessionPool,
+    engine: Option<OnnxEngine>,
+}
+
+impl OnnxSessionPool {
+    /// Create a pool of `count` sessions from a model file
+    /// When `cache_optimized` is set, the optimized graph is persisted next to the model
+    pub fn from_path(
+        model_path: &Path,
+        count: usize,
+        cache_optimized: bool,
+    ) -> Result<Self, String> {
+        let info = crate::onnx_proto::read_model_info(model_path);
+        // ORT can't serialize optimized graphs of models too big for a single protobuf
+        let has_external_data = info
+            .as_ref()
+            .is_ok_and(|info| !info.external_data.is_empty());
+        if cache_optimized && has_external_data {
+            log::info!("[OnnxEngine] Model uses external data, not caching the optimized graph");
+        }
+        let cache_optimized = cache_optimized && !has_external_data;
+        let engines = (0..count.max(1))
+            .map(|_| {
+                if cache_optimized {
+                    OnnxEngine::new_cached(model_path)
+                } else {
+                    OnnxEngine::new(model_path)
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let komi_scale = info.as_ref().ok().and_then(model_komi_scale);
+        let board_size = info.as_ref().ok().and_then(model_board_size);
+        let is_quantized = detect_quantization(info);
+        Ok(Self::with_engines(
+            engines,
+            Some(model_path.to_path_buf()),
+            is_quantized,
+            komi_scale,
+            board_size,
+        ))
+    }
+
+    /// Create a pool of `count` sessions from model bytes
+    pub fn from_bytes(model_bytes: &[u8], count: usize) -> Result<Self, String> {
+        let info = crate::onnx_proto::read_model_info_from_bytes(model_bytes);
+        // External data is resolved relative to the model file, which bytes don't have
+        if info
+            .as_ref()
+            .is_ok_and(|info| !info.external_data.is_empty())
+        {
+            return Err("Model stores its weights in external data files and must be loaded from a file path".to_string());
+        }
+        let engines = (0..count.max(1))
+            .map(|_| OnnxEngine::from_bytes(model_bytes))
+            .collect::<Result<Vec<_>, _>>()?;
+        let komi_scale = info.as_ref().ok().and_then(model_komi_scale);
+        let board_size = info.as_ref().ok().and_then(model_board_size);
+        let is_quantized = detect_quantization(info);
+        Ok(Self::with_engines(
+            engines,
+            None,
+            is_quantized,
+            komi_scale,
+            board_size,
+        ))
+    }
+
+    fn with_engines(
+        mut engines: Vec<OnnxEngine>,
+        model_path: Option<PathBuf>,
+        is_quantized: bool,
+        komi_scale: Option<f32>,
+        board_size: Option<u32>,
+    ) -> Self {
+        for engine in &mut engines {
+            engine.komi_scale = komi_scale;
+        }
+        let provider_name = engines[0].get_provider_name().to_string();
+        let fallback_used = engines.iter().any(|e| e.fallback_used);
+        let settings = get_provider_settings();
+        let device_index = settings.device_index;
+        let device_name = match provider_name.as_str() {
+            "cuda" | "tensorrt" => {
+                nvidia_device_names().and_then(|names| names.into_iter().nth(device_index as usize))
+            }
+            _ => None,
+        };
+        log::info!(
+            "[OnnxEngine] Created session pool with {} session(s)",
+            engines.len()
+        );
+        if let Some(size) = board_size {
+            log::info!("[OnnxEngine] Model only supports {}x{} boards", size, size);
+        }
+        Self {
+            state: Mutex::new(PoolState {
+                idle: engines,
+                waiting: Vec::new(),
+            }),
+            available: Condvar::new(),
+            provider_name,
+            fallback_used,
+            is_quantized,
+            device_index,
+            device_name,
+            coreml_compute_units: settings.coreml_compute_units,
+            model_path,
+            model_id: None,
+            model_sha256: None,
+            optimal_batch_size: AtomicUsize::new(0),
+            deterministic: Mutex::new(None),
+            komi_scale,
+            board_size,
+            config: None,
+        }
+    }
+
+    /// Apply a companion config, whose komi scale takes precedence over the model's metadata
+    fn apply_config(&mut self, config: ModelConfig) {
+        if let Some(komi_scale) = config.komi_scale {
+            log::info!(
+                "[OnnxEngine] Using komi scale {} from the model config",
+                komi_scale
+            );
+            self.komi_scale = Some(komi_scale);
+            for engine in self.state.get_mut().unwrap().idle.iter_mut() {
+                engine.komi_scale = Some(komi_scale);
+            }
+        }
+        self.config = Some(config);
+    }
+
+    /// Check that the model supports `size`x`size` boards, before featurizing for it
+    fn check_board_size(&self, size: usize) -> Result<(), OnnxError> {
+        match self.board_size {
+            Some(expected) if expected as usize != size => Err(OnnxError::ShapeMismatch(format!(
+                "The loaded model only supports {}x{} boards, got {}x{}",
+                expected, expected, size, size
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Check out a pooled session, or the deterministic session when `deterministic` is set
+    /// The deterministic session is loaded from the model file on first use; requests
+    /// for it are served in arrival order whatever their `priority`
+    pub fn checkout_for(
+        &self,
+        deterministic: bool,
+        priority: u8,
+    ) -> Result<EngineGuard<'_>, OnnxError> {
+        self.checkout_cancellable(deterministic, priority, None)
+    }
+
+    /// [`checkout_for`](Self::checkout_for) that gives up with [`OnnxError::Cancelled`]
+    /// once `cancel` is triggered while waiting for a pooled session
+    pub fn checkout_cancellable(
+        &self,
+        deterministic: bool,
+        priority: u8,
+        cancel: Option<&CancelToken>,
+    ) -> Result<EngineGuard<'_>, OnnxError> {
+        if !deterministic {
+            return Ok(EngineGuard::Pooled(
+                self.wait_for_session(priority, cancel)?,
+            ));
+        }
+        let mut engine = self.deterministic.lock().unwrap();
+        if engine.is_none() {
+            let model_path = self.model_path.as_ref().ok_or_else(|| {
+                OnnxError::Inference(
+                    "Deterministic analysis needs a model loaded from a file".to_string(),
+                )
+            })?;
+            let mut deterministic = OnnxEngine::new_deterministic(model_path)?;
+            deterministic.komi_scale = self.komi_scale;
+            *engine = Some(deterministic);
+        }
+        Ok(EngineGuard::Deterministic(engine))
+    }
+
+    /// Check out an idle session at the lowest priority, blocking until one becomes available
+    pub fn checkout(&self) -> PooledEngine<'_> {
+        self.checkout_with_priority(0)
+    }
+
+    /// Check out an idle session, blocking until one is available and no waiting
+    /// request has a higher priority
+    pub fn checkout_with_priority(&self, priority: u8) -> PooledEngine<'_> {
+        match self.wait_for_session(priority, None) {
+            Ok(engine) => engine,
+            Err(_) => unreachable!("only cancellable waits fail"),
+        }
+    }
+
+    /// Wait for an idle session as in [`checkout_with_priority`](Self::checkout_with_priority),
+    /// checking `cancel` every [`CANCEL_POLL_INTERVAL`] while blocked
+    fn wait_for_session(
+        &self,
+        priority: u8,
+        cancel: Option<&CancelToken>,
+    ) -> Result<PooledEngine<'_>, OnnxError> {
+        let mut state = self.state.lock().unwrap();
+        state.waiting.push(priority);
+        loop {
+            let cancelled = cancel.is_some_and(|cancel| cancel.check().is_err());
+            match state.poll(priority, cancelled) {
+                Ok(Some(engine)) => {
+                    return Ok(PooledEngine {
+                        pool: self,
+                        engine: Some(engine),
+                    })
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    drop(state);
+                    // Requests this one outranked may go ahead now
+                    self.available.notify_all();
+                    return Err(e);
+                }
+            }
+            state = match cancel {
+                Some(_) => {
+                    self.available
+                        .wait_timeout(state, CANCEL_POLL_INTERVAL)
+                        .unwrap()
+                        .0
+                }
+                None => self.available.wait(state).unwrap(),
+            };
+        }
+    }
+
+    /// Whether a request with a higher priority than `priority` is waiting for a session
+    pub fn is_outranked(&self, priority: u8) -> bool {
+        self.state
+            .lock()
+            .unwrap()
+            .waiting
+            .iter()
+            .any(|&p| p > priority)
+    }
+
+    /// Number of requests waiting for a session
+    pub fn queue_depth(&self) -> usize {
+        self.state.lock().unwrap().waiting.len()
+    }
+
+    /// Run a dummy `board_size` inference on every session so kernels are
+    /// allocated before the first real analysis. Failures are logged, not returned.
+    pub fn warm_up(&self, board_size: usize) {
+        let mut state = self.state.lock().unwrap();
+        for engine in state.idle.iter_mut() {
+            if let Err(e) = engine.warm_up(board_size) {
+                log::warn!("[OnnxEngine] Warm-up inference failed: {}", e);
+            }
+        }
+    }
+
+    /// Get the name of the execution provider used by the pool
+    pub fn get_provider_name(&self) -> &str {
+        &self.provider_name
+    }
+}
+
+impl Deref for PooledEngine<'_> {
+    type Target = OnnxEngine;
+
+    fn deref(&self) -> &OnnxEngine {
+        self.engine
+            .as_ref()
+            .expect("pooled engine already returned")
+    }
+}
+
+impl DerefMut for PooledEngine<'_> {
+    fn deref_mut(&mut self) -> &mut OnnxEngine {
+        self.engine
+            .as_mut()
+            .expect("pooled engine already returned")
+    }
+}
+
+impl Deref for EngineGuard<'_> {
+    type Target = OnnxEngine;
+
+    fn deref(&self) -> &OnnxEngine {
+        match self {
+            EngineGuard::Pooled(engine) => engine,
+            EngineGuard::Deterministic(engine) => {
+                engine.as_ref().expect("deterministic engine not created")
+            }
+        }
+    }
+}
+
+impl DerefMut for EngineGuard<'_> {
+    fn deref_mut(&mut self) -> &mut OnnxEngine {
+        match self {
+            EngineGuard::Pooled(engine) => engine,
+            EngineGuard::Deterministic(engine) => {
+                engine.as_mut().expect("deterministic engine not created")
+            }
+        }
+    }
+}
+
+impl Drop for PooledEngine<'_> {
+    fn drop(&mut self) {
+        if let Some(engine) = self.engine.take() {
+            self.pool.state.lock().unwrap().idle.push(engine);
+            // Every waiter re-checks whether it is outranked, so wake them all
+            self.pool.available.notify_all();
+        }
+    }
+}
+
+/// Operators that only appear in int8 quantized graphs, in QDQ
+/// (QuantizeLinear/DequantizeLinear pairs) or QOperator (QLinear*, *Integer) form
+const QUANTIZED_OP_TYPES: [&str; 8] = [
+    "QuantizeLinear",
+    "DequantizeLinear",
+    "DynamicQuantizeLinear",
+    "QLinearConv",
+    "QLinearMatMul",
+    "QLinearAdd",
+    "ConvInteger",
+    "MatMulInteger",
+];
+
+/// Whether a model is int8 quantized
+///
+/// Quantization is internal to the graph: inputs and outputs stay f32, so
+/// featurization and output processing are unchanged. Expect slightly
+/// noisier policy and win rates than the f32 net, in exchange for faster
+/// CPU inference and a smaller download.
+pub fn is_quantized_model(info: &crate::onnx_proto::ModelInfo) -> bool {
+    info.op_types
+        .iter()
+        .any(|op| QUANTIZED_OP_TYPES.contains(&op.as_str()))
+}
+
+/// Divisor applied to komi for the komi global input, as in current KataGo nets
+const DEFAULT_KOMI_SCALE: f32 = 20.0;
+
+/// Metadata key exporters can use to record the komi scale a net was trained with
+const KOMI_SCALE_METADATA_KEY: &str = "komi_scale";
+
+/// Komi scale recorded in a model's metadata, if any
+pub fn model_komi_scale(info: &crate::onnx_proto::ModelInfo) -> Option<f32> {
+    info.metadata_props
+        .iter()
+        .find(|(key, _)| key == KOMI_SCALE_METADATA_KEY)
+        .and_then(|(_, value)| value.trim().parse::<f32>().ok())
+        .filter(|scale| scale.is_finite() && *scale > 0.0)
+}
+
+/// Board size a model's spatial input is fixed to, None if it accepts any size
+/// (symbolic height and width, as in most KataGo exports)
+pub fn model_board_size(info: &crate::onnx_proto::ModelInfo) -> Option<u32> {
+    let bin_input = info.inputs.iter().find(|input| input.shape.len() == 4)?;
+    match (bin_input.shape[2], bin_input.shape[3]) {
+        (Some(h), Some(w)) if h == w => Some(h as u32),
+        _ => None,
+    }
+}
+
+/// Detect quantization when creating a pool, treating an unreadable graph as not quantized
+fn detect_quantization(info: Result<crate::onnx_proto::ModelInfo, String>) -> bool {
+    match info {
+        Ok(info) => {
+            let is_quantized = is_quantized_model(&info);
+            log::info!("[OnnxEngine] Detected quantized model: {}", is_quantized);
+            is_quantized
+        }
+        Err(e) => {
+            log::warn!(
+                "[OnnxEngine] Failed to inspect model for quantization: {}",
+                e
+            );
+            false
+        }
+    }
+}
+
+/// Cumulative counters for diagnosing slow analysis, see [`get_stats`]
+struct Counters {
+    inferences: AtomicU64,
+    positions: AtomicU64,
+    inference_us: AtomicU64,
+    cache_hits: AtomicU64,
+    cache_misses: AtomicU64,
+    failures: AtomicU64,
+}
+
+static STATS: Counters = Counters {
+    inferences: AtomicU64::new(0),
+    positions: AtomicU64::new(0),
+    inference_us: AtomicU64::new(0),
+    cache_hits: AtomicU64::new(0),
+    cache_misses: AtomicU64::new(0),
+    failures: AtomicU64::new(0),
+};
+
+/// Analysis counters since startup or the last [`reset_stats`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EngineStats {
+    /// Successful ONNX Runtime `run` calls
+    pub inferences: u64,
+    /// Positions evaluated by those calls, symmetries included
+    pub positions: u64,
+    /// Time spent in those calls, in milliseconds
+    pub total_inference_ms: f64,
+    /// Average time per call, in milliseconds
+    pub average_inference_ms: f64,
+    /// Single-position analyses answered from the analysis cache
+    pub cache_hits: u64,
+    /// Single-position analyses that missed the cache (not counted while it's disabled)
+    pub cache_misses: u64,
+    /// `run` calls that failed
+    pub failures: u64,
+}
+
+/// Get the analysis counters
+pub fn get_stats() -> EngineStats {
+    let inferences = STATS.inferences.load(Ordering::Relaxed);
+    let total_inference_ms = STATS.inference_us.load(Ordering::Relaxed) as f64 / 1000.0;
+    EngineStats {
+        inferences,
+        positions: STATS.positions.load(Ordering::Relaxed),
+        total_inference_ms,
+        average_inference_ms: if inferences == 0 {
+            0.0
+        } else {
+            total_inference_ms / inferences as f64
+        },
+        cache_hits: STATS.cache_hits.load(Ordering::Relaxed),
+        cache_misses: STATS.cache_misses.load(Ordering::Relaxed),
+        failures: STATS.failures.load(Ordering::Relaxed),
+    }
+}
+
+/// Reset the analysis counters to zero
+pub fn reset_stats() {
+    for counter in [
+        &STATS.inferences,
+        &STATS.positions,
+        &STATS.inference_us,
+        &STATS.cache_hits,
+        &STATS.cache_misses,
+        &STATS.failures,
+    ] {
+        counter.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Global session pool (lazy loaded)
+static ENGINE: RwLock<Option<Arc<OnnxSessionPool>>> = RwLock::new(None);
+
+/// Session pool loaded in the background by [`preload_engine_from_path`], not yet active
+static STANDBY_ENGINE: Mutex<Option<OnnxSessionPool>> = Mutex::new(None);
+
+/// Get a handle to the active session pool
+fn active_pool() -> Result<Arc<OnnxSessionPool>, OnnxError> {
+    let global = ENGINE
+        .read()
+        .map_err(|e| OnnxError::Inference(e.to_string()))?;
+    global.clone().ok_or(OnnxError::NotInitialized)
+}
+
+impl OnnxEngine {
+    /// Create a new ONNX engine from a model file
+    pub fn new(model_path: &Path) -> Result<Self, String> {
+        // Ensure ONNX Runtime is initialized (required for load-dynamic on Android)
+        ensure_ort_initialized()?;
+
+        let (session, provider, fallback_used) =
+            build_session_with_fallback(&get_provider_settings(), |builder| {
+                builder
+                    .commit_from_file(model_path)
+                    .map_err(|e| format!("Failed to load model from {:?}: {}", model_path, e))
+            })?;
+
+        Ok(Self::with_session(session, provider, fallback_used))
+    }
+
+    /// Create a single-threaded CPU engine with deterministic kernels, for reproducible analysis
+    fn new_deterministic(model_path: &Path) -> Result<Self, String> {
+        ensure_ort_initialized()?;
+
+        let settings = ProviderSettings {
+            preference: ExecutionProviderPreference::Cpu,
+            intra_op_threads: 1,
+            inter_op_threads: 1,
+            ..get_provider_settings()
+        };
+        let (session, provider, fallback_used) =
+            build_session_with_fallback(&settings, |builder| {
+                builder
+                    .with_deterministic_compute(true)
+                    .map_err(|e| format!("Failed to enable deterministic compute: {}", e))?
+                    .commit_from_file(model_path)
+                    .map_err(|e| format!("Failed to load model from {:?}: {}", model_path, e))
+            })?;
+
+        Ok(Self::with_session(session, provider, fallback_used))
+    }
+
+    /// Create a new ONNX engine from a model file, reusing the optimized graph
+    /// cached next to it when it was built for the same provider and settings
+    pub fn new_cached(model_path: &Path) -> Result<Self, String> {
+        // Ensure ONNX Runtime is initialized (required for load-dynamic on Android)
+        ensure_ort_initialized()?;
+
+        let settings = get_provider_settings();
+        let opt_path = optimized_model_path(model_path);
+
+        if let Some(stamp) = read_optimized_stamp(model_path) {
+            if stamp.optimization_level == settings.optimization_level.name() {
+                let loaded = build_session_with_fallback(&settings, |builder| {
+                    builder.commit_from_file(&opt_path).map_err(|e| {
+                        format!("Failed to load optimized model from {:?}: {}", opt_path, e)
+                    })
+                });
+                match loaded {
+                    Ok((session, provider, fallback_used))
+                        if preference_to_name(provider) == stamp.provider =>
+                    {
+                        log::info!("[OnnxEngine] Loaded optimized model from {:?}", opt_path);
+                        return Ok(Self::with_session(session, provider, fallback_used));
+                    }
+                    Ok((_, provider, _)) => log::info!(
+                        "[OnnxEngine] Optimized model was built for {}, rebuilding for {}",
+                        stamp.provider,
+                        preference_to_name(provider)
+                    ),
+                    Err(e) => log::info!("[OnnxEngine] Rebuilding optimized model: {}", e),
+                }
+            }
+            remove_optimized_model(model_path);
+        }
+
+        let (session, provider, fallback_used) =
+            build_session_with_fallback(&settings, |builder| {
+                builder
+                    .with_optimized_model_path(&opt_path)
+                    .map_err(|e| format!("Failed to set optimized model path: {}", e))?
+                    .commit_from_file(model_path)
+                    .map_err(|e| format!("Failed to load model from {:?}: {}", model_path, e))
+            })?;
+
+        let stamp = OptimizedModelStamp {
+            provider: preference_to_name(provider),
+            optimization_level: settings.optimization_level.name().to_string(),
+        };
+        let written = serde_json::to_string_pretty(&stamp)
+            .map_err(|e| e.to_string())
+            .and_then(|json| {
+                std::fs::write(optimized_stamp_path(model_path), json).map_err(|e| e.to_string())
+            });
+        if let Err(e) = written {
+            log::warn!("[OnnxEngine] Failed to write optimized model stamp: {}", e);
+        }
+
+        Ok(Self::with_session(session, provider, fallback_used))
+    }
+
+    /// Create a new ONNX engine from model bytes
+    pub fn from_bytes(model_bytes: &[u8]) -> Result<Self, String> {
+        // Ensure ONNX Runtime is initialized (required for load-dynamic on Android)
+        ensure_ort_initialized()?;
+
+        let (session, provider, fallback_used) =
+            build_session_with_fallback(&get_provider_settings(), |builder| {
+                builder
+                    .commit_from_memory(model_bytes)
+                    .map_err(|e| format!("Failed to load model from bytes: {}", e))
+            })?;
+
+        Ok(Self::with_session(session, provider, fallback_used))
+    }
+
+    fn with_session(
+        session: Session,
+        provider: ExecutionProviderPreference,
+        fallback_used: bool,
+    ) -> Self {
+        // Detect if model uses fp16 inputs by checking first input's type
+        let is_fp16 = session.inputs.first().map_or(false, |input| {
+            let type_str = format!("{:?}", input.input_type);
+            log::debug!("[OnnxEngine] Input type: {}", type_str);
+            type_str.contains("Float16") || type_str.contains("float16") || type_str.contains("f16")
+        });
+        log::info!("[OnnxEngine] Detected fp16 model: {}", is_fp16);
+
+        Self {
+            session,
+            board_size: 19,
+            provider_name: preference_to_name(provider),
+            fallback_used,
+            is_fp16,
+            komi_scale: None,
+            feature_templates: Vec::new(),
+        }
+    }
+
+    /// Get the name of the active execution provider
+    pub fn get_provider_name(&self) -> &str {
+        &self.provider_name
+    }
+
+    /// Run one dummy inference on an empty board, discarding the result
+    fn warm_up(&mut self, board_size: usize) -> Result<(), String> {
+        let sign_map = vec![vec![0i8; board_size]; board_size];
+        self.analyze(&sign_map, &AnalysisOptions::default())?;
+        Ok(())
+    }
+
+    /// Measure single-position and batch-8 latency over `iterations` runs
+    fn benchmark(
+        &mut self,
+        iterations: usize,
+        batch_sizes: &[usize],
+    ) -> Result<BenchmarkResult, String> {
+        let sign_map = vec![vec![0i8; 19]; 19];
+        let options = AnalysisOptions::default();
+
+        // Warm up the single shape so allocation is not measured
+        self.analyze(&sign_map, &options)?;
+        let single_ms = median_ms(iterations, || {
+            self.analyze(&sign_map, &options)?;
+            Ok(())
+        })?;
+
+        let mut batches = Vec::with_capacity(batch_sizes.len());
+        for &batch_size in batch_sizes {
+            let batch: Vec<_> = (0..batch_size)
+                .map(|_| (sign_map.clone(), options.clone()))
+                .collect();
+            self.analyze_batch(&batch)?;
+            let median_ms = median_ms(iterations, || {
+                self.analyze_batch(&batch)?;
+                Ok(())
+            })?;
+            batches.push(BatchThroughput {
+                batch_size,
+                median_ms,
+                inf_s: batch_size as f64 * 1000.0 / median_ms,
+            });
+        }
+
+        Ok(BenchmarkResult { single_ms, batches })
+    }
+
+    /// Measure throughput for each of [`AUTOTUNE_BATCH_SIZES`]
+    /// The sweep stops at the first batch size that fails, e.g. GPU out of memory
+    fn autotune_batch(&mut self) -> Result<AutotuneResult, String> {
+        let sign_map = vec![vec![0i8; 19]; 19];
+        let options = AnalysisOptions::default();
+        let mut results = Vec::new();
+
+        for batch_size in AUTOTUNE_BATCH_SIZES {
+            let batch: Vec<_> = (0..batch_size)
+                .map(|_| (sign_map.clone(), options.clone()))
+                .collect();
+
+            // The first run allocates buffers for the new shape and is not timed
+            let measured = self
+                .analyze_batch(&batch)
+                .map_err(String::from)
+                .and_then(|_| {
+                    median_ms(AUTOTUNE_ITERATIONS, || {
+                        self.analyze_batch(&batch)?;
+                        Ok(())
+                    })
+                });
+            match measured {
+                Ok(median_ms) => results.push(BatchThroughput {
+                    batch_size,
+                    median_ms,
+                    inf_s: batch_size as f64 * 1000.0 / median_ms,
+                }),
+                Err(e) if !results.is_empty() => {
+                    log::warn!(
+                        "[OnnxEngine] Batch size {} failed, stopping sweep: {}",
+                        batch_size,
+                        e
+                    );
+                    break;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        let best_batch_size = results
+            .iter()
+            .max_by(|a, b| a.inf_s.total_cmp(&b.inf_s))
+            .map_or(1, |r| r.batch_size);
+        Ok(AutotuneResult {
+            best_batch_size,
+            results,
+        })
+    }
+
+    /// Analyze a single position
+    pub fn analyze(
+        &mut self,
+        sign_map: &[Vec<i8>],
+        options: &AnalysisOptions,
+    ) -> Result<AnalysisResult, OnnxError> {
+        let (results, next_pla, symmetries, featurize_ms) = self.infer(sign_map, options)?;
+
+        let postprocess_start = Instant::now();
+        let mut result = if symmetries == 1 {
+            self.process_results(&results, sign_map, next_pla, options)?
+        } else {
+            let averaged = average_symmetries(&results, symmetries, self.board_size);
+            self.process_results(&averaged, sign_map, next_pla, options)?
+        };
+        result.featurize_ms = featurize_ms;
+        result.postprocess_ms = postprocess_start.elapsed().as_secs_f64() * 1000.0;
+        result.symmetries_used = symmetries;
+        Ok(result)
+    }
+
+    /// Featurize a single position and run it through the network, one batch item per symmetry
+    /// Returns the raw outputs, the player to move, the number of symmetries and
+    /// the featurization time in milliseconds
+    fn infer(
+        &mut self,
+        sign_map: &[Vec<i8>],
+        options: &AnalysisOptions,
+    ) -> Result<(OnnxOutputs, i8, usize, f64), OnnxError> {
+        validate_sign_map(sign_map)?;
+        self.board_size = sign_map.len();
+        let next_pla = next_player(sign_map, options);
+
+        // Featurize
+        let start = Instant::now();
+        let (bin_input, global_input) = self.featurize(sign_map, next_pla, options);
+
+        if let (Some(budget_ms), false) = (options.time_budget_ms, options.deterministic) {
+            return self.infer_within_budget(&bin_input, &global_input, next_pla, budget_ms, start);
+        }
+
+        let symmetries = if options.deterministic {
+            1
+        } else {
+            options.symmetries.clamp(1, 8)
+        };
+
+        // Run every symmetry in one batch and average back in the original orientation
+        let (bin_batch, global_batch) = if symmetries == 1 {
+            (bin_input, global_input)
+        } else {
+            symmetry_batch(&bin_input, &global_input, 0..symmetries)
+        };
+        let featurize_ms = start.elapsed().as_secs_f64() * 1000.0;
+
+        let results = self.run_inference(&bin_batch, &global_batch, symmetries)?;
+        Ok((results, next_pla, symmetries, featurize_ms))
+    }
+
+    /// Run symmetries of a featurized position one at a time while the next one is
+    /// expected to finish within `budget_ms` of `start`, always running the first
+    /// Returns the same as [`Self::infer`], with the outputs batched in symmetry order
+    fn infer_within_budget(
+        &mut self,
+        bin_input: &Array4<f32>,
+        global_input: &Array2<f32>,
+        next_pla: i8,
+        budget_ms: u32,
+        start: Instant,
+    ) -> Result<(OnnxOutputs, i8, usize, f64), OnnxError> {
+        let budget = std::time::Duration::from_millis(u64::from(budget_ms));
+        let mut outputs = Vec::new();
+        let mut featurize_ms = start.elapsed().as_secs_f64() * 1000.0;
+        let mut slowest = std::time::Duration::ZERO;
+        for s in 0..8 {
+            if s > 0 && start.elapsed() + slowest > budget {
+                break;
+            }
+            let step_start = Instant::now();
+            let (bin_batch, global_batch) = symmetry_batch(bin_input, global_input, s..s + 1);
+            featurize_ms += step_start.elapsed().as_secs_f64() * 1000.0;
+            outputs.push(self.run_inference(&bin_batch, &global_batch, 1)?);
+            slowest = slowest.max(step_start.elapsed());
+        }
+        let symmetries = outputs.len();
+        log::debug!(
+            "[OnnxEngine] Ran {} symmetries in {:.1} ms of a {} ms budget",
+            symmetries,
+            start.elapsed().as_secs_f64() * 1000.0,
+            budget_ms
+        );
+        Ok((concat_outputs(outputs), next_pla, symmetries, featurize_ms))
+    }
+
+    /// Analyze multiple positions in a batch
+    /// Positions of different board sizes run as one batch per size, and the
+    /// results are returned in input order
+    pub fn analyze_batch(
+        &mut self,
+        inputs: &[(Vec<Vec<i8>>, AnalysisOptions)],
+    ) -> Result<Vec<AnalysisResult>, OnnxError> {
+        if inputs.is_empty() {
+            return Ok(vec![]);
+        }
+
+        for (i, (sign_map, _)) in inputs.iter().enumerate() {
+            validate_sign_map(sign_map)
+                .map_err(|e| OnnxError::InvalidBoard(format!("Position {}: {}", i, e)))?;
+        }
+
+        let first_size = inputs.first().map_or(0, |(sign_map, _)| sign_map.len());
+        if inputs
+            .iter()
+            .all(|(sign_map, _)| sign_map.len() == first_size)
+        {
+            return self.analyze_uniform_batch(inputs);
+        }
+        analyze_by_board_size(inputs, |group| self.analyze_uniform_batch(group))
+    }
+
+    /// Analyze validated positions sharing one board size, in windows of at most
+    /// [`get_max_batch_size`] positions so tensor memory doesn't grow with the input
+    fn analyze_uniform_batch(
+        &mut self,
+        inputs: &[(Vec<Vec<i8>>, AnalysisOptions)],
+    ) -> Result<Vec<AnalysisResult>, OnnxError> {
+        analyze_in_windows(inputs, get_max_batch_size(), |window| {
+            self.analyze_uniform_window(window)
+        })
+    }
+
+    /// Analyze one window of validated positions sharing one board size in a single inference call
+    fn analyze_uniform_window(
+        &mut self,
+        inputs: &[(Vec<Vec<i8>>, AnalysisOptions)],
+    ) -> Result<Vec<AnalysisResult>, OnnxError> {
+        let Some((first_map, first_options)) = inputs.first() else {
+            return Ok(vec![]);
+        };
+        self.board_size = first_map.len();
+        let size = self.board_size;
+        let batch_size = inputs.len();
+        let featurize_start = Instant::now();
+
+        // Prepare batch tensors
+        let mut bin_input = Array4::<f32>::zeros((batch_size, 22, size, size));
+        let mut global_input = Array2::<f32>::zeros((batch_size, 19));
+        let mut plas = Vec::with_capacity(batch_size);
+        let mut template = self.feature_template(size, first_options.rules);
+
+        for (b, (sign_map, options)) in inputs.iter().enumerate() {
+            let next_pla: i8 = match &options.next_to_play {
+                Some(s) if s == "W" => -1,
+                _ => 1,
+            };
+            plas.push(next_pla);
+
+            if template.rules != options.rules {
+                template = self.feature_template(size, options.rules);
+            }
+            // Featurize straight into the batch tensors
+            Self::featurize_into(
+                sign_map,
+                next_pla,
+                options,
+                self.komi_scale,
+                &template,
+                bin_input.index_axis_mut(Axis(0), b),
+                global_input.index_axis_mut(Axis(0), b),
+            );
+        }
+
+        let featurize_ms = featurize_start.elapsed().as_secs_f64() * 1000.0;
+
+        // Run batch inference
+        let results = self.run_inference(&bin_input, &global_input, batch_size)?;
+
+        // Process batch results
+        let postprocess_start = Instant::now();
+        let items: Vec<BatchItem> = inputs
+            .iter()
+            .zip(&plas)
+            .map(|((sign_map, options), &pla)| BatchItem {
+                sign_map,
+                pla,
+                options,
+            })
+            .collect();
+        let mut results = process_batch_results(size, &results, &items)?;
+        let postprocess_ms = postprocess_start.elapsed().as_secs_f64() * 1000.0;
+        for result in &mut results {
+            result.featurize_ms = featurize_ms;
+            result.postprocess_ms = postprocess_ms;
+        }
+        Ok(results)
+    }
+
+    /// Feature template for a board size and rules, cached per session so
+    /// concurrent requests on other sessions never contend for it
+    fn feature_template(&mut self, size: usize, rules: Rules) -> Arc<FeatureTemplate> {
+        cached_feature_template(&mut self.feature_templates, size, rules)
+    }
+
+    /// Featurize a board position into neural network inputs
+    fn featurize(
+        &mut self,
+        sign_map: &[Vec<i8>],
+        pla: i8,
+        options: &AnalysisOptions,
+    ) -> (Array4<f32>, Array2<f32>) {
+        let size = self.board_size;
+        let mut bin_input = Array4::<f32>::zeros((1, 22, size, size));
+        let mut global_input = Array2::<f32>::zeros((1, 19));
+        let template = self.feature_template(size, options.rules);
+        Self::featurize_into(
+            sign_map,
+            pla,
+            options,
+            self.komi_scale,
+            &template,
+            bin_input.index_axis_mut(Axis(0), 0),
+            global_input.index_axis_mut(Axis(0), 0),
+        );
+        (bin_input, global_input)
+    }
+
+    /// Featurize a board position into one batch entry, starting from the
+    /// static planes and globals of `template`
+    /// `model_komi_scale` is the model's own komi scale, used when the options don't set one
+    fn featurize_into(
+        sign_map: &[Vec<i8>],
+        pla: i8,
+        options: &AnalysisOptions,
+        model_komi_scale: Option<f32>,
+        template: &FeatureTemplate,
+        mut bin_input: ArrayViewMut3<f32>,
+        mut global_input: ArrayViewMut1<f32>,
+    ) {
+        let size = sign_map.len();
+        let history = &options.history;
+        let opp = -pla;
+
+        bin_input.assign(&template.bin);
+        global_input.assign(&template.global);
+
+        // Compute liberties for each group
+        let liberties = Self::compute_liberties(sign_map);
+
+        for y in 0..size {
+            for x in 0..size {
+                let color = sign_map[y][x];
+                if color == pla {
+                    bin_input[[1, y, x]] = 1.0;
+                } else if color == opp {
+                    bin_input[[2, y, x]] = 1.0;
+                }
+
+                if color != 0 {
+                    let libs = liberties[y][x];
+                    if libs == 1 {
+                        bin_input[[3, y, x]] = 1.0;
+                    }
+                    if libs == 2 {
+                        bin_input[[4, y, x]] = 1.0;
+                    }
+                    if libs == 3 {
+                        bin_input[[5, y, x]] = 1.0;
+                    }
+                }
+            }
+        }
+
+        // Ko-ban point (channel 6)
+        if let Some((x, y)) = options.ko_point {
+            if (x as usize) < size && (y as usize) < size {
+                bin_input[[6, y as usize, x as usize]] = 1.0;
+            }
+        }
+
+        // History features, most recent first: channels 9-13 mark the last 5 moves,
+        // global features 0-4 flag the ones that were passes. Like KataGo, history
+        // stops at the first move that breaks the alternation ending with the opponent,
+        // and anything older than 5 moves is ignored.
+        let mut expected_color = opp;
+        for (i, m) in history.iter().rev().take(HISTORY_MOVES).enumerate() {
+            if m.color != expected_color {
+                break;
+            }
+            expected_color = -expected_color;
+            if m.is_pass() {
+                global_input[i] = 1.0;
+            } else if (m.x as usize) < size && (m.y as usize) < size {
+                bin_input[[9 + i, m.y as usize, m.x as usize]] = 1.0;
+            }
+        }
+
+        // Komi
+        let usable = |scale: &f32| scale.is_finite() && *scale > 0.0;
+        let komi_scale = options
+            .komi_scale
+            .filter(usable)
+            .or(model_komi_scale.filter(usable))
+            .unwrap_or(DEFAULT_KOMI_SCALE);
+        global_input[5] = options.komi / komi_scale;
+
+        // Encore phase (globals 12-13), and whether passing now ends the phase
+        // (global 14), which happens when it answers the opponent's pass
+        if options.encore_phase > 0 {
+            global_input[12] = 1.0;
+        }
+        if options.encore_phase > 1 {
+            global_input[13] = 1.0;
+        }
+        if history
+            .last()
+            .is_some_and(|m| m.color == opp && m.is_pass())
+        {
+            global_input[14] = 1.0;
+        }
+    }
+
+    /// Compute liberties for each position
+    fn compute_liberties(sign_map: &[Vec<i8>]) -> Vec<Vec<usize>> {
+        let size = sign_map.len();
+        let mut liberties = vec![vec![0usize; size]; size];
+        let mut visited = vec![vec![false; size]; size];
+
+        for y in 0..size {
+            for x in 0..size {
+                if sign_map[y][x] != 0 && !visited[y][x] {
+                    // Find group and count liberties
+                    let mut group = Vec::new();
+                    let mut liberty_set = std::collections::HashSet::new();
+                    let mut stack = vec![(x, y)];
+                    let color = sign_map[y][x];
+
+                    while let Some((cx, cy)) = stack.pop() {
+                        if visited[cy][cx] {
+                            continue;
+                        }
+                        if sign_map[cy][cx] != color {
+                            if sign_map[cy][cx] == 0 {
+                                liberty_set.insert((cx, cy));
+                            }
+                            continue;
+                        }
+
+                        visited[cy][cx] = true;
+                        group.push((cx, cy));
+
+                        // Check neighbors
+                        if cx > 0 {
+                            stack.push((cx - 1, cy));
+                        }
+                        if cx + 1 < size {
+                            stack.push((cx + 1, cy));
+                        }
+                        if cy > 0 {
+                            stack.push((cx, cy - 1));
+                        }
+                        if cy + 1 < size {
+                            stack.push((cx, cy + 1));
+                        }
+                    }
+
+                    // Check liberties from group edges
+                    for &(gx, gy) in &group {
+                        let neighbors = [
+                            (gx.wrapping_sub(1), gy),
+                            (gx + 1, gy),
+                            (gx, gy.wrapping_sub(1)),
+                            (gx, gy + 1),
+                        ];
+                        for (nx, ny) in neighbors {
+                            if nx < size && ny < size && sign_map[ny][nx] == 0 {
+                                liberty_set.insert((nx, ny));
+                            }
+                        }
+                    }
+
+                    let lib_count = liberty_set.len();
+                    for (gx, gy) in group {
+                        liberties[gy][gx] = lib_count;
+                    }
+                }
+            }
+        }
+
+        liberties
+    }
+
+    /// Run ONNX inference
+    fn run_inference(
+        &mut self,
+        bin_input: &Array4<f32>,
+        global_input: &Array2<f32>,
+        batch_size: usize,
+    ) -> Result<OnnxOutputs, OnnxError> {
+        let outputs = if self.is_fp16 {
+            self.run_inference_fp16(bin_input, global_input)
+        } else {
+            self.run_inference_fp32(bin_input, global_input)
+        };
+        let outputs = outputs.inspect_err(|_| {
+            STATS.failures.fetch_add(1, Ordering::Relaxed);
+        })?;
+        STATS.inferences.fetch_add(1, Ordering::Relaxed);
+        STATS
+            .positions
+            .fetch_add(batch_size as u64, Ordering::Relaxed);
+        STATS
+            .inference_us
+            .fetch_add((outputs.inference_ms * 1000.0) as u64, Ordering::Relaxed);
+        log::debug!(
+            "[OnnxEngine] Inference of {} position(s) on {} took {:.1} ms",
+            batch_size,
+            self.provider_name,
+            outputs.inference_ms
+        );
+        Ok(outputs)
+    }
+
+    /// Run the session on caller-provided tensors, without featurizing or post-processing
+    /// The inputs must be exactly the model's inputs; fp16 inputs are converted from
+    /// f32 and fp16 outputs back to f32
+    fn run_raw(
+        &mut self,
+        mut inputs: HashMap<String, TensorData>,
+    ) -> Result<HashMap<String, TensorData>, OnnxError> {
+        let expected: Vec<&str> = self
+            .session
+            .inputs
+            .iter()
+            .map(|input| input.name.as_str())
+            .collect();
+        if inputs.len() != expected.len() || expected.iter().any(|name| !inputs.contains_key(*name))
+        {
+            let mut given: Vec<&str> = inputs.keys().map(String::as_str).collect();
+            given.sort_unstable();
+            return Err(OnnxError::ShapeMismatch(format!(
+                "Model expects inputs [{}], got [{}]",
+                expected.join(", "),
+                given.join(", ")
+            )));
+        }
+
+        let mut values: Vec<(String, SessionInputValue)> = Vec::with_capacity(inputs.len());
+        for input in &self.session.inputs {
+            let tensor = inputs.remove(&input.name).unwrap();
+            let is_fp16 = format!("{:?}", input.input_type).contains("Float16");
+            let value: SessionInputValue = if is_fp16 {
+                let data: Vec<f16> = tensor.data.iter().map(|&v| f16::from_f32(v)).collect();
+                Tensor::from_array((tensor.shape, data))
+                    .map_err(|e| {
+                        OnnxError::ShapeMismatch(format!("Invalid input {}: {}", input.name, e))
+                    })?
+                    .into()
+            } else {
+                Tensor::from_array((tensor.shape, tensor.data))
+                    .map_err(|e| {
+                        OnnxError::ShapeMismatch(format!("Invalid input {}: {}", input.name, e))
+                    })?
+                    .into()
+            };
+            values.push((input.name.clone(), value));
+        }
+
+        let start = Instant::now();
+        let outputs = self
+            .session
+            .run(values)
+            .map_err(|e| run_error(&self.provider_name, e))?;
+        log::debug!(
+            "[OnnxEngine] Raw inference on {} took {:.1} ms",
+            self.provider_name,
+            start.elapsed().as_secs_f64() * 1000.0
+        );
+
+        let mut tensors = HashMap::new();
+        for (name, value) in &outputs {
+            let (shape, data) = match value.try_extract_tensor::<f32>() {
+                Ok((shape, data)) => (shape.to_vec(), data.to_vec()),
+                Err(_) => {
+                    let (shape, data) = value.try_extract_tensor::<f16>().map_err(|e| {
+                        OnnxError::ShapeMismatch(format!("Failed to extract {}: {}", name, e))
+                    })?;
+                    (shape.to_vec(), data.iter().map(|v| v.to_f32()).collect())
+                }
+            };
+            tensors.insert(name.to_string(), TensorData { shape, data });
+        }
+        Ok(tensors)
+    }
+
+    /// Run ONNX inference with fp32 tensors
+    fn run_inference_fp32(
+        &mut self,
+        bin_input: &Array4<f32>,
+        global_input: &Array2<f32>,
+    ) -> Result<OnnxOutputs, OnnxError> {
+        // Clone arrays to get owned data for tensor creation
+        let bin_owned = bin_input.clone();
+        let global_owned = global_input.clone();
+
+        // Create input tensors from owned arrays
+        let bin_tensor = Tensor::from_array(bin_owned)
+            .map_err(|e| format!("Failed to create bin_input tensor: {}", e))?;
+
+        let global_tensor = Tensor::from_array(global_owned)
+            .map_err(|e| format!("Failed to create global_input tensor: {}", e))?;
+
+        // Run inference
+        let start = Instant::now();
+        let outputs = self
+            .session
+            .run(ort::inputs![bin_tensor, global_tensor])
+            .map_err(|e| run_error(&self.provider_name, e))?;
+        let inference_ms = start.elapsed().as_secs_f64() * 1000.0;
+
+        // Extract outputs - try_extract_tensor returns (&Shape, &[T])
+        let (policy_shape, policy_data) = outputs["policy"]
+            .try_extract_tensor::<f32>()
+            .map_err(|e| OnnxError::ShapeMismatch(format!("Failed to extract policy: {}", e)))?;
+
+        let (_value_shape, value_data) = outputs["value"]
+            .try_extract_tensor::<f32>()
+            .map_err(|e| OnnxError::ShapeMismatch(format!("Failed to extract value: {}", e)))?;
+
+        let (_misc_shape, miscvalue_data) = outputs["miscvalue"]
+            .try_extract_tensor::<f32>()
+            .map_err(|e| OnnxError::ShapeMismatch(format!("Failed to extract miscvalue: {}", e)))?;
+
+        let ownership = if outputs.contains_key("ownership") {
+            let (_own_shape, own_data) =
+                outputs["ownership"]
+                    .try_extract_tensor::<f32>()
+                    .map_err(|e| {
+                        OnnxError::ShapeMismatch(format!("Failed to extract ownership: {}", e))
+                    })?;
+            Some(own_data.to_vec())
+        } else {
+            None
+        };
+
+        let moremiscvalue = if outputs.contains_key("moremiscvalue") {
+            let (_more_shape, more_data) = outputs["moremiscvalue"]
+                .try_extract_tensor::<f32>()
+                .map_err(|e| {
+                    OnnxError::ShapeMismatch(format!("Failed to extract moremiscvalue: {}", e))
+                })?;
+            Some(more_data.to_vec())
+        } else {
+            None
+        };
+
+        // Convert Shape to Vec<usize>
+        let mut policy_dims: Vec<usize> = policy_shape.iter().map(|&d| d as usize).collect();
+        let mut policy = policy_data.to_vec();
+        if outputs.contains_key(PASS_OUTPUT_NAME) {
+            let (_pass_shape, pass_data) = outputs[PASS_OUTPUT_NAME]
+                .try_extract_tensor::<f32>()
+                .map_err(|e| {
+                    OnnxError::ShapeMismatch(format!(
+                        "Failed to extract {}: {}",
+                        PASS_OUTPUT_NAME, e
+                    ))
+                })?;
+            (policy, policy_dims) = append_pass_logits(&policy, &policy_dims, pass_data)?;
+        }
+
+        Ok(OnnxOutputs {
+            policy,
+            value: value_data.to_vec(),
+            miscvalue: miscvalue_data.to_vec(),
+            moremiscvalue,
+            ownership,
+            policy_dims,
+            inference_ms,
+        })
+    }
+
+    /// Run ONNX inference with fp16 tensors (converts f32 inputs to f16, runs inference, converts f16 outputs back to f32)
+    fn run_inference_fp16(
+        &mut self,
+        bin_input: &Array4<f32>,
+        global_input: &Array2<f32>,
+    ) -> Result<OnnxOutputs, OnnxError> {
+        // Convert f32 inputs to f16
+        let bin_fp16 = bin_input.mapv(|v| f16::from_f32(v));
+        let global_fp16 = global_input.mapv(|v| f16::from_f32(v));
+
+        // Create input tensors from f16 arrays
+        let bin_tensor = Tensor::from_array(bin_fp16)
+            .map_err(|e| format!("Failed to create bin_input f16 tensor: {}", e))?;
+
+        let global_tensor = Tensor::from_array(global_fp16)
+            .map_err(|e| format!("Failed to create global_input f16 tensor: {}", e))?;
+
+        // Run inference
+        let start = Instant::now();
+        let outputs = self
+            .session
+            .run(ort::inputs![bin_tensor, global_tensor])
+            .map_err(|e| run_error(&self.provider_name, e))?;
+        let inference_ms = start.elapsed().as_secs_f64() * 1000.0;
+
+        // Extract outputs as f16 and convert to f32
+        let (policy_shape, policy_data) = outputs["policy"]
+            .try_extract_tensor::<f16>()
+            .map_err(|e| OnnxError::ShapeMismatch(format!("Failed to extract policy: {}", e)))?;
+
+        let (_value_shape, value_data) = outputs["value"]
+            .try_extract_tensor::<f16>()
+            .map_err(|e| OnnxError::ShapeMismatch(format!("Failed to extract value: {}", e)))?;
+
+        let (_misc_shape, miscvalue_data) = outputs["miscvalue"]
+            .try_extract_tensor::<f16>()
+            .map_err(|e| OnnxError::ShapeMismatch(format!("Failed to extract miscvalue: {}", e)))?;
+
+        let ownership = if outputs.contains_key("ownership") {
+            let (_own_shape, own_data) =
+                outputs["ownership"]
+                    .try_extract_tensor::<f16>()
+                    .map_err(|e| {
+                        OnnxError::ShapeMismatch(format!("Failed to extract ownership: {}", e))
+                    })?;
+            Some(own_data.iter().map(|v| v.to_f32()).collect())
+        } else {
+            None
+        };
+
+        let moremiscvalue = if outputs.contains_key("moremiscvalue") {
+            let (_more_shape, more_data) = outputs["moremiscvalue"]
+                .try_extract_tensor::<f16>()
+                .map_err(|e| {
+                    OnnxError::ShapeMismatch(format!("Failed to extract moremiscvalue: {}", e))
+                })?;
+            Some(more_data.iter().map(|v| v.to_f32()).collect())
+        } else {
+            None
+        };
+
+        // Convert Shape to Vec<usize>
+        let mut policy_dims: Vec<usize> = policy_shape.iter().map(|&d| d as usize).collect();
+        let mut policy: Vec<f32> = policy_data.iter().map(|v| v.to_f32()).collect();
+        if outputs.contains_key(PASS_OUTPUT_NAME) {
+            let (_pass_shape, pass_data) = outputs[PASS_OUTPUT_NAME]
+                .try_extract_tensor::<f16>()
+                .map_err(|e| {
+                    OnnxError::ShapeMismatch(format!(
+                        "Failed to extract {}: {}",
+                        PASS_OUTPUT_NAME, e
+                    ))
+                })?;
+            let pass: Vec<f32> = pass_data.iter().map(|v| v.to_f32()).collect();
+            (policy, policy_dims) = append_pass_logits(&policy, &policy_dims, &pass)?;
+        }
+
+        // Convert f16 outputs to f32
+        Ok(OnnxOutputs {
+            policy,
+            value: value_data.iter().map(|v| v.to_f32()).collect(),
+            miscvalue: miscvalue_data.iter().map(|v| v.to_f32()).collect(),
+            moremiscvalue,
+            ownership,
+            policy_dims,
+            inference_ms,
+        })
+    }
+
+    /// Process single inference result
+    fn process_results(
+        &self,
+        outputs: &OnnxOutputs,
+        sign_map: &[Vec<i8>],
+        pla: i8,
+        options: &AnalysisOptions,
+    ) -> Result<AnalysisResult, OnnxError> {
+        let item = BatchItem {
+            sign_map,
+            pla,
+            options,
+        };
+        let results = process_batch_results(self.board_size, outputs, &[item])?;
+        results
+            .into_iter()
+            .next()
+            .ok_or_else(|| OnnxError::Inference("No results".to_string()))
+    }
+}
+
+/// One position of an inference batch, as needed to decode its outputs
+struct BatchItem<'a> {
+    sign_map: &'a [Vec<i8>],
+    pla: i8,
+    options: &'a AnalysisOptions,
+}
+
+/// Process batch inference results for `size`x`size` positions
+/// Illegal moves (occupied, suicide and ko points) are masked out of the policy,
+/// and items with `policy_only` set skip the score and ownership decoding
+fn process_batch_results(
+    size: usize,
+    outputs: &OnnxOutputs,
+    items: &[BatchItem],
+) -> Result<Vec<AnalysisResult>, OnnxError> {
+    let batch_size = items.len();
+    if batch_size == 0 {
+        return Ok(vec![]);
+    }
+
+    // Determine strides from dimensions
+    let policy_dims = &outputs.policy_dims;
+    let num_policy_heads = if policy_dims.len() == 3 {
+        policy_dims[1]
+    } else {
+        1
+    };
+    let num_moves = if policy_dims.len() == 3 {
+        policy_dims[2]
+    } else {
+        policy_dims[1]
+    };
+    let policy_stride = num_policy_heads * num_moves;
+    let value_stride = 3;
+    let miscvalue_stride = 10;
+    let ownership_stride = size * size;
+    // The short-term errors are the first two values of each item, older models lack them
+    let moremiscvalue_stride = outputs
+        .moremiscvalue
+        .as_ref()
+        .map_or(0, |more| more.len() / batch_size.max(1));
+    let inference_ms = outputs.inference_ms / batch_size as f64;
+
+    // Reject outputs too small for the batch instead of panicking while slicing
+    let check_len = |name: &str, len: usize, stride: usize| {
+        if len < batch_size * stride {
+            return Err(OnnxError::ShapeMismatch(format!(
+                "Output {} has {} values, expected {} for a batch of {} on a {}x{} board",
+                name,
+                len,
+                batch_size * stride,
+                batch_size,
+                size,
+                size
+            )));
+        }
+        Ok(())
+    };
+    check_len("policy", outputs.policy.len(), policy_stride)?;
+    check_len("value", outputs.value.len(), value_stride)?;
+    check_len("miscvalue", outputs.miscvalue.len(), miscvalue_stride)?;
+    if let Some(ownership) = &outputs.ownership {
+        check_len("ownership", ownership.len(), ownership_stride)?;
+    }
+    if num_moves != size * size + 1 {
+        return Err(OnnxError::ShapeMismatch(format!(
+            "Policy has {} moves, expected {} for a {}x{} board",
+            num_moves,
+            size * size + 1,
+            size,
+            size
+        )));
+    }
+
+    let mut results = Vec::with_capacity(batch_size);
+
+    for (b, item) in items.iter().enumerate() {
+        let pla = item.pla;
+        let policy_only = item.options.policy_only;
+
+        // Extract policy for this batch item
+        let policy_start = b * policy_stride;
+        let policy_end = policy_start + num_moves;
+        let mut policy = outputs.policy[policy_start..policy_end].to_vec();
+
+        // Extract value
+        let value_start = b * value_stride;
+        let mut value = outputs.value[value_start..value_start + 3].to_vec();
+
+        // Extract miscvalue, unless only the policy is wanted
+        let misc_start = b * miscvalue_stride;
+        let mut miscvalue = if policy_only {
+            vec![]
+        } else {
+            outputs.miscvalue[misc_start..misc_start + miscvalue_stride].to_vec()
+        };
+
+        // Corrupt or incompatible models can output NaN/Inf: a bad policy logit
+        // gets zero probability, other values fall back to neutral
+        let mut had_invalid_outputs = replace_non_finite(&mut policy, f32::NEG_INFINITY);
+        had_invalid_outputs |= replace_non_finite(&mut value, 0.0);
+        had_invalid_outputs |= replace_non_finite(&mut miscvalue, 0.0);
+
+        let legal = legal_move_mask(item.sign_map, pla, item.options.ko_point);
+        for (logit, _) in policy.iter_mut().zip(&legal).filter(|(_, &legal)| !legal) {
+            *logit = f32::NEG_INFINITY;
+        }
+
+        // Win rate from value head
+        let exp_values: Vec<f32> = value.iter().map(|v| v.exp()).collect();
+        let sum_value: f32 = exp_values.iter().sum();
+        let winrate_current = exp_values[0] / sum_value;
+        let draw_rate = exp_values[2] / sum_value;
+        let black_winrate = if pla == 1 {
+            winrate_current
+        } else {
+            1.0 - winrate_current
+        };
+
+        // Score lead
+        let lead_current = miscvalue.get(2).map_or(0.0, |lead| lead * 20.0);
+        let black_lead = lead_current * (pla as f32);
+        // Without a score lead there is nothing to blend in
+        let effective_winrate = if policy_only {
+            black_winrate
+        } else {
+            score_to_winrate(black_winrate, black_lead)
+        };
+
+        // Short-term errors, scaled the way KataGo post-processes them
+        let has_shortterm_errors = !policy_only && moremiscvalue_stride >= 2;
+        let (shortterm_value_error, shortterm_score_error) = match &outputs.moremiscvalue {
+            Some(more) if has_shortterm_errors => {
+                let start = b * moremiscvalue_stride;
+                let mut raw = [more[start], more[start + 1]];
+                had_invalid_outputs |= replace_non_finite(&mut raw, 0.0);
+                (
+                    (softplus(raw[0]) * SHORTTERM_VALUE_ERROR_MULTIPLIER).sqrt(),
+                    (softplus(raw[1]) * SHORTTERM_SCORE_ERROR_MULTIPLIER).sqrt(),
+                )
+            }
+            _ => (0.0, 0.0),
+        };
+
+        // Policy softmax, uniform over the legal moves if no logit is usable
+        let max_logit = policy.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+        let probs: Vec<f32> = if max_logit == f32::NEG_INFINITY {
+            let num_legal = legal.iter().filter(|&&legal| legal).count();
+            legal
+                .iter()
+                .map(|&legal| if legal { 1.0 / num_legal as f32 } else { 0.0 })
+                .collect()
+        } else {
+            let exp_logits: Vec<f32> = policy.iter().map(|p| (p - max_logit).exp()).collect();
+            let sum_probs: f32 = exp_logits.iter().sum();
+            exp_logits.iter().map(|p| p / sum_probs).collect()
+        };
+
+        // Get top 10 legal moves
+        let mut indices: Vec<usize> = (0..num_moves).filter(|&idx| legal[idx]).collect();
+        indices.sort_by(|&a, &b| probs[b].partial_cmp(&probs[a]).unwrap());
+
+        let move_suggestions: Vec<MoveSuggestion> = indices
+            .iter()
+            .take(10)
+            .map(|&idx| {
+                let move_str = if idx == size * size {
+                    "PASS".to_string()
+                } else {
+                    crate::board::format_gtp_vertex(idx % size, idx / size, size)
+                };
+                MoveSuggestion {
+                    move_str,
+                    probability: probs[idx],
+                }
+            })
+            .collect();
+
+        // Ownership
+        let ownership = outputs
+            .ownership
+            .as_ref()
+            .filter(|_| !policy_only)
+            .map(|own| {
+                let start = b * ownership_stride;
+                let mut ownership: Vec<f32> = own[start..start + ownership_stride]
+                    .iter()
+                    .map(|v| v * (pla as f32))
+                    .collect();
+                had_invalid_outputs |= replace_non_finite(&mut ownership, 0.0);
+                ownership
+            });
+
+        if had_invalid_outputs {
+            log::warn!(
+                "[OnnxEngine] Warning: model produced NaN/Inf outputs, replaced with safe values"
+            );
+        }
+
+        results.push(AnalysisResult {
+            move_suggestions,
+            win_rate: black_winrate,
+            effective_winrate,
+            draw_rate,
+            score_lead: black_lead,
+            current_turn: if pla == 1 { "B" } else { "W" }.to_string(),
+            ownership,
+            inference_ms,
+            featurize_ms: 0.0,
+            postprocess_ms: 0.0,
+            batch_size,
+            had_invalid_outputs,
+            shortterm_value_error,
+            shortterm_score_error,
+            has_shortterm_errors,
+            symmetries_used: 1,
+            policy: item.options.include_policy.then_some(probs),
+        });
+    }
+
+    Ok(results)
+}
+
+/// Legal moves for `pla` in policy order (row-major points, then pass), with a
+/// simple ko at `ko_point`; pass is always legal
+fn legal_move_mask(sign_map: &[Vec<i8>], pla: i8, ko_point: Option<(i32, i32)>) -> Vec<bool> {
+    let size = sign_map.len();
+    let Ok(mut board) = GoBoard::from_signmap(sign_map) else {
+        return vec![true; size * size + 1];
+    };
+    board.set_ko_point(ko_point.map(|(x, y)| (x as usize, y as usize)), pla);
+    (0..size * size)
+        .map(|idx| board.is_legal((idx % size) as i32, (idx / size) as i32, pla))
+        .chain([true])
+        .collect()
+}
+
+/// Output holding the pass logit, for models that keep it out of the policy output
+const PASS_OUTPUT_NAME: &str = "policy_pass";
+
+/// Append the logits of a separate pass output to a board-only policy, so the
+/// policy has the usual size*size + 1 moves per head with pass last
+/// `pass` needs one logit per policy row (batch item and head)
+fn append_pass_logits(
+    policy: &[f32],
+    dims: &[usize],
+    pass: &[f32],
+) -> Result<(Vec<f32>, Vec<usize>), OnnxError> {
+    let num_points = dims.last().copied().unwrap_or(0);
+    let rows = dims[..dims.len().saturating_sub(1)]
+        .iter()
+        .product::<usize>();
+    if num_points == 0 || pass.len() != rows || policy.len() != rows * num_points {
+        return Err(OnnxError::ShapeMismatch(format!(
+            "Output {} has {} values, expected one per policy row ({})",
+            PASS_OUTPUT_NAME,
+            pass.len(),
+            rows
+        )));
+    }
+    let mut merged = Vec::with_capacity(rows * (num_points + 1));
+    for (row, &pass) in policy.chunks(num_points).zip(pass) {
+        merged.extend_from_slice(row);
+        merged.push(pass);
+    }
+    let mut dims = dims.to_vec();
+    if let Some(last) = dims.last_mut() {
+        *last += 1;
+    }
+    Ok((merged, dims))
+}
+
+/// KataGo's scale for the raw short-term win/loss error prediction
+const SHORTTERM_VALUE_ERROR_MULTIPLIER: f32 = 0.25;
+
+/// KataGo's scale for the raw short-term score error prediction
+const SHORTTERM_SCORE_ERROR_MULTIPLIER: f32 = 150.0;
+
+/// Numerically stable softplus
+fn softplus(x: f32) -> f32 {
+    x.max(0.0) + (-x.abs()).exp().ln_1p()
+}
+
+/// Replace NaN and infinite values with `replacement`, returning whether there were any
+fn replace_non_finite(values: &mut [f32], replacement: f32) -> bool {
+    let mut found = false;
+    for v in values.iter_mut().filter(|v| !v.is_finite()) {
+        *v = replacement;
+        found = true;
+    }
+    found
+}
+
+/// Run `analyze` once per board size on the inputs of that size, returning the
+/// results in input order
+fn analyze_by_board_size<T>(
+    inputs: &[(Vec<Vec<i8>>, AnalysisOptions)],
+    mut analyze: impl FnMut(&[(Vec<Vec<i8>>, AnalysisOptions)]) -> Result<Vec<T>, OnnxError>,
+) -> Result<Vec<T>, OnnxError> {
+    let mut groups: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for (i, (sign_map, _)) in inputs.iter().enumerate() {
+        groups.entry(sign_map.len()).or_default().push(i);
+    }
+    let mut results: Vec<Option<T>> = inputs.iter().map(|_| None).collect();
+    for indices in groups.values() {
+        let group: Vec<_> = indices.iter().map(|&i| inputs[i].clone()).collect();
+        let group_results = analyze(&group)?;
+        for (&i, result) in indices.iter().zip(group_results) {
+            results[i] = Some(result);
+        }
+    }
+    Ok(results.into_iter().flatten().collect())
+}
+
+/// Run `analyze` on consecutive windows of at most `window` inputs, concatenating
+/// the results in input order
+fn analyze_in_windows<I, T>(
+    inputs: &[I],
+    window: usize,
+    mut analyze: impl FnMut(&[I]) -> Result<Vec<T>, OnnxError>,
+) -> Result<Vec<T>, OnnxError> {
+    let mut results = Vec::with_capacity(inputs.len());
+    for chunk in inputs.chunks(window.max(1)) {
+        results.extend(analyze(chunk)?);
+    }
+    Ok(results)
+}
+
+/// Input planes and globals that depend only on the board size and rules
+struct FeatureTemplate {
+    size: usize,
+    rules: Rules,
+    /// Channel 0 (on board) set, every other channel zero
+    bin: Array3<f32>,
+    /// Rules features set, every other feature zero
+    global: Array1<f32>,
+}
+
+impl FeatureTemplate {
+    fn new(size: usize, rules: Rules) -> Self {
+        let mut bin = Array3::<f32>::zeros((22, size, size));
+        bin.index_axis_mut(Axis(0), 0).fill(1.0);
+        let mut global = Array1::<f32>::zeros(19);
+        if rules.uses_territory_scoring() {
+            global[9] = 1.0;
+        }
+        Self {
+            size,
+            rules,
+            bin,
+            global,
+        }
+    }
+}
+
+/// Number of feature templates kept per session, enough for a few board sizes and rulesets
+const MAX_FEATURE_TEMPLATES: usize = 4;
+
+/// Get the template for a board size and rules from `templates` (most recent last),
+/// building it if needed and evicting the least recently used one when full
+fn cached_feature_template(
+    templates: &mut Vec<Arc<FeatureTemplate>>,
+    size: usize,
+    rules: Rules,
+) -> Arc<FeatureTemplate> {
+    let template = match templates
+        .iter()
+        .position(|t| t.size == size && t.rules == rules)
+    {
+        Some(i) => templates.remove(i),
+        None => Arc::new(FeatureTemplate::new(size, rules)),
+    };
+    if templates.len() >= MAX_FEATURE_TEMPLATES {
+        templates.remove(0);
+    }
+    templates.push(template.clone());
+    template
+}
+
+/// Player to move: `options.next_to_play`, or guessed from the stone counts
+fn next_player(sign_map: &[Vec<i8>], options: &AnalysisOptions) -> i8 {
+    match &options.next_to_play {
+        Some(s) if s == "W" => -1,
+        Some(_) => 1,
+        None => {
+            // Count stones to determine
+            let (mut black, mut white) = (0, 0);
+            for row in sign_map {
+                for &s in row {
+                    if s == 1 {
+                        black += 1;
+                    } else if s == -1 {
+                        white += 1;
+                    }
+                }
+            }
+            if black == white {
+                1
+            } else {
+                -1
+            }
+        }
+    }
+}
+
+/// Check that a sign map is a non-empty square board of -1, 0 and 1
+pub fn validate_sign_map(sign_map: &[Vec<i8>]) -> Result<(), OnnxError> {
+    let size = sign_map.len();
+    if size == 0 {
+        return Err(OnnxError::InvalidBoard("Board is empty".to_string()));
+    }
+    for (y, row) in sign_map.iter().enumerate() {
+        if row.len() != size {
+            return Err(OnnxError::InvalidBoard(format!(
+                "Board is not square: row {} has {} cells, expected {}",
+                y,
+                row.len(),
+                size
+            )));
+        }
+        if let Some(x) = row.iter().position(|&s| !(-1..=1).contains(&s)) {
+            return Err(OnnxError::InvalidBoard(format!(
+                "Invalid value {} at ({}, {}), expected -1, 0 or 1",
+                row[x], x, y
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Split a row-major buffer of `count` boards of `board_size` x `board_size` cells into sign maps
+pub fn unflatten_sign_maps(
+    cells: &[i8],
+    board_size: usize,
+    count: usize,
+) -> Result<Vec<Vec<Vec<i8>>>, OnnxError> {
+    let area = board_size * board_size;
+    if board_size == 0 || cells.len() != area * count {
+        return Err(OnnxError::InvalidBoard(format!(
+            "Expected {} cells for {} board(s) of {}x{}, got {}",
+            area * count,
+            count,
+            board_size,
+            board_size,
+            cells.len()
+        )));
+    }
+    Ok(cells
+        .chunks(area)
+        .map(|board| board.chunks(board_size).map(|row| row.to_vec()).collect())
+        .collect())
+}
+
+/// Lowercase fragments of the messages GPU runtimes report for device failures
+/// (lost device, out of memory, driver errors), which ORT passes on as generic failures
+const DEVICE_FAILURE_MARKERS: &[&str] = &[
+    "cuda",
+    "cudnn",
+    "cublas",
+    "out of memory",
+    "hiperror",
+    "miopen",
+    "device removed",
+    "device_removed",
+    "device lost",
+    "dxgi",
+    "d3d12",
+];
+
+/// Classify a failed session run
+/// Failures of the execution provider itself are reported as the provider being
+/// unavailable, anything else (bad inputs, model errors) as an inference failure
+fn run_error(provider_name: &str, error: ort::Error) -> OnnxError {
+    let message = format!("Inference failed: {}", error);
+    let provider_failed = provider_name != "cpu"
+        && (error.code() == ort::error::ErrorCode::ExecutionProviderFailure || {
+            let lower = error.message().to_lowercase();
+            DEVICE_FAILURE_MARKERS
+                .iter()
+                .any(|marker| lower.contains(marker))
+        });
+    if provider_failed {
+        OnnxError::ProviderUnavailable(message)
+    } else {
+        OnnxError::Inference(message)
+    }
+}
+
+/// Internal struct for ONNX outputs
+struct OnnxOutputs {
+    policy: Vec<f32>,
+    value: Vec<f32>,
+    miscvalue: Vec<f32>,
+    /// Extra value outputs of newer models, the short-term errors among them
+    moremiscvalue: Option<Vec<f32>>,
+    ownership: Option<Vec<f32>>,
+    policy_dims: Vec<usize>,
+    /// Wall time of the ORT `run` call in milliseconds
+    inference_ms: f64,
+}
+
+/// Map (x, y) through one of the 8 board symmetries
+/// Bit 0 flips vertically, bit 1 flips horizontally, bit 2 transposes
+fn apply_symmetry(x: usize, y: usize, size: usize, symmetry: usize) -> (usize, usize) {
+    let (mut x, mut y) = if symmetry & 4 != 0 { (y, x) } else { (x, y) };
+    if symmetry & 1 != 0 {
+        y = size - 1 - y;
+    }
+    if symmetry & 2 != 0 {
+        x = size - 1 - x;
+    }
+    (x, y)
+}
+
+/// Softmax of a slice of logits
+fn softmax(logits: &[f32]) -> Vec<f32> {
+    let max = logits.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+    let exps: Vec<f32> = logits.iter().map(|l| (l - max).exp()).collect();
+    let sum: f32 = exps.iter().sum();
+    exps.iter().map(|e| e / sum).collect()
+}
+
+/// Win rate at which [`score_to_winrate`] starts blending in the score lead,
+/// and its mirror below 0.5
+const WINRATE_SATURATION_START: f32 = 0.95;
+
+/// Score lead in points at which the score-based win rate reaches about 73%
+const SCORE_WINRATE_SCALE: f32 = 10.0;
+
+/// Spread saturated win rates by the score lead, both from the same perspective
+/// Win rates up to 95% (or down to 5%) are returned as they are; past that the
+/// result is placed within the 95-100% band (or 0-5%) by a logistic of the score
+/// lead, so a decided position still tells a 5-point lead from a 40-point one
+/// The result never decreases as either the win rate or the score lead grows
+pub fn score_to_winrate(win_rate: f32, score_lead: f32) -> f32 {
+    let band = 1.0 - WINRATE_SATURATION_START;
+    let score_winrate = 1.0 / (1.0 + (-score_lead / SCORE_WINRATE_SCALE).exp());
+    if win_rate > WINRATE_SATURATION_START {
+        WINRATE_SATURATION_START + band * score_winrate
+    } else if 1.0 - win_rate > WINRATE_SATURATION_START {
+        band * score_winrate
+    } else {
+        win_rate
+    }
+}
+
+/// Build a batch holding the `symmetries` transforms of a single featurized position,
+/// one batch item per symmetry in order
+fn symmetry_batch(
+    bin_input: &Array4<f32>,
+    global_input: &Array2<f32>,
+    symmetries: std::ops::Range<usize>,
+) -> (Array4<f32>, Array2<f32>) {
+    let size = bin_input.shape()[2];
+    let mut bin_batch = Array4::<f32>::zeros((symmetries.len(), 22, size, size));
+    let mut global_batch = Array2::<f32>::zeros((symmetries.len(), 19));
+    for (b, s) in symmetries.enumerate() {
+        for c in 0..22 {
+            for y in 0..size {
+                for x in 0..size {
+                    let (tx, ty) = apply_symmetry(x, y, size, s);
+                    bin_batch[[b, c, ty, tx]] = bin_input[[0, c, y, x]];
+                }
+            }
+        }
+        for i in 0..19 {
+            global_batch[[b, i]] = global_input[[0, i]];
+        }
+    }
+    (bin_batch, global_batch)
+}
+
+/// Join the outputs of separate inference runs into one batch, in order
+fn concat_outputs(outputs: Vec<OnnxOutputs>) -> OnnxOutputs {
+    let mut outputs = outputs.into_iter();
+    let mut joined = outputs.next().expect("at least one output");
+    for next in outputs {
+        joined.policy.extend(next.policy);
+        joined.value.extend(next.value);
+        joined.miscvalue.extend(next.miscvalue);
+        if let (Some(more), Some(next)) = (joined.moremiscvalue.as_mut(), next.moremiscvalue) {
+            more.extend(next);
+        }
+        if let (Some(own), Some(next)) = (joined.ownership.as_mut(), next.ownership) {
+            own.extend(next);
+        }
+        joined.policy_dims[0] += next.policy_dims[0];
+        joined.inference_ms += next.inference_ms;
+    }
+    joined
+}
+
+/// Average a batch of `symmetries` outputs of the same position into a single output
+/// Policy and value are averaged as probabilities and stored back as log-probabilities,
+/// so downstream softmax reproduces the averaged distribution
+fn average_symmetries(outputs: &OnnxOutputs, symmetries: usize, size: usize) -> OnnxOutputs {
+    let n = symmetries as f32;
+    let dims = &outputs.policy_dims;
+    let num_moves = dims[dims.len() - 1];
+    let policy_stride = outputs.policy.len() / symmetries;
+    let value_stride = outputs.value.len() / symmetries;
+    let misc_stride = outputs.miscvalue.len() / symmetries;
+    let board_points = size * size;
+
+    let mut policy = vec![0.0f32; num_moves];
+    let mut value = vec![0.0f32; value_stride];
+    let mut miscvalue = vec![0.0f32; misc_stride];
+    let more_stride = outputs
+        .moremiscvalue
+        .as_ref()
+        .map_or(0, |more| more.len() / symmetries);
+    let mut moremiscvalue = outputs
+        .moremiscvalue
+        .as_ref()
+        .map(|_| vec![0.0f32; more_stride]);
+    let mut ownership = outputs
+        .ownership
+        .as_ref()
+        .map(|_| vec![0.0f32; board_points]);
+
+    for s in 0..symmetries {
+        // Only the first policy head is used
+        let start = s * policy_stride;
+        let probs = softmax(&outputs.policy[start..start + num_moves]);
+        for (idx, p) in policy.iter_mut().enumerate() {
+            let src = if idx < board_points {
+                let (tx, ty) = apply_symmetry(idx % size, idx / size, size, s);
+                ty * size + tx
+            } else {
+                idx
+            };
+            *p += probs[src] / n;
+        }
+
+        let value_probs = softmax(&outputs.value[s * value_stride..(s + 1) * value_stride]);
+        for (v, p) in value.iter_mut().zip(value_probs) {
+            *v += p / n;
+        }
+
+        for (m, src) in miscvalue
+            .iter_mut()
+            .zip(&outputs.miscvalue[s * misc_stride..(s + 1) * misc_stride])
+        {
+            *m += src / n;
+        }
+
+        if let (Some(more), Some(src)) = (moremiscvalue.as_mut(), outputs.moremiscvalue.as_ref()) {
+            for (m, src) in more
+                .iter_mut()
+                .zip(&src[s * more_stride..(s + 1) * more_stride])
+            {
+                *m += src / n;
+            }
+        }
+
+        if let (Some(own), Some(src)) = (ownership.as_mut(), outputs.ownership.as_ref()) {
+            for (idx, o) in own.iter_mut().enumerate() {
+                let (tx, ty) = apply_symmetry(idx % size, idx / size, size, s);
+                *o += src[s * board_points + ty * size + tx] / n;
+            }
+        }
+    }
+
+    OnnxOutputs {
+        policy: policy
+            .iter()
+            .map(|p| p.max(f32::MIN_POSITIVE).ln())
+            .collect(),
+        value: value
+            .iter()
+            .map(|p| p.max(f32::MIN_POSITIVE).ln())
+            .collect(),
+        miscvalue,
+        moremiscvalue,
+        ownership,
+        policy_dims: vec![1, num_moves],
+        inference_ms: outputs.inference_ms,
+    }
+}
+
+/// Average the outputs of several models for the same position
+/// Policy and value are averaged as probabilities, like [`average_symmetries`];
+/// ownership is averaged over the models that have it
+fn average_models(outputs: &[OnnxOutputs]) -> OnnxOutputs {
+    let n = outputs.len() as f32;
+    let num_moves = outputs
+        .iter()
+        .map(|o| o.policy_dims[o.policy_dims.len() - 1])
+        .min()
+        .unwrap_or(0);
+
+    let mut policy = vec![0.0f32; num_moves];
+    let mut value = [0.0f32; 3];
+    for o in outputs {
+        // Only the first policy head is used
+        for (p, prob) in policy.iter_mut().zip(softmax(&o.policy[..num_moves])) {
+            *p += prob / n;
+        }
+        for (v, prob) in value.iter_mut().zip(softmax(&o.value[..3])) {
+            *v += prob / n;
+        }
+    }
+
+    // Value-like outputs are averaged over the values every model has
+    let average = |values: Vec<&Vec<f32>>| -> Vec<f32> {
+        let len = values.iter().map(|v| v.len()).min().unwrap_or(0);
+        let count = values.len() as f32;
+        (0..len)
+            .map(|i| values.iter().map(|v| v[i]).sum::<f32>() / count)
+            .collect()
+    };
+    let miscvalue = average(outputs.iter().map(|o| &o.miscvalue).collect());
+    let moremiscvalue = outputs
+        .iter()
+        .map(|o| o.moremiscvalue.as_ref())
+        .collect::<Option<Vec<_>>>()
+        .map(average);
+    let ownerships: Vec<_> = outputs
+        .iter()
+        .filter_map(|o| o.ownership.as_ref())
+        .collect();
+    let ownership = (!ownerships.is_empty()).then(|| average(ownerships));
+
+    OnnxOutputs {
+        policy: policy
+            .iter()
+            .map(|p| p.max(f32::MIN_POSITIVE).ln())
+            .collect(),
+        value: value
+            .iter()
+            .map(|p| p.max(f32::MIN_POSITIVE).ln())
+            .collect(),
+        miscvalue,
+        moremiscvalue,
+        ownership,
+        policy_dims: vec![1, num_moves],
+        inference_ms: outputs.iter().map(|o| o.inference_ms).sum(),
+    }
+}
+
+// Public API for Tauri commands
+
+/// Initialize the global engine with model bytes
+/// `warmup` is the board size to run a warm-up inference at, None to skip it
+pub fn initialize_engine(model_bytes: &[u8], warmup: Option<usize>) -> Result<(), String> {
+    let pool = OnnxSessionPool::from_bytes(model_bytes, get_session_count())?;
+    if let Some(board_size) = warmup {
+        pool.warm_up(board_size);
+    }
+    let mut global = ENGINE.write().map_err(|e| e.to_string())?;
+    *global = Some(Arc::new(pool));
+    ENGINE_STALE.store(false, Ordering::SeqCst);
+    clear_analysis_cache();
+    Ok(())
+}
+
+/// How [`initialize_engine_from_path`] and [`preload_engine_from_path`] load a model
+#[derive(Debug, Clone, Default)]
+pub struct ModelLoadOptions {
+    /// Board size to run a warm-up inference at, None to skip it
+    pub warmup: Option<usize>,
+    /// Persist the optimized graph next to the model
+    pub cache_optimized: bool,
+    /// Cache ID, reported back by [`get_loaded_model`]
+    pub model_id: Option<String>,
+    /// Hex-encoded SHA-256 of the model file, reported back by [`get_loaded_model`]
+    pub sha256: Option<String>,
+    /// The model's companion config, if it has one
+    pub config: Option<ModelConfig>,
+}
+
+/// Initialize the global engine from a file path
+pub fn initialize_engine_from_path(
+    model_path: &str,
+    options: ModelLoadOptions,
+) -> Result<(), String> {
+    let pool = load_pool_from_path(model_path, options)?;
+    let mut global = ENGINE.write().map_err(|e| e.to_string())?;
+    *global = Some(Arc::new(pool));
+    ENGINE_STALE.store(false, Ordering::SeqCst);
+    clear_analysis_cache();
+    Ok(())
+}
+
+/// Create and warm up a session pool from a file path, see [`initialize_engine_from_path`]
+fn load_pool_from_path(
+    model_path: &str,
+    options: ModelLoadOptions,
+) -> Result<OnnxSessionPool, String> {
+    let mut pool = OnnxSessionPool::from_path(
+        Path::new(model_path),
+        get_session_count(),
+        options.cache_optimized,
+    )?;
+    pool.model_id = options.model_id;
+    pool.model_sha256 = options.sha256;
+    if let Some(config) = options.config {
+        pool.apply_config(config);
+    }
+    if let Some(board_size) = options.warmup {
+        pool.warm_up(board_size);
+    }
+    Ok(pool)
+}
+
+/// Load a model into the standby slot while the active engine keeps analyzing
+/// Takes the same arguments as [`initialize_engine_from_path`] and replaces any
+/// model preloaded earlier; call [`activate_preloaded_engine`] to switch to it
+pub fn preload_engine_from_path(model_path: &str, options: ModelLoadOptions) -> Result<(), String> {
+    let pool = load_pool_from_path(model_path, options)?;
+    log::info!(
+        "[OnnxEngine] Preloaded {} into the standby slot",
+        model_path
+    );
+    *STANDBY_ENGINE.lock().unwrap() = Some(pool);
+    Ok(())
+}
+
+/// Make the preloaded model the active engine
+/// In-flight analyses finish on the previous model; fails if nothing is preloaded
+pub fn activate_preloaded_engine() -> Result<(), String> {
+    let pool = STANDBY_ENGINE
+        .lock()
+        .unwrap()
+        .take()
+        .ok_or("No model is preloaded")?;
+    stop_ponder();
+    let mut global = ENGINE.write().map_err(|e| e.to_string())?;
+    *global = Some(Arc::new(pool));
+    ENGINE_STALE.store(false, Ordering::SeqCst);
+    clear_analysis_cache();
+    log::info!("[OnnxEngine] Activated the preloaded model");
+    Ok(())
+}
+
+/// Check whether `player` (1 = Black, -1 = White) may play at (x, y)
+/// Rejects off-board and occupied points, suicide, and retaking a simple ko at `ko_point`
+pub fn is_legal_move(
+    sign_map: &[Vec<i8>],
+    x: i32,
+    y: i32,
+    player: i8,
+    ko_point: Option<(i32, i32)>,
+) -> bool {
+    if x < 0 || y < 0 {
+        return false;
+    }
+    let Ok(mut board) = GoBoard::from_signmap(sign_map) else {
+        return false;
+    };
+    board.set_ko_point(ko_point.map(|(x, y)| (x as usize, y as usize)), player);
+    board.is_legal(x, y, player)
+}
+
+/// Board holding `sign_map` with the simple ko from `options.ko_point`, to replay moves on
+fn replay_board(sign_map: &[Vec<i8>], options: &AnalysisOptions) -> Result<GoBoard, String> {
+    let mut board = GoBoard::from_signmap(sign_map)?;
+    let player = next_player(sign_map, options);
+    board.set_ko_point(
+        options.ko_point.map(|(x, y)| (x as usize, y as usize)),
+        player,
+    );
+    Ok(board)
+}
+
+/// Options for the position after `color` played (x, y) on `board`, negative for a pass
+fn options_after_move(
+    options: &AnalysisOptions,
+    board: &GoBoard,
+    color: i8,
+    (x, y): (i32, i32),
+) -> AnalysisOptions {
+    let mut after = options.clone();
+    after.next_to_play = Some(if color == 1 { "W" } else { "B" }.to_string());
+    after.history.push(HistoryMove { color, x, y });
+    after.ko_point = board.ko_point().map(|(x, y)| (x as i32, y as i32));
+    after
+}
+
+/// Estimate the final score of a finished game from the ownership map
+/// Points are classified as Black, White or dame by thresholding ownership at
+/// `options.ownership_threshold`, and stones on points owned by the opponent are
+/// reported as dead; a higher threshold leaves more dame and fewer dead stones. Territory rules
+/// count dead stones as prisoners; captures made during the game are not known
+/// here and are not counted.
+pub fn estimate_final_score(
+    sign_map: Vec<Vec<i8>>,
+    options: AnalysisOptions,
+) -> Result<FinalScore, String> {
+    let rules = options.rules;
+    let komi = options.komi;
+    let threshold = options.ownership_threshold;
+    let options = AnalysisOptions {
+        symmetries: options.symmetries.max(FINAL_SCORE_SYMMETRIES),
+        ..options
+    };
+    let result = analyze_cached(sign_map.clone(), options)?;
+    let ownership = result
+        .ownership
+        .ok_or("Model has no ownership output, cannot estimate the final score")?;
+    Ok(score_from_ownership(
+        &sign_map, &ownership, rules, komi, threshold,
+    ))
+}
+
+/// Count a finished board from a Black-oriented ownership map, see [`estimate_final_score`]
+fn score_from_ownership(
+    sign_map: &[Vec<i8>],
+    ownership: &[f32],
+    rules: Rules,
+    komi: f32,
+    threshold: f32,
+) -> FinalScore {
+    let size = sign_map.len();
+    let territory = rules.uses_territory_scoring();
+    let (mut black_score, mut white_score) = (0.0f32, komi);
+    let mut dead_stones = Vec::new();
+    for y in 0..size {
+        for x in 0..size {
+            let owner = match ownership[y * size + x] {
+                o if o > threshold => 1,
+                o if o < -threshold => -1,
+                _ => 0,
+            };
+            let stone = sign_map[y][x];
+            let dead = stone != 0 && owner == -stone;
+            if dead {
+                dead_stones.push((x as i32, y as i32));
+            }
+
+            let points = if territory {
+                // Territory: empty points and dead stones, plus dead stones as prisoners
+                match (stone, dead) {
+                    (0, _) => 1.0,
+                    (_, true) => 2.0,
+                    _ => 0.0,
+                }
+            } else {
+                // Area: every owned point, stones included
+                1.0
+            };
+            match owner {
+                1 => black_score += points,
+                -1 => white_score += points,
+                _ => {}
+            }
+        }
+    }
+
+    FinalScore {
+        black_score,
+        white_score,
+        dead_stones,
+        score_lead: black_score - white_score,
+    }
+}
+
+/// Estimate the fair komi of a position by sampling Black's win rate across `range`
+/// Samples are one point apart, capped at [`MAX_KOMI_SAMPLES`] evenly spaced values,
+/// and analyzed as a single batch. The fair komi is linearly interpolated between
+/// the two samples where the win rate crosses 50%.
+pub fn estimate_fair_komi(
+    sign_map: Vec<Vec<i8>>,
+    options: AnalysisOptions,
+    (min_komi, max_komi): (f32, f32),
+) -> Result<FairKomi, String> {
+    if !(min_komi.is_finite() && max_komi.is_finite() && min_komi < max_komi) {
+        return Err(format!("Invalid komi range: {} to {}", min_komi, max_komi));
+    }
+    let count = ((max_komi - min_komi).ceil() as usize + 1).clamp(2, MAX_KOMI_SAMPLES);
+    let step = (max_komi - min_komi) / (count - 1) as f32;
+    let komis: Vec<f32> = (0..count).map(|i| min_komi + step * i as f32).collect();
+    let inputs: Vec<_> = komis
+        .iter()
+        .map(|&komi| {
+            let options = AnalysisOptions {
+                komi,
+                ..options.clone()
+            };
+            (sign_map.clone(), options)
+        })
+        .collect();
+
+    let pool = active_pool()?;
+    let results = pool
+        .checkout_for(options.deterministic, options.priority)?
+        .analyze_batch(&inputs)?;
+    let samples: Vec<KomiSample> = komis
+        .iter()
+        .zip(&results)
+        .map(|(&komi, result)| KomiSample {
+            komi,
+            win_rate: result.win_rate,
+        })
+        .collect();
+
+    let fair_komi = samples.windows(2).find_map(|pair| {
+        let (a, b) = (&pair[0], &pair[1]);
+        if (a.win_rate - 0.5) * (b.win_rate - 0.5) > 0.0 {
+            return None;
+        }
+        if a.win_rate == b.win_rate {
+            return Some(a.komi);
+        }
+        let t = (a.win_rate - 0.5) / (a.win_rate - b.win_rate);
+        Some(a.komi + t * (b.komi - a.komi))
+    });
+
+    Ok(FairKomi { fair_komi, samples })
+}
+
+/// Ownership change caused by playing `(x, y)`, as a [y][x] grid from Black's perspective
+/// Analyzes the position before and after the move, with captures resolved
+/// `options.orient_to` is ignored: the grid is always from Black's perspective
+pub fn move_impact(
+    sign_map: Vec<Vec<i8>>,
+    (x, y): (i32, i32),
+    options: AnalysisOptions,
+) -> Result<Vec<Vec<f32>>, String> {
+    validate_sign_map(&sign_map)?;
+    let size = sign_map.len();
+    let player = next_player(&sign_map, &options);
+    let mut board = replay_board(&sign_map, &options)?;
+    if x < 0 || y < 0 {
+        return Err(format!("Illegal move at ({}, {})", x, y));
+    }
+    board
+        .play(x, y, player)
+        .map_err(|e| format!("Illegal move at ({}, {}): {}", x, y, e))?;
+    let after = board.to_signmap();
+    let after_options = options_after_move(&options, &board, player, (x, y));
+
+    let pool = active_pool()?;
+    let mut engine = pool.checkout_for(options.deterministic, options.priority)?;
+    let ownership = |result: AnalysisResult| {
+        result
+            .ownership
+            .ok_or("Model has no ownership output, cannot compute move impact")
+    };
+    let before = ownership(engine.analyze(&sign_map, &options)?)?;
+    let after = ownership(engine.analyze(&after, &after_options)?)?;
+
+    Ok((0..size)
+        .map(|y| {
+            (0..size)
+                .map(|x| after[y * size + x] - before[y * size + x])
+                .collect()
+        })
+        .collect())
+}
+
+/// Grade playing `(x, y)`, or passing with negative coordinates, by the win rate it loses
+/// Analyzes the position before and after the move through the analysis cache; the
+/// engine's top move is always graded [`MoveGrade::Best`], others by the thresholds in `options`
+pub fn grade_move(
+    sign_map: Vec<Vec<i8>>,
+    (x, y): (i32, i32),
+    options: AnalysisOptions,
+) -> Result<GradedMove, String> {
+    validate_sign_map(&sign_map)?;
+    let size = sign_map.len();
+    let player = next_player(&sign_map, &options);
+    let mut board = replay_board(&sign_map, &options)?;
+    board
+        .play(x, y, player)
+        .map_err(|e| format!("Illegal move at ({}, {}): {}", x, y, e))?;
+    let after_options = options_after_move(&options, &board, player, (x, y));
+
+    let before = analyze_cached(sign_map, options.clone())?;
+    let after = analyze_cached(board.to_signmap(), after_options)?;
+    grade_from_analyses(&before, &after, (x, y), size, &options)
+}
+
+/// Grade the move `(x, y)` from the analyses before and after it, both from Black's perspective
+fn grade_from_analyses(
+    before: &AnalysisResult,
+    after: &AnalysisResult,
+    (x, y): (i32, i32),
+    size: usize,
+    options: &AnalysisOptions,
+) -> Result<GradedMove, String> {
+    let is_pass = x < 0 || y < 0;
+    let pla = if before.current_turn == "B" {
+        1.0
+    } else {
+        -1.0
+    };
+    let win_rate_delta = (after.win_rate - before.win_rate) * pla;
+    let score_lead_delta = (after.score_lead - before.score_lead) * pla;
+    let best_move = before
+        .move_suggestions
+        .first()
+        .map(|m| m.move_str.clone())
+        .ok_or("Model returned no move suggestions")?;
+    let played = (!is_pass).then_some((x, y));
+    let grade = if parse_gtp_move(&best_move, size) == played {
+        MoveGrade::Best
+    } else {
+        MoveGrade::from_win_rate_loss(-win_rate_delta, options)
+    };
+
+    Ok(GradedMove {
+        grade,
+        win_rate_delta,
+        score_lead_delta,
+        best_move,
+    })
+}
+
+/// Approximate a principal variation by greedily playing the top legal move for `depth` plies
+/// Candidates that are illegal or recreate an earlier position are skipped in favor of the
+/// next one; the line ends early after two consecutive passes
+/// `options.orient_to` is ignored: the win rates are Black's, whoever is to move
+pub fn get_pv(
+    sign_map: Vec<Vec<i8>>,
+    options: AnalysisOptions,
+    depth: usize,
+) -> Result<PrincipalVariation, String> {
+    let pool = active_pool()?;
+    let mut engine = pool.checkout_for(options.deterministic, options.priority)?;
+    let size = sign_map.len();
+    let mut board = replay_board(&sign_map, &options)?;
+    let mut options = options;
+    let mut seen = std::collections::HashSet::from([sign_map]);
+    let mut pv = PrincipalVariation {
+        moves: Vec::new(),
+        win_rates: Vec::new(),
+    };
+
+    for _ in 0..depth.min(MAX_PV_DEPTH) {
+        let result = engine.analyze(&board.to_signmap(), &options)?;
+        let player = if result.current_turn == "B" { 1 } else { -1 };
+
+        // First candidate that is a pass, or legal and not repeating a position
+        let chosen = result.move_suggestions.iter().find_map(|suggestion| {
+            let Some((x, y)) = parse_gtp_move(&suggestion.move_str, size) else {
+                return Some((suggestion.move_str.clone(), None));
+            };
+            board.play(x, y, player).ok()?;
+            let next = board.to_signmap();
+            board.undo();
+            (!seen.contains(&next)).then(|| (suggestion.move_str.clone(), Some((x, y, next))))
+        });
+        let (move_str, placed) = chosen.unwrap_or_else(|| ("PASS".to_string(), None));
+
+        let (x, y) = match placed {
+            Some((x, y, next)) => {
+                seen.insert(next);
+                (x, y)
+            }
+            None => (-1, -1),
+        };
+        board.play(x, y, player)?;
+        let consecutive_pass = x < 0 && options.history.last().is_some_and(|m| m.x < 0);
+
+        pv.moves.push(move_str);
+        pv.win_rates.push(result.win_rate);
+        options = options_after_move(&options, &board, player, (x, y));
+
+        if consecutive_pass {
+            break;
+        }
+    }
+
+    Ok(pv)
+}
+
+/// Pick genmove's move from a full policy with illegal moves at 0, None for a pass
+/// See [`genmove`] for when pass is chosen
+fn choose_genmove(
+    policy: &[f32],
+    size: usize,
+    black_win_rate: f32,
+    pass_threshold: f32,
+    randomize_top_k: Option<usize>,
+) -> Option<(i32, i32)> {
+    let num_points = size * size;
+    let pass_probability = policy.get(num_points).copied().unwrap_or(0.0);
+    let mut candidates: Vec<(usize, f32)> = policy
+        .iter()
+        .take(num_points)
+        .copied()
+        .enumerate()
+        .filter(|&(_, p)| p > 0.0)
+        .collect();
+    candidates.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    let decided = black_win_rate >= GENMOVE_DECIDED_WIN_RATE
+        || black_win_rate <= 1.0 - GENMOVE_DECIDED_WIN_RATE;
+    if candidates.is_empty() || (decided && pass_probability > pass_threshold) {
+        return None;
+    }
+    let top = &candidates[..randomize_top_k.unwrap_or(1).clamp(1, candidates.len())];
+    let total: f32 = top.iter().map(|c| c.1).sum();
+    let mut target = rand::random::<f32>() * total;
+    let (idx, _) = top
+        .iter()
+        .find(|c| {
+            target -= c.1;
+            target <= 0.0
+        })
+        .unwrap_or(&top[0]);
+    Some(((idx % size) as i32, (idx / size) as i32))
+}
+
+/// Parse a GTP move ("D4", "PASS") into board coordinates, None for a pass
+fn parse_gtp_move(move_str: &str, size: usize) -> Option<(i32, i32)> {
+    let (x, y) = crate::board::parse_gtp_vertex(move_str, size).ok()??;
+    Some((x as i32, y as i32))
+}
+
+/// Pick a move to play: the most likely legal move, with pass demoted below it
+///
+/// Pass is only chosen when no legal move is left, or when the value head
+/// considers the game decided (see [`GENMOVE_DECIDED_WIN_RATE`]) and the pass
+/// probability exceeds `options.pass_threshold`. The policy otherwise passes
+/// too early, as its pass logit can outrank weak but legal moves.
+/// With `randomize_top_k`, samples among the top K legal moves weighted by probability
+/// Resigns instead when `options.resign_threshold` is met, see [`RESIGN_STREAK`]
+/// Never retakes a simple ko at `options.ko_point`
+/// `options.orient_to` is ignored: the win rate and score lead are always Black's
+pub fn genmove(
+    sign_map: Vec<Vec<i8>>,
+    mut options: AnalysisOptions,
+    randomize_top_k: Option<usize>,
+) -> Result<GenmoveResult, String> {
+    let size = sign_map.len();
+    let pass_threshold = options.pass_threshold;
+    let resign_threshold = options.resign_threshold;
+    let resign_streak = options.resign_streak;
+    options.include_policy = true;
+    let result = analyze_cached(sign_map, options)?;
+    let player = if result.current_turn == "B" { 1 } else { -1 };
+
+    let own_win_rate = if player == 1 {
+        result.win_rate
+    } else {
+        1.0 - result.win_rate
+    };
+    let below_resign_threshold = resign_threshold.is_some_and(|threshold| own_win_rate < threshold);
+    if below_resign_threshold && resign_streak + 1 >= RESIGN_STREAK {
+        return Ok(GenmoveResult {
+            x: -1,
+            y: -1,
+            is_pass: false,
+            resign: true,
+            below_resign_threshold,
+            win_rate: result.win_rate,
+            score_lead: result.score_lead,
+        });
+    }
+
+    let policy = result.policy.as_deref().unwrap_or_default();
+    let chosen = choose_genmove(
+        policy,
+        size,
+        result.win_rate,
+        pass_threshold,
+        randomize_top_k,
+    );
+
+    let (x, y) = chosen.unwrap_or((-1, -1));
+    Ok(GenmoveResult {
+        x,
+        y,
+        is_pass: chosen.is_none(),
+        resign: false,
+        below_resign_threshold,
+        win_rate: result.win_rate,
+        score_lead: result.score_lead,
+    })
+}
+
+/// Play a game against itself from `sign_map` with [`genmove`], for sample games
+///
+/// Moves are sampled among the top `randomize_top_k` legal moves so games differ.
+/// The game stops after two consecutive passes or `max_moves` moves; simple ko is
+/// respected so it can't loop on a ko fight. Games are played out, never resigned.
+pub fn selfplay(
+    sign_map: Vec<Vec<i8>>,
+    options: AnalysisOptions,
+    max_moves: usize,
+    randomize_top_k: Option<usize>,
+) -> Result<SelfPlayGame, String> {
+    validate_sign_map(&sign_map)?;
+    let mut board = replay_board(&sign_map, &options)?;
+    let mut options = AnalysisOptions {
+        resign_threshold: None,
+        ..options
+    };
+    let mut player = next_player(&sign_map, &options);
+    let mut moves = Vec::new();
+    let mut consecutive_passes = 0;
+
+    while moves.len() < max_moves && consecutive_passes < 2 {
+        options.next_to_play = Some(if player == 1 { "B" } else { "W" }.to_string());
+        options.ko_point = board.ko_point().map(|(x, y)| (x as i32, y as i32));
+        let chosen = genmove(board.to_signmap(), options.clone(), randomize_top_k)?;
+        board.play(chosen.x, chosen.y, player)?;
+        if chosen.is_pass {
+            consecutive_passes += 1;
+        } else {
+            consecutive_passes = 0;
+        }
+        let played = HistoryMove {
+            color: player,
+            x: chosen.x,
+            y: chosen.y,
+        };
+        options.history.push(played.clone());
+        moves.push(played);
+        player = -player;
+    }
+
+    options.next_to_play = Some(if player == 1 { "B" } else { "W" }.to_string());
+    options.ko_point = board.ko_point().map(|(x, y)| (x as i32, y as i32));
+    let final_board = board.to_signmap();
+    let result = analyze_cached(final_board.clone(), options)?;
+    Ok(SelfPlayGame {
+        finished: consecutive_passes >= 2,
+        moves,
+        final_board,
+        win_rate: result.win_rate,
+        score_lead: result.score_lead,
+    })
+}
+
+/// One-ply lookahead: expand the top `playouts` legal policy moves, evaluate each
+/// resulting position in one batch and rank the moves by their value
+///
+/// This is not a tree search, but it corrects policy moves that look natural and
+/// lose on the spot, which improves the ranking in tactical positions.
+/// `playouts` is capped at the number of policy suggestions.
+/// Values are reported from `options.orient_to`'s perspective, like [`analyze_position`]
+pub fn analyze_searched(
+    sign_map: Vec<Vec<i8>>,
+    options: AnalysisOptions,
+    playouts: usize,
+) -> Result<SearchedAnalysis, String> {
+    validate_orient_to(options.orient_to)?;
+    let size = sign_map.len();
+    let pool = active_pool()?;
+    let mut engine = pool.checkout_for(options.deterministic, options.priority)?;
+    let root = engine.analyze(&sign_map, &options)?;
+    let player: i8 = if root.current_turn == "B" { 1 } else { -1 };
+
+    let mut board = replay_board(&sign_map, &options)?;
+    let mut candidates = Vec::new();
+    let mut children = Vec::new();
+    for suggestion in &root.move_suggestions {
+        if children.len() >= playouts.max(1) {
+            break;
+        }
+        let point = parse_gtp_move(&suggestion.move_str, size).unwrap_or((-1, -1));
+        if board.play(point.0, point.1, player).is_err() {
+            continue;
+        }
+        let child_options = options_after_move(&options, &board, player, point);
+        children.push((board.to_signmap(), child_options));
+        board.undo();
+        candidates.push(suggestion);
+    }
+
+    let results = engine.analyze_batch(&children)?;
+    let mut searched_moves: Vec<SearchedMove> = candidates
+        .iter()
+        .zip(&results)
+        .map(|(suggestion, result)| SearchedMove {
+            move_str: suggestion.move_str.clone(),
+            prior: suggestion.probability,
+            win_rate: result.win_rate,
+            score_lead: result.score_lead,
+        })
+        .collect();
+    // Best for the player to move first, breaking win rate ties by score
+    let sign = player as f32;
+    searched_moves.sort_by(|a, b| {
+        (sign * b.win_rate)
+            .total_cmp(&(sign * a.win_rate))
+            .then((sign * b.score_lead).total_cmp(&(sign * a.score_lead)))
+    });
+
+    let (win_rate, score_lead) = searched_moves
+        .first()
+        .map_or((root.win_rate, root.score_lead), |best| {
+            (best.win_rate, best.score_lead)
+        });
+    let mut analysis = SearchedAnalysis {
+        policy: root,
+        searched_moves,
+        win_rate,
+        score_lead,
+    };
+    analysis.orient(options.orient_to)?;
+    Ok(analysis)
+}
+
+/// Default number of analysis results kept for repeated positions
+const DEFAULT_ANALYSIS_CACHE_CAPACITY: usize = 256;
+
+/// Maximum number of cached analysis results, 0 disables the cache
+static ANALYSIS_CACHE_CAPACITY: AtomicUsize = AtomicUsize::new(DEFAULT_ANALYSIS_CACHE_CAPACITY);
+
+/// Recently returned analysis results, most recent last
+static ANALYSIS_CACHE: Mutex<Vec<(AnalysisCacheKey, AnalysisResult)>> = Mutex::new(Vec::new());
+
+/// Bumped whenever the cache is cleared, so an analysis that started before a model
+/// switch can't store its result from the previous model afterwards
+static ANALYSIS_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Everything that affects the output of [`analyze_position`]
+#[derive(PartialEq)]
+struct AnalysisCacheKey {
+    hash: u64,
+    sign_map: Vec<Vec<i8>>,
+    /// The options as JSON, with the player to move resolved
+    options: String,
+}
+
+impl AnalysisCacheKey {
+    fn new(sign_map: &[Vec<i8>], options: &AnalysisOptions) -> Option<Self> {
+        // Cached results are stored from Black's perspective and oriented on the way out
+        let mut options = AnalysisOptions {
+            orient_to: None,
+            ..options.clone()
+        };
+        options.next_to_play = Some(
+            if next_player(sign_map, &options) == 1 {
+                "B"
+            } else {
+                "W"
+            }
+            .to_string(),
+        );
+        let options = serde_json::to_string(&options).ok()?;
+
+        // Zobrist-style hash of the stones, mixed with a hash of the options
+        let mut hash = splitmix64(sign_map.len() as u64);
+        for (i, &stone) in sign_map.iter().flatten().enumerate() {
+            if stone != 0 {
+                hash ^= splitmix64(0x100 + ((i as u64) << 1 | u64::from(stone == 1)));
+            }
+        }
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        std::hash::Hash::hash(&options, &mut hasher);
+        hash ^= std::hash::Hasher::finish(&hasher);
+
+        Some(Self {
+            hash,
+            sign_map: sign_map.to_vec(),
+            options,
+        })
+    }
+}
+
+/// SplitMix64 finalizer, used to derive Zobrist keys without a table
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Look up a cached result, marking it as the most recently used
+fn cached_analysis(key: &AnalysisCacheKey) -> Option<AnalysisResult> {
+    let mut cache = ANALYSIS_CACHE.lock().unwrap();
+    let i = cache
+        .iter()
+        .position(|(k, _)| k.hash == key.hash && k == key)?;
+    let entry = cache.remove(i);
+    let result = entry.1.clone();
+    cache.push(entry);
+    Some(result)
+}
+
+/// Store a result, evicting the least recently used entries beyond the capacity
+/// `generation` is the [`ANALYSIS_GENERATION`] read before the analysis picked its
+/// engine; the result is dropped if the cache was cleared since
+fn store_analysis(key: AnalysisCacheKey, generation: u64, result: &AnalysisResult) {
+    let capacity = ANALYSIS_CACHE_CAPACITY.load(Ordering::Relaxed);
+    if capacity == 0 || result.had_invalid_outputs {
+        return;
+    }
+    let mut cache = ANALYSIS_CACHE.lock().unwrap();
+    if ANALYSIS_GENERATION.load(Ordering::SeqCst) != generation {
+        return;
+    }
+    cache.retain(|(k, _)| k != &key);
+    while cache.len() >= capacity {
+        cache.remove(0);
+    }
+    cache.push((key, result.clone()));
+}
+
+/// Set how many analysis results are cached, 0 disables the cache
+pub fn set_analysis_cache_capacity(capacity: usize) {
+    ANALYSIS_CACHE_CAPACITY.store(capacity, Ordering::Relaxed);
+    let mut cache = ANALYSIS_CACHE.lock().unwrap();
+    let excess = cache.len().saturating_sub(capacity);
+    cache.drain(..excess);
+}
+
+/// Drop every cached analysis result
+/// Analyses already running when the cache is cleared don't store their results,
+/// as they may come from a model that was just replaced
+pub fn clear_analysis_cache() {
+    ANALYSIS_GENERATION.fetch_add(1, Ordering::SeqCst);
+    ANALYSIS_CACHE.lock().unwrap().clear();
+}
+
+/// File the last analyzed position is saved to, None while autosave is off
+static AUTOSAVE_PATH: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// A position saved by autosave, to resume after a crash
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SavedPosition {
+    pub sign_map: Vec<Vec<i8>>,
+    pub options: AnalysisOptions,
+}
+
+/// Save every position passed to [`analyze_position`] to `path`, or stop with None
+/// Stopping deletes the saved position
+pub fn set_autosave_path(path: Option<PathBuf>) {
+    let mut autosave = AUTOSAVE_PATH.lock().unwrap();
+    if path.is_none() {
+        if let Some(old) = autosave.as_ref() {
+            let _ = std::fs::remove_file(old);
+        }
+    }
+    *autosave = path;
+}
+
+/// Read a position saved by autosave, None if there is none or it can't be parsed
+pub fn read_saved_position(path: &Path) -> Option<SavedPosition> {
+    let contents = std::fs::read_to_string(path).ok()?;
+    serde_json::from_str(&contents)
+        .inspect_err(|e| log::warn!("[OnnxEngine] Ignoring unreadable saved position: {}", e))
+        .ok()
+}
+
+/// Delete the autosaved position, called on clean shutdown
+pub fn clear_saved_position() {
+    if let Some(path) = AUTOSAVE_PATH.lock().unwrap().as_ref() {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+/// Write the position to the autosave file, if autosave is on
+/// Failures are only logged, they must not fail the analysis
+fn autosave_position(sign_map: &[Vec<i8>], options: &AnalysisOptions) {
+    // Don't hold the lock while writing, concurrent analyses would queue behind the disk
+    let Some(path) = AUTOSAVE_PATH.lock().unwrap().clone() else {
+        return;
+    };
+    let saved = SavedPosition {
+        sign_map: sign_map.to_vec(),
+        options: options.clone(),
+    };
+    let result = serde_json::to_string(&saved)
+        .map_err(|e| e.to_string())
+        .and_then(|contents| {
+            write_atomically(&path, contents.as_bytes()).map_err(|e| e.to_string())
+        });
+    if let Err(e) = result {
+        log::warn!(
+            "[OnnxEngine] Failed to autosave position to {:?}: {}",
+            path,
+            e
+        );
+    }
+}
+
+/// Counter giving each [`write_atomically`] call its own temporary file
+static TEMP_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Write `contents` to a temporary file next to `path`, then rename it over `path`,
+/// so a crash mid-write never leaves a truncated file behind
+fn write_atomically(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(format!(
+        ".{}.{}.tmp",
+        std::process::id(),
+        TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let temp_path = path.with_file_name(temp_name);
+    std::fs::write(&temp_path, contents)
+        .and_then(|_| std::fs::rename(&temp_path, path))
+        .inspect_err(|_| {
+            let _ = std::fs::remove_file(&temp_path);
+        })
+}
+
+/// Analyze a single position, oriented to `options.orient_to`
+/// Repeated positions with identical options are answered from the analysis cache
+/// With autosave on, the position is saved before it is analyzed
+pub fn analyze_position(
+    sign_map: Vec<Vec<i8>>,
+    options: AnalysisOptions,
+) -> Result<AnalysisResult, OnnxError> {
+    validate_orient_to(options.orient_to)?;
+    autosave_position(&sign_map, &options);
+    let orient_to = options.orient_to;
+    let mut result = analyze_cached(sign_map, options)?;
+    result.orient(orient_to)?;
+    Ok(result)
+}
+
+/// Analyze a single position from Black's perspective, whatever `options.orient_to` says,
+/// going through the analysis cache
+/// A stale engine is re-initialized with the current provider first
+fn analyze_cached(
+    sign_map: Vec<Vec<i8>>,
+    options: AnalysisOptions,
+) -> Result<AnalysisResult, OnnxError> {
+    reinit_if_stale()?;
+    // Read before picking the engine: every model switch installs the new engine first
+    // and clears the cache after, so a result from the old engine is never stored
+    let generation = ANALYSIS_GENERATION.load(Ordering::SeqCst);
+    let pool = active_pool()?;
+    validate_sign_map(&sign_map)?;
+    pool.check_board_size(sign_map.len())?;
+    let key = if ANALYSIS_CACHE_CAPACITY.load(Ordering::Relaxed) > 0 {
+        AnalysisCacheKey::new(&sign_map, &options)
+    } else {
+        None
+    };
+    if let Some(result) = key.as_ref().and_then(cached_analysis) {
+        STATS.cache_hits.fetch_add(1, Ordering::Relaxed);
+        return Ok(result);
+    }
+    if key.is_some() {
+        STATS.cache_misses.fetch_add(1, Ordering::Relaxed);
+    }
+    let mut engine = pool.checkout_for(options.deterministic, options.priority)?;
+    let result = engine.analyze(&sign_map, &options)?;
+    if let Some(key) = key {
+        store_analysis(key, generation, &result);
+    }
+    Ok(result)
+}
+
+/// A position reached by playing moves from a base position, with its analysis
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PositionAnalysis {
+    /// The board after the moves, captures resolved
+    pub sign_map: Vec<Vec<i8>>,
+    pub result: AnalysisResult,
+}
+
+/// Play `moves` on `sign_map` with captures and simple ko, then analyze the result
+/// The moves are appended to `options.history` and the player after the last move is
+/// to play; fails on the first illegal move, naming its 1-based number
+pub fn analyze_after_moves(
+    sign_map: Vec<Vec<i8>>,
+    moves: Vec<HistoryMove>,
+    mut options: AnalysisOptions,
+) -> Result<PositionAnalysis, OnnxError> {
+    validate_sign_map(&sign_map)?;
+    let mut board = replay_board(&sign_map, &options).map_err(OnnxError::InvalidBoard)?;
+    for (i, m) in moves.iter().enumerate() {
+        board
+            .play(m.x, m.y, m.color)
+            .map_err(|e| OnnxError::InvalidBoard(format!("Illegal move {}: {}", i + 1, e)))?;
+    }
+    if let Some(last) = moves.last() {
+        options.next_to_play = Some(if last.color == 1 { "W" } else { "B" }.to_string());
+    }
+    options.history.extend(moves);
+    options.ko_point = board.ko_point().map(|(x, y)| (x as i32, y as i32));
+
+    let sign_map = board.to_signmap();
+    let result = analyze_position(sign_map.clone(), options)?;
+    Ok(PositionAnalysis { sign_map, result })
+}
+
+/// Input tensors built for a position, to check featurization against a reference featurizer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeatureDump {
+    /// Player the position was featurized for ('B' or 'W')
+    pub next_player: String,
+    /// Shape of the spatial input, [1, C, H, W]
+    pub bin_shape: Vec<usize>,
+    /// Shape of the global input, [1, F]
+    pub global_shape: Vec<usize>,
+    /// Sum of each spatial channel
+    pub bin_channel_sums: Vec<f32>,
+    /// The global input values
+    pub global_input: Vec<f32>,
+    /// The spatial input values in [C, H, W] order, only when the full dump is requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bin_input: Option<Vec<f32>>,
+}
+
+/// Featurize a position without running the model
+/// Only per-channel sums of the spatial input are returned unless `full` is set
+pub fn debug_featurize(
+    sign_map: Vec<Vec<i8>>,
+    options: AnalysisOptions,
+    full: bool,
+) -> Result<FeatureDump, OnnxError> {
+    validate_sign_map(&sign_map)?;
+    let pool = active_pool()?;
+    let mut engine = pool.checkout();
+    engine.board_size = sign_map.len();
+    let pla = next_player(&sign_map, &options);
+    let (bin_input, global_input) = engine.featurize(&sign_map, pla, &options);
+
+    Ok(FeatureDump {
+        next_player: if pla == 1 { "B" } else { "W" }.to_string(),
+        bin_shape: bin_input.shape().to_vec(),
+        global_shape: global_input.shape().to_vec(),
+        bin_channel_sums: bin_input
+            .axis_iter(Axis(1))
+            .map(|channel| channel.sum())
+            .collect(),
+        global_input: global_input.iter().copied().collect(),
+        bin_input: full.then(|| bin_input.iter().copied().collect()),
+    })
+}
+
+/// A tensor passed to or returned from [`run_raw`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TensorData {
+    pub shape: Vec<i64>,
+    /// Values in row-major order
+    pub data: Vec<f32>,
+}
+
+/// Run the loaded model on raw input tensors keyed by input name, returning every output
+/// Bypasses featurization and result processing, so any ONNX model can be run
+/// through the engine's sessions for debugging
+pub fn run_raw(
+    inputs: HashMap<String, TensorData>,
+) -> Result<HashMap<String, TensorData>, OnnxError> {
+    let pool = active_pool()?;
+    let mut engine = pool.checkout();
+    engine.run_raw(inputs)
+}
+
+/// Cancellation flags of running requests, keyed by request ID
+static ACTIVE_REQUESTS: Mutex<Option<HashMap<String, Arc<AtomicBool>>>> = Mutex::new(None);
+
+/// Cancellation flag for a long-running request, unregistered when dropped
+///
+/// Inference runs cannot be interrupted, so the flag is checked between
+/// sub-batches and positions.
+pub struct CancelToken {
+    request_id: Option<String>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    /// Register a token that `cancel_request(request_id)` can trigger
+    /// Without a request ID the token can never be cancelled
+    /// Fails if a running request already uses the ID, which it could no longer be cancelled by
+    pub fn register(request_id: Option<String>) -> Result<Self, OnnxError> {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        if let Some(id) = &request_id {
+            let mut requests = ACTIVE_REQUESTS.lock().unwrap();
+            let requests = requests.get_or_insert_with(HashMap::new);
+            if requests.contains_key(id) {
+                return Err(OnnxError::Inference(format!(
+                    "Request {} is already running",
+                    id
+                )));
+            }
+            requests.insert(id.clone(), cancelled.clone());
+        }
+        Ok(Self {
+            request_id,
+            cancelled,
+        })
+    }
+
+    /// Return an error if the request has been cancelled
+    pub fn check(&self) -> Result<(), OnnxError> {
+        if self.cancelled.load(Ordering::SeqCst) {
+            Err(OnnxError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Drop for CancelToken {
+    fn drop(&mut self) {
+        if let Some(id) = &self.request_id {
+            if let Some(requests) = ACTIVE_REQUESTS.lock().unwrap().as_mut() {
+                requests.remove(id);
+            }
+        }
+    }
+}
+
+/// How often a request blocked waiting for a session checks whether it was cancelled
+const CANCEL_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(50);
+
+/// Cancel a running request, returning false if no request has this ID
+pub fn cancel_request(request_id: &str) -> bool {
+    let requests = ACTIVE_REQUESTS.lock().unwrap();
+    match requests.as_ref().and_then(|r| r.get(request_id)) {
+        Some(cancelled) => {
+            cancelled.store(true, Ordering::SeqCst);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Cancel every running request, returning how many were cancelled
+pub fn cancel_all_requests() -> usize {
+    let requests = ACTIVE_REQUESTS.lock().unwrap();
+    let cancelled = requests.as_ref().map_or(0, |r| r.len());
+    for flag in requests.iter().flat_map(|r| r.values()) {
+        flag.store(true, Ordering::SeqCst);
+    }
+    cancelled
+}
+
+/// Analyze multiple positions in a batch, in sub-batches of at most [`get_max_batch_size`]
+/// Cancellation is honored while waiting for a free session and between sub-batches
+/// An empty batch returns no results, even when no model is loaded
+pub fn analyze_batch(
+    inputs: Vec<(Vec<Vec<i8>>, AnalysisOptions)>,
+    cancel: &CancelToken,
+) -> Result<Vec<AnalysisResult>, OnnxError> {
+    analyze_batch_chunked(&inputs, cancel, Some(get_max_batch_size()), |_, _| {})
+}
+
+/// Priority a batch is scheduled at, the highest among its positions
+fn batch_priority(inputs: &[(Vec<Vec<i8>>, AnalysisOptions)]) -> u8 {
+    inputs
+        .iter()
+        .map(|(_, options)| options.priority)
+        .max()
+        .unwrap_or(0)
+}
+
+/// Number of analysis requests waiting for a free session of the active engine
+pub fn queue_depth() -> usize {
+    active_pool().map_or(0, |pool| pool.queue_depth())
+}
+
+/// Orient each result of a batch to its own options' `orient_to`
+fn orient_batch(
+    results: &mut [AnalysisResult],
+    inputs: &[(Vec<Vec<i8>>, AnalysisOptions)],
+) -> Result<(), OnnxError> {
+    for (result, (_, options)) in results.iter_mut().zip(inputs) {
+        result.orient(options.orient_to)?;
+    }
+    Ok(())
+}
+
+/// Time `run` over `iterations` runs and return the median in milliseconds
+/// The median discards outliers such as scheduler hiccups or GPU clock ramp-up
+fn median_ms(
+    iterations: usize,
+    mut run: impl FnMut() -> Result<(), String>,
+) -> Result<f64, String> {
+    let mut samples = Vec::with_capacity(iterations.max(1));
+    for _ in 0..iterations.max(1) {
+        let start = Instant::now();
+        run()?;
+        samples.push(start.elapsed().as_secs_f64() * 1000.0);
+    }
+    samples.sort_by(|a, b| a.total_cmp(b));
+    let mid = samples.len() / 2;
+    Ok(if samples.len() % 2 == 0 {
+        (samples[mid - 1] + samples[mid]) / 2.0
+    } else {
+        samples[mid]
+    })
+}
+
+/// Benchmark the loaded model on a dummy 19x19 position, single and at each batch size
+/// Batch sizes must be between 1 and the maximum batch size
+pub fn benchmark(iterations: usize, batch_sizes: &[usize]) -> Result<BenchmarkResult, String> {
+    let max = get_max_batch_size();
+    if let Some(&size) = batch_sizes.iter().find(|&&size| size == 0 || size > max) {
+        return Err(format!(
+            "Invalid benchmark batch size {}, expected 1 to {}",
+            size, max
+        ));
+    }
+    let pool = active_pool()
+        .map_err(|_| "Engine not initialized, load a model before benchmarking".to_string())?;
+    let mut engine = pool.checkout();
+    engine.benchmark(iterations, batch_sizes)
+}
+
+/// Find the batch size with the best throughput for the loaded model
+/// The winner is used by [`analyze_batch_auto`] until another model is loaded
+pub fn autotune_batch() -> Result<AutotuneResult, String> {
+    let pool = active_pool()?;
+    let result = pool.checkout().autotune_batch()?;
+    log::info!(
+        "[OnnxEngine] Auto-tuned batch size: {}",
+        result.best_batch_size
+    );
+    pool.optimal_batch_size
+        .store(result.best_batch_size, Ordering::SeqCst);
+    Ok(result)
+}
+
+/// Analyze any number of positions, split into auto-tuned sub-batches
+pub fn analyze_batch_auto(
+    inputs: Vec<(Vec<Vec<i8>>, AnalysisOptions)>,
+    cancel: &CancelToken,
+) -> Result<Vec<AnalysisResult>, OnnxError> {
+    analyze_batch_chunked(&inputs, cancel, None, |_, _| {})
+}
+
+/// Analyze positions in auto-tuned sub-batches, calling `on_result(index, result)`
+/// for each position as soon as its sub-batch finishes
+pub fn analyze_batch_streaming(
+    inputs: Vec<(Vec<Vec<i8>>, AnalysisOptions)>,
+    cancel: &CancelToken,
+    mut on_result: impl FnMut(usize, &AnalysisResult),
+) -> Result<usize, OnnxError> {
+    let results = analyze_batch_chunked(&inputs, cancel, None, |start, chunk| {
+        for (i, result) in chunk.iter().enumerate() {
+            on_result(start + i, result);
+        }
+    })?;
+    Ok(results.len())
+}
+
+/// Analyze positions in sub-batches of `chunk_size` (None for the auto-tuned size),
+/// calling `on_chunk(start, results)` after each with the index of its first position
+/// The session is handed to any higher-priority request waiting between sub-batches
+/// Stops with [`OnnxError::Cancelled`] between sub-batches once `cancel` is triggered
+fn analyze_batch_chunked(
+    inputs: &[(Vec<Vec<i8>>, AnalysisOptions)],
+    cancel: &CancelToken,
+    chunk_size: Option<usize>,
+    mut on_chunk: impl FnMut(usize, &[AnalysisResult]),
+) -> Result<Vec<AnalysisResult>, OnnxError> {
+    // Earlier sub-batches are already reported when a later one fails, so bad options
+    // must be rejected up front
+    for (_, options) in inputs {
+        validate_orient_to(options.orient_to)?;
+    }
+    if inputs.is_empty() {
+        return Ok(vec![]);
+    }
+    let pool = active_pool()?;
+    for (sign_map, _) in inputs {
+        pool.check_board_size(sign_map.len())?;
+    }
+    // Capped so a sub-batch is a single inference call, which can't yield half way
+    let chunk_size = chunk_size
+        .unwrap_or_else(|| match pool.optimal_batch_size.load(Ordering::SeqCst) {
+            0 => DEFAULT_AUTO_BATCH_SIZE,
+            n => n,
+        })
+        .clamp(1, get_max_batch_size().max(1));
+    let deterministic = inputs.iter().any(|(_, options)| options.deterministic);
+    let priority = batch_priority(inputs);
+    let mut engine = pool.checkout_cancellable(deterministic, priority, Some(cancel))?;
+    let mut results = Vec::with_capacity(inputs.len());
+    for chunk in inputs.chunks(chunk_size) {
+        cancel.check()?;
+        // Let outranking requests run before the next sub-batch
+        if !deterministic && pool.is_outranked(priority) {
+            drop(engine);
+            engine = pool.checkout_cancellable(deterministic, priority, Some(cancel))?;
+            cancel.check()?;
+        }
+        let start = results.len();
+        results.extend(engine.analyze_batch(chunk)?);
+        orient_batch(&mut results[start..], chunk)?;
+        on_chunk(start, &results[start..]);
+    }
+    Ok(results)
+}
+
+/// Replay a game and analyze the position before the first move and after every move
+/// `on_progress(done, total)` is called after each sub-batch
+/// An empty move list yields just the initial position; passes are analyzed like
+/// any other move, so an all-pass game yields the initial position once per pass
+pub fn analyze_game(
+    moves: &[HistoryMove],
+    options: &GameAnalysisOptions,
+    cancel: &CancelToken,
+    mut on_progress: impl FnMut(usize, usize),
+) -> Result<GameAnalysis, OnnxError> {
+    let positions = game_positions(moves, options)?;
+    let total = positions.len();
+    let results = analyze_batch_chunked(&positions, cancel, None, |start, chunk| {
+        on_progress(start + chunk.len(), total)
+    })?;
+    let win_rate_deltas = results
+        .windows(2)
+        .map(|pair| pair[1].win_rate - pair[0].win_rate)
+        .collect();
+    Ok(GameAnalysis {
+        results,
+        win_rate_deltas,
+    })
+}
+
+/// A board and the options it is analyzed with
+type Position = (Vec<Vec<i8>>, AnalysisOptions);
+
+/// Positions of a game replayed from an empty board, the start position first
+fn game_positions(
+    moves: &[HistoryMove],
+    options: &GameAnalysisOptions,
+) -> Result<Vec<Position>, OnnxError> {
+    let size = options.board_size;
+    if size < 2 {
+        return Err(OnnxError::InvalidBoard(format!(
+            "Invalid board size: {}",
+            size
+        )));
+    }
+    let mut board = GoBoard::new(size).map_err(OnnxError::InvalidBoard)?;
+    let first_player = moves.first().map_or(1, |m| m.color);
+    let position_options = |history: &[HistoryMove], next: i8, board: &GoBoard| AnalysisOptions {
+        komi: options.komi,
+        next_to_play: Some(if next == 1 { "B" } else { "W" }.to_string()),
+        history: history.to_vec(),
+        ko_point: board.ko_point().map(|(x, y)| (x as i32, y as i32)),
+        // Fixed to Black so consecutive results, and the deltas between them, share one view
+        orient_to: Some(1),
+        ..AnalysisOptions::default()
+    };
+
+    let mut positions = Vec::with_capacity(moves.len() + 1);
+    positions.push((
+        board.to_signmap(),
+        position_options(&[], first_player, &board),
+    ));
+    for (i, m) in moves.iter().enumerate() {
+        board
+            .play(m.x, m.y, m.color)
+            .map_err(|e| OnnxError::InvalidBoard(format!("Illegal move {}: {}", i + 1, e)))?;
+        let next = moves.get(i + 1).map_or(-m.color, |next| next.color);
+        positions.push((
+            board.to_signmap(),
+            position_options(&moves[..=i], next, &board),
+        ));
+    }
+    Ok(positions)
+}
+
+/// Symmetry counts analyzed in turn while pondering
+const PONDER_SYMMETRY_STEPS: [usize; 4] = [1, 2, 4, 8];
+
+/// Incremented to cancel the running ponder task
+static PONDER_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Start pondering a position in the background, cancelling any previous ponder
+/// Re-analyzes with increasing symmetry counts, calling `on_update(symmetries, result)`
+/// after each refinement, and stops after the last step or when cancelled
+pub fn start_ponder(
+    sign_map: Vec<Vec<i8>>,
+    options: AnalysisOptions,
+    mut on_update: impl FnMut(usize, AnalysisResult) + Send + 'static,
+) -> Result<(), String> {
+    validate_orient_to(options.orient_to)?;
+    let pool = active_pool()?;
+    let generation = PONDER_GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+
+    std::thread::spawn(move || {
+        for symmetries in PONDER_SYMMETRY_STEPS {
+            if PONDER_GENERATION.load(Ordering::SeqCst) != generation {
+                return;
+            }
+            let options = AnalysisOptions {
+                symmetries,
+                ..options.clone()
+            };
+            let result = pool
+                .checkout_with_priority(options.priority)
+                .analyze(&sign_map, &options)
+                .and_then(|mut result| result.orient(options.orient_to).map(|_| result));
+            // Drop results that finished after a cancellation
+            if PONDER_GENERATION.load(Ordering::SeqCst) != generation {
+                return;
+            }
+            match result {
+                Ok(result) => on_update(symmetries, result),
+                Err(e) => {
+                    log::warn!("[OnnxEngine] Ponder analysis failed: {}", e);
+                    return;
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Stop the running ponder task, if any
+pub fn stop_ponder() {
+    PONDER_GENERATION.fetch_add(1, Ordering::SeqCst);
+}
+
+/// Dispose the global engine and any preloaded model
+///
+/// Sessions still checked out by in-flight analyses are released
+/// as soon as those analyses finish.
+pub fn dispose_engine() -> Result<(), String> {
+    stop_ponder();
+    *STANDBY_ENGINE.lock().unwrap() = None;
+    let mut global = ENGINE.write().map_err(|e| e.to_string())?;
+    *global = None;
+    ENGINE_STALE.store(false, Ordering::SeqCst);
+    clear_analysis_cache();
+    Ok(())
+}
+
+/// Return to a clean uninitialized state: cancel running requests, stop pondering,
+/// and dispose the engine, every model slot and the analysis cache
+/// Safe to call when nothing is loaded
+pub fn reset_engine() -> Result<(), String> {
+    let cancelled = cancel_all_requests();
+    MODEL_SLOTS.write().map_err(|e| e.to_string())?.clear();
+    dispose_engine()?;
+    log::info!(
+        "[OnnxEngine] Engine reset, {} running requests cancelled",
+        cancelled
+    );
+    Ok(())
+}
+
+/// Get the model file the active engine was loaded from
+/// Returns None if no engine is loaded or it was loaded from bytes
+pub fn get_loaded_model_path() -> Option<PathBuf> {
+    active_pool().ok()?.model_path.clone()
+}
+
+/// Get the model the active engine was loaded from
+/// Returns None if no engine is loaded or it was loaded from bytes
+pub fn get_loaded_model() -> Option<LoadedModel> {
+    let pool = active_pool().ok()?;
+    Some(LoadedModel {
+        id: pool.model_id.clone(),
+        path: pool.model_path.as_ref()?.to_string_lossy().to_string(),
+        sha256: pool.model_sha256.clone(),
+        config: pool.config.clone(),
+    })
+}
+
+/// Number of model slots available for ensemble analysis
+pub const MAX_MODEL_SLOTS: u8 = 3;
+
+/// A model loaded for ensemble analysis, independent of the main engine
+struct ModelSlot {
+    pool: OnnxSessionPool,
+    /// None if the model accepts any board size
+    board_size: Option<u32>,
+}
+
+/// Models loaded for ensemble analysis, by slot
+static MODEL_SLOTS: RwLock<BTreeMap<u8, Arc<ModelSlot>>> = RwLock::new(BTreeMap::new());
+
+/// One model's share of an ensemble analysis
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SlotAnalysis {
+    pub slot: u8,
+    pub result: AnalysisResult,
+}
+
+/// Result of analyzing a position with every loaded model slot
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EnsembleAnalysis {
+    /// Policy, value, score and ownership averaged across the slots
+    /// Timings are the totals over all slots
+    pub combined: AnalysisResult,
+    /// Each slot's own analysis, in slot order
+    pub slots: Vec<SlotAnalysis>,
+}
+
+/// Load a model into an ensemble slot, replacing the model already there
+/// Each slot gets a single session, separate from the main engine's pool
+pub fn initialize_slot(slot: u8, model_path: &str) -> Result<(), String> {
+    if slot >= MAX_MODEL_SLOTS {
+        return Err(format!(
+            "Invalid model slot {}, expected 0-{}",
+            slot,
+            MAX_MODEL_SLOTS - 1
+        ));
+    }
+    let path = Path::new(model_path);
+    let board_size = inspect_model(path)?.expected_board_size;
+
+    // A fixed-size model can't be ensembled with a model fixed to another size
+    if let Some(size) = board_size {
+        let slots = MODEL_SLOTS.read().map_err(|e| e.to_string())?;
+        let conflict = slots
+            .iter()
+            .find(|(&other, model)| other != slot && model.board_size.is_some_and(|s| s != size));
+        if let Some((other, model)) = conflict {
+            let other_size = model.board_size.unwrap_or_default();
+            return Err(format!(
+                "Model only supports {}x{} boards, but slot {} holds a {}x{} model",
+                size, size, other, other_size, other_size
+            ));
+        }
+    }
+
+    let pool = OnnxSessionPool::from_path(path, 1, false)?;
+    let mut slots = MODEL_SLOTS.write().map_err(|e| e.to_string())?;
+    slots.insert(slot, Arc::new(ModelSlot { pool, board_size }));
+    log::info!(
+        "[OnnxEngine] Loaded model into slot {}: {}",
+        slot,
+        model_path
+    );
+    Ok(())
+}
+
+/// Unload the model in an ensemble slot, if any
+pub fn dispose_slot(slot: u8) -> Result<(), String> {
+    let mut slots = MODEL_SLOTS.write().map_err(|e| e.to_string())?;
+    slots.remove(&slot);
+    Ok(())
+}
+
+/// Analyze a position with every loaded model slot and average the outputs
+/// Policy is averaged in probability space; fails if a slot's model doesn't
+/// support the board size
+pub fn analyze_ensemble(
+    sign_map: Vec<Vec<i8>>,
+    options: AnalysisOptions,
+) -> Result<EnsembleAnalysis, OnnxError> {
+    validate_sign_map(&sign_map)?;
+    validate_orient_to(options.orient_to)?;
+    let models: Vec<(u8, Arc<ModelSlot>)> = {
+        let slots = MODEL_SLOTS
+            .read()
+            .map_err(|e| OnnxError::Inference(e.to_string()))?;
+        slots
+            .iter()
+            .map(|(&slot, model)| (slot, model.clone()))
+            .collect()
+    };
+    if models.is_empty() {
+        return Err(OnnxError::NotInitialized);
+    }
+
+    let size = sign_map.len();
+    for (slot, model) in &models {
+        if let Some(expected) = model.board_size.filter(|&s| s as usize != size) {
+            return Err(OnnxError::InvalidBoard(format!(
+                "Model in slot {} only supports {}x{} boards, got {}x{}",
+                slot, expected, expected, size, size
+            )));
+        }
+    }
+
+    let mut engines = models
+        .iter()
+        .map(|(_, model)| {
+            model
+                .pool
+                .checkout_for(options.deterministic, options.priority)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut outputs = Vec::with_capacity(models.len());
+    let mut slots = Vec::with_capacity(models.len());
+    let mut next_pla = 1;
+    let mut featurize_ms = 0.0;
+    let mut postprocess_ms = 0.0;
+    for ((slot, _), engine) in models.iter().zip(engines.iter_mut()) {
+        let (results, pla, symmetries, slot_featurize_ms) = engine.infer(&sign_map, &options)?;
+        let postprocess_start = Instant::now();
+        let averaged = if symmetries == 1 {
+            results
+        } else {
+            average_symmetries(&results, symmetries, size)
+        };
+        let mut result = engine.process_results(&averaged, &sign_map, pla, &options)?;
+        result.orient(options.orient_to)?;
+        result.featurize_ms = slot_featurize_ms;
+        result.postprocess_ms = postprocess_start.elapsed().as_secs_f64() * 1000.0;
+        result.symmetries_used = symmetries;
+
+        featurize_ms += result.featurize_ms;
+        postprocess_ms += result.postprocess_ms;
+        next_pla = pla;
+        outputs.push(averaged);
+        slots.push(SlotAnalysis {
+            slot: *slot,
+            result,
+        });
+    }
+
+    let postprocess_start = Instant::now();
+    let mut combined =
+        engines[0].process_results(&average_models(&outputs), &sign_map, next_pla, &options)?;
+    combined.featurize_ms = featurize_ms;
+    combined.postprocess_ms = postprocess_ms + postprocess_start.elapsed().as_secs_f64() * 1000.0;
+    combined.symmetries_used = slots
+        .iter()
+        .map(|s| s.result.symmetries_used)
+        .min()
+        .unwrap_or(1);
+    combined.orient(options.orient_to)?;
+
+    Ok(EnsembleAnalysis { combined, slots })
+}
+
+/// Check if engine is initialized
+pub fn is_engine_initialized() -> bool {
+    ENGINE.read().map(|g| g.is_some()).unwrap_or(false)
+}
+
+/// Read a model's inputs and outputs without initializing a session
+/// Expects KataGo's layout: a [N, C, H, W] spatial input and a [N, F] global input
+pub fn inspect_model(model_path: &Path) -> Result<ModelMetadata, String> {
+    let info = crate::onnx_proto::read_model_info(model_path)?;
+
+    let bin_input = info
+        .inputs
+        .iter()
+        .find(|input| input.shape.len() == 4)
+        .ok_or("Model has no [N, C, H, W] spatial input")?;
+    let global_input = info
+        .inputs
+        .iter()
+        .find(|input| input.shape.len() == 2)
+        .ok_or("Model has no [N, F] global input")?;
+
+    let num_bin_channels = bin_input.shape[1]
+        .ok_or_else(|| format!("Input {} has a dynamic channel dimension", bin_input.name))?;
+    let num_global_features = global_input.shape[1].ok_or_else(|| {
+        format!(
+            "Input {} has a dynamic feature dimension",
+            global_input.name
+        )
+    })?;
+    let expected_board_size = model_board_size(&info);
+
+    Ok(ModelMetadata {
+        input_names: info.inputs.iter().map(|i| i.name.clone()).collect(),
+        output_names: info.outputs.iter().map(|o| o.name.clone()).collect(),
+        expected_board_size,
+        num_bin_channels: num_bin_channels as u32,
+        num_global_features: num_global_features as u32,
+        is_quantized: is_quantized_model(&info),
+        komi_scale: model_komi_scale(&info),
+    })
+}
+
+/// Get information about the current execution provider
+pub fn get_provider_info() -> Option<ExecutionProviderInfo> {
+    let pool = active_pool().ok()?;
+
+    let name = pool.get_provider_name();
+    let (is_gpu, description) = match name {
+        "cuda" => (true, "NVIDIA CUDA GPU acceleration"),
+        "tensorrt" => (true, "NVIDIA TensorRT GPU acceleration"),
+        "coreml" => (true, "Apple CoreML (Metal/Neural Engine)"),
+        "directml" => (true, "Windows DirectML GPU acceleration"),
+        "rocm" => (true, "AMD ROCm GPU acceleration"),
+        "nnapi" => (true, "Android NNAPI (Neural Networks API)"),
+        "cpu" => (false, "CPU (multi-threaded)"),
+        "auto" => {
+            // When auto is selected, we can't easily know which one is actually used
+            // ONNX Runtime doesn't provide a direct API for this
+            // We'll report it as "auto" with GPU likely
+            (true, "Auto-selected (GPU if available)")
+        }
+        _ => (false, "Unknown execution provider"),
+    };
+
+    let device_index = match name {
+        "cuda" | "tensorrt" | "rocm" | "directml" => Some(pool.device_index),
+        _ => None,
+    };
+
+    let (_, supports_fp16) = provider_capabilities(name);
+
+    Some(ExecutionProviderInfo {
+        name: name.to_string(),
+        is_gpu,
+        description: description.to_string(),
+        fallback_used: pool.fallback_used,
+        is_quantized: pool.is_quantized,
+        device_index,
+        device_name: pool.device_name.clone(),
+        display_name: provider_display_name(name, pool.device_name.as_deref()),
+        supports_fp16,
+        recommended: name == preference_to_name(recommended_provider()),
+        coreml_compute_units: (name == "coreml").then_some(pool.coreml_compute_units),
+        last_init_error: last_init_error(name),
+    })
+}
+
+/// Get the ONNX Runtime version and the execution providers it was built with
+pub fn get_runtime_info() -> Result<RuntimeInfo, String> {
+    use ExecutionProviderPreference::*;
+    ensure_ort_initialized()?;
+
+    let build_info = ort::info().to_string();
+    // The build info names the release branch, e.g. "git-branch=rel-1.22.0"
+    let ort_version = build_info
+        .split(',')
+        .find_map(|field| field.trim().strip_prefix("git-branch=rel-"))
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("1.{}.x", ort::MINOR_VERSION));
+
+    let compiled_providers = [TensorRt, Cuda, Rocm, DirectMl, CoreMl, Nnapi, Cpu]
+        .into_iter()
+        .filter(|&provider| is_provider_available(provider))
+        .map(preference_to_name)
+        .collect();
+
+    Ok(RuntimeInfo {
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        ort_version,
+        ort_build_info: build_info,
+        compiled_providers,
+        build_profile: if cfg!(debug_assertions) {
+            "debug"
+        } else {
+            "release"
+        }
+        .to_string(),
+    })
+}
+
+/// Get the device memory usage of the active execution provider
+///
+/// ORT does not expose its allocator statistics, so CUDA and TensorRT are
+/// queried through `nvidia-smi` for the whole device. Other providers
+/// report no device figures.
+pub fn get_memory_info() -> Option<MemoryInfo> {
+    let pool = active_pool().ok()?;
+    let provider = pool.get_provider_name().to_string();
+    let (device_used_bytes, device_total_bytes) = match provider.as_str() {
+        "cuda" | "tensorrt" => query_nvidia_memory(pool.device_index).unzip(),
+        _ => (None, None),
+    };
+    Some(MemoryInfo {
+        provider,
+        device_used_bytes,
+        device_total_bytes,
+    })
+}
+
+/// Run an `nvidia-smi --query-gpu` query, returning one output line per GPU
+fn query_nvidia_smi(fields: &str, device_index: Option<u32>) -> Option<Vec<String>> {
+    let mut command = std::process::Command::new("nvidia-smi");
+    command.args([
+        format!("--query-gpu={}", fields),
+        "--format=csv,noheader,nounits".to_string(),
+    ]);
+    if let Some(index) = device_index {
+        command.arg(format!("--id={}", index));
+    }
+    #[cfg(target_os = "windows")]
+    {
+        // CREATE_NO_WINDOW, so polling doesn't flash a console window
+        use std::os::windows::process::CommandExt;
+        command.creation_flags(0x0800_0000);
+    }
+    let output = command.output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Some(stdout.lines().map(|line| line.trim().to_string()).collect())
+}
+
+/// Names of the NVIDIA GPUs in device index order, None if `nvidia-smi` is unavailable
+///
+/// `nvidia-smi` orders GPUs by PCI bus, which matches CUDA device indices
+/// unless `CUDA_DEVICE_ORDER` is left at its fastest-first default on a
+/// machine with mixed GPU models.
+pub fn nvidia_device_names() -> Option<Vec<String>> {
+    query_nvidia_smi("name", None)
+}
+
+/// Query (used, total) memory of an NVIDIA GPU in bytes
+fn query_nvidia_memory(device_index: u32) -> Option<(u64, u64)> {
+    let lines = query_nvidia_smi("memory.used,memory.total", Some(device_index))?;
+    // Values are in MiB, e.g. "1234, 6144"
+    let (used, total) = lines.first()?.split_once(',')?;
+    let mib = |s: &str| s.trim().parse::<u64>().ok().map(|v| v * 1024 * 1024);
+    Some((mib(used)?, mib(total)?))
+}
+
+/// Whether the ORT build can register a provider on this platform
+fn is_provider_available(provider: ExecutionProviderPreference) -> bool {
+    use ExecutionProviderPreference::*;
+    match provider {
+        Auto | Cpu => true,
+        Nnapi => cfg!(target_os = "android"),
+        Cuda => CUDAExecutionProvider::default()
+            .is_available()
+            .unwrap_or(false),
+        TensorRt => TensorRTExecutionProvider::default()
+            .is_available()
+            .unwrap_or(false),
+        Rocm => {
+            cfg!(feature = "rocm")
+                && ROCmExecutionProvider::default()
+                    .is_available()
+                    .unwrap_or(false)
+        }
+        DirectMl => DirectMLExecutionProvider::default()
+            .is_available()
+            .unwrap_or(false),
+        CoreMl => CoreMLExecutionProvider::default()
+            .is_available()
+            .unwrap_or(false),
+    }
+}
+
+/// The provider `Auto` would pick, the first available one in its fallback chain
+fn recommended_provider() -> ExecutionProviderPreference {
+    fallback_chain(ExecutionProviderPreference::Auto)
+        .into_iter()
+        .find(|&provider| is_provider_available(provider))
+        .unwrap_or(ExecutionProviderPreference::Cpu)
+}
+
+/// Short label and fp16 support of a provider, by name
+fn provider_capabilities(name: &str) -> (&'static str, bool) {
+    match name {
+        "cuda" => ("CUDA", true),
+        "tensorrt" => ("TensorRT", true),
+        "rocm" => ("ROCm", true),
+        "directml" => ("DirectML", true),
+        "coreml" => ("CoreML", true),
+        "nnapi" => ("NNAPI", true),
+        "cpu" => ("CPU", false),
+        "auto" => ("Auto", true),
+        _ => ("Unknown", false),
+    }
+}
+
+/// Picker label such as "NVIDIA GeForce RTX 4070 (CUDA)", or the bare label without a device name
+fn provider_display_name(name: &str, device_name: Option<&str>) -> String {
+    let (label, _) = provider_capabilities(name);
+    match device_name {
+        Some(device) => format!("{} ({})", device, label),
+        None => label.to_string(),
+    }
+}
+
+/// Build the listing entry for a provider
+fn provider_entry(
+    provider: ExecutionProviderPreference,
+    is_gpu: bool,
+    description: &str,
+    recommended: ExecutionProviderPreference,
+) -> ExecutionProviderInfo {
+    let name = preference_to_name(provider);
+    let settings = get_provider_settings();
+    let device_index = settings.device_index;
+    let device_name = match provider {
+        ExecutionProviderPreference::Cuda | ExecutionProviderPreference::TensorRt => {
+            nvidia_device_names().and_then(|names| names.into_iter().nth(device_index as usize))
+        }
+        _ => None,
+    };
+    let (_, supports_fp16) = provider_capabilities(&name);
+    let last_init_error = last_init_error(&name);
+    ExecutionProviderInfo {
+        display_name: provider_display_name(&name, device_name.as_deref()),
+        supports_fp16,
+        recommended: provider != ExecutionProviderPreference::Auto && provider == recommended,
+        name,
+        is_gpu,
+        description: description.to_string(),
+        fallback_used: false,
+        is_quantized: false,
+        device_index: device_name.as_ref().map(|_| device_index),
+        device_name,
+        coreml_compute_units: (provider == ExecutionProviderPreference::CoreMl)
+            .then_some(settings.coreml_compute_units),
+        last_init_error,
+    }
 }
 
-/// Global engine instance (lazy loaded)
-static ENGINE: Mutex<Option<OnnxEngine>> = Mutex::new(None);
+/// Get available execution providers for this platform
+pub fn get_available_providers() -> Vec<ExecutionProviderInfo> {
+    use ExecutionProviderPreference::*;
+    let recommended = recommended_provider();
+    let mut providers = vec![];
 
-impl OnnxEngine {
-    /// Create a new ONNX engine from a model file
-    pub fn new(model_path: &Path) -> Result<Self, String> {
-        // Ensure ONNX Runtime is initialized (required for load-dynamic on Android)
-        ensure_ort_initialized()?;
-        
-        let preference = get_execution_provider_preference();
-        let provider_name = preference_to_name(preference);
-        
-        let builder = Session::builder()
-            .map_err(|e| format!("Failed to create session builder: {}", e))?;
-        
-        // Configure execution providers based on preference and platform
-        let builder = configure_execution_providers(builder, preference)?;
-        
-        // Common optimizations
-        // Note: On Android, we use fewer threads to be more battery-friendly
-        #[cfg(target_os = "android")]
-        let num_threads = 2;
-        #[cfg(not(target_os = "android"))]
-        let num_threads = 4;
-        
-        let session = builder
-            .with_optimization_level(GraphOptimizationLevel::Level3)
-            .map_err(|e| format!("Failed to set optimization level: {}", e))?
-            .with_intra_threads(num_threads)
-            .map_err(|e| format!("Failed to set intra threads: {}", e))?
-            .commit_from_file(model_path)
-            .map_err(|e| format!("Failed to load model from {:?}: {}", model_path, e))?;
+    // Auto is always available
+    providers.push(provider_entry(
+        Auto,
+        true,
+        "Auto-select best available (recommended)",
+        recommended,
+    ));
 
-        // Detect if model uses fp16 inputs by checking first input's type
-        let is_fp16 = session.inputs.first().map_or(false, |input| {
-            let type_str = format!("{:?}", input.input_type);
-            eprintln!("[OnnxEngine] Input type: {}", type_str);
-            type_str.contains("Float16") || type_str.contains("float16") || type_str.contains("f16")
-        });
-        eprintln!("[OnnxEngine] Detected fp16 model: {}", is_fp16);
+    // Platform-specific GPU providers
+    #[cfg(target_os = "android")]
+    providers.push(provider_entry(
+        Nnapi,
+        true,
+        "Android NNAPI (Neural Networks API)",
+        recommended,
+    ));
 
-        Ok(Self {
-            session,
-            board_size: 19,
-            provider_name,
-            is_fp16,
-        })
+    #[cfg(target_os = "macos")]
+    providers.push(provider_entry(
+        CoreMl,
+        true,
+        "Apple CoreML (Metal/Neural Engine)",
+        recommended,
+    ));
+
+    #[cfg(target_os = "windows")]
+    {
+        providers.push(provider_entry(
+            DirectMl,
+            true,
+            "DirectML (Windows GPU)",
+            recommended,
+        ));
+        providers.push(provider_entry(
+            Cuda,
+            true,
+            "NVIDIA CUDA (requires CUDA toolkit)",
+            recommended,
+        ));
     }
 
-    /// Create a new ONNX engine from model bytes
-    pub fn from_bytes(model_bytes: &[u8]) -> Result<Self, String> {
-        // Ensure ONNX Runtime is initialized (required for load-dynamic on Android)
-        ensure_ort_initialized()?;
-        
-        let preference = get_execution_provider_preference();
-        let provider_name = preference_to_name(preference);
-        
-        let builder = Session::builder()
-            .map_err(|e| format!("Failed to create session builder: {}", e))?;
-        
-        // Configure execution providers based on preference and platform
-        let builder = configure_execution_providers(builder, preference)?;
-        
-        // Common optimizations
-        #[cfg(target_os = "android")]
-        let num_threads = 2;
-        #[cfg(not(target_os = "android"))]
-        let num_threads = 4;
-        
-        let session = builder
-            .with_optimization_level(GraphOptimizationLevel::Level3)
-            .map_err(|e| format!("Failed to set optimization level: {}", e))?
-            .with_intra_threads(num_threads)
-            .map_err(|e| format!("Failed to set intra threads: {}", e))?
-            .commit_from_memory(model_bytes)
-            .map_err(|e| format!("Failed to load model from bytes: {}", e))?;
+    #[cfg(target_os = "linux")]
+    providers.push(provider_entry(
+        Cuda,
+        true,
+        "NVIDIA CUDA (requires CUDA toolkit)",
+        recommended,
+    ));
 
-        // Detect if model uses fp16 inputs by checking first input's type
-        let is_fp16 = session.inputs.first().map_or(false, |input| {
-            let type_str = format!("{:?}", input.input_type);
-            eprintln!("[OnnxEngine from_bytes] Input type: {}", type_str);
-            type_str.contains("Float16") || type_str.contains("float16") || type_str.contains("f16")
-        });
-        eprintln!("[OnnxEngine from_bytes] Detected fp16 model: {}", is_fp16);
+    // ROCm is only listed when built with the `rocm` feature and the ORT build includes it
+    #[cfg(target_os = "linux")]
+    if is_provider_available(Rocm) {
+        providers.push(provider_entry(
+            Rocm,
+            true,
+            "AMD ROCm (requires ROCm libraries)",
+            recommended,
+        ));
+    }
 
-        Ok(Self {
-            session,
-            board_size: 19,
-            provider_name,
-            is_fp16,
-        })
+    // TensorRT is only listed when the ORT build includes it
+    #[cfg(any(target_os = "windows", target_os = "linux"))]
+    if is_provider_available(TensorRt) {
+        providers.push(provider_entry(
+            TensorRt,
+            true,
+            "NVIDIA TensorRT (requires TensorRT libraries)",
+            recommended,
+        ));
     }
-    
-    /// Get the name of the active execution provider
-    pub fn get_provider_name(&self) -> &str {
-        &self.provider_name
+
+    // CPU is always available
+    providers.push(provider_entry(
+        Cpu,
+        false,
+        "CPU only (most compatible)",
+        recommended,
+    ));
+
+    providers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Network outputs for one position with the given policy logits and a neutral value
+    fn single_outputs(policy: Vec<f32>) -> OnnxOutputs {
+        OnnxOutputs {
+            policy_dims: vec![1, policy.len()],
+            policy,
+            value: vec![0.0; 3],
+            miscvalue: vec![0.0; 10],
+            moremiscvalue: None,
+            ownership: None,
+            inference_ms: 0.0,
+        }
     }
 
-    /// Analyze a single position
-    pub fn analyze(
-        &mut self,
-        sign_map: &[Vec<i8>],
-        options: &AnalysisOptions,
-    ) -> Result<AnalysisResult, String> {
-        self.board_size = sign_map.len();
+    /// Black just took a ko at C4, so White may not retake at B4; A5 is suicide for White
+    /// ```text
+    /// . B W . .
+    /// B . B W .
+    /// . B W . .
+    /// ```
+    fn white_after_ko_capture() -> (Vec<Vec<i8>>, AnalysisOptions) {
+        let mut sign_map = vec![vec![0i8; 5]; 5];
+        for (x, y) in [(1, 0), (0, 1), (2, 1), (1, 2)] {
+            sign_map[y][x] = 1;
+        }
+        for (x, y) in [(2, 0), (3, 1), (2, 2)] {
+            sign_map[y][x] = -1;
+        }
+        let options = AnalysisOptions {
+            next_to_play: Some("W".to_string()),
+            ko_point: Some((1, 1)),
+            ..AnalysisOptions::default()
+        };
+        (sign_map, options)
+    }
 
-        // Determine next player
-        let next_pla: i8 = match &options.next_to_play {
-            Some(s) if s == "W" => -1,
-            Some(_) => 1,
-            None => {
-                // Count stones to determine
-                let (mut black, mut white) = (0, 0);
-                for row in sign_map {
-                    for &s in row {
-                        if s == 1 {
-                            black += 1;
-                        } else if s == -1 {
-                            white += 1;
-                        }
-                    }
-                }
-                if black == white {
-                    1
-                } else {
-                    -1
-                }
-            }
+    #[test]
+    fn filling_the_last_liberty_is_legal_when_it_captures() {
+        let mut sign_map = vec![vec![0i8; 5]; 5];
+        for (x, y) in [(1, 0), (0, 1)] {
+            sign_map[y][x] = -1;
+        }
+        for (x, y) in [(2, 0), (1, 1), (0, 2)] {
+            sign_map[y][x] = 1;
+        }
+        assert!(is_legal_move(&sign_map, 0, 0, 1, None));
+        assert!(!is_legal_move(&sign_map, 1, 0, 1, None));
+        assert!(!is_legal_move(&sign_map, 5, 0, 1, None));
+    }
+
+    #[test]
+    fn suicide_and_ko_recapture_are_illegal() {
+        let (sign_map, options) = white_after_ko_capture();
+        assert!(!is_legal_move(&sign_map, 0, 0, -1, None));
+        assert!(!is_legal_move(&sign_map, 1, 1, -1, options.ko_point));
+        assert!(is_legal_move(&sign_map, 1, 1, -1, None));
+        assert!(is_legal_move(&sign_map, 4, 4, -1, options.ko_point));
+    }
+
+    #[test]
+    fn suggestions_skip_occupied_suicide_and_ko_points() {
+        let (sign_map, options) = white_after_ko_capture();
+        let mut policy = vec![0.0; 26];
+        // Favor the ko retake, the suicide point and an occupied point
+        for idx in [6, 0, 1] {
+            policy[idx] = 10.0;
+        }
+        let item = BatchItem {
+            sign_map: &sign_map,
+            pla: -1,
+            options: &options,
         };
+        let result = process_batch_results(5, &single_outputs(policy), &[item]).unwrap();
 
-        // Featurize
-        let (bin_input, global_input) =
-            self.featurize(sign_map, next_pla, options.komi, &options.history);
+        let moves: Vec<&str> = result[0]
+            .move_suggestions
+            .iter()
+            .map(|s| s.move_str.as_str())
+            .collect();
+        assert_eq!(moves.len(), 10);
+        for masked in ["B4", "A5", "B5"] {
+            assert!(
+                !moves.contains(&masked),
+                "{} suggested in {:?}",
+                masked,
+                moves
+            );
+        }
+        // 16 legal points and pass share the probability evenly
+        for suggestion in &result[0].move_suggestions {
+            assert!((suggestion.probability - 1.0 / 17.0).abs() < 1e-6);
+        }
+    }
 
-        // Run inference
-        let results = self.run_inference(&bin_input, &global_input, 1)?;
+    #[test]
+    fn unusable_policy_is_uniform_over_legal_moves() {
+        let (sign_map, options) = white_after_ko_capture();
+        let item = BatchItem {
+            sign_map: &sign_map,
+            pla: -1,
+            options: &options,
+        };
+        let result =
+            process_batch_results(5, &single_outputs(vec![f32::NAN; 26]), &[item]).unwrap();
 
-        // Process results
-        self.process_results(&results, next_pla)
+        assert!(result[0].had_invalid_outputs);
+        assert!(result[0]
+            .move_suggestions
+            .iter()
+            .all(|s| s.move_str != "B4"));
+        assert!((result[0].move_suggestions[0].probability - 1.0 / 17.0).abs() < 1e-6);
     }
 
-    /// Analyze multiple positions in a batch
-    pub fn analyze_batch(
-        &mut self,
-        inputs: &[(Vec<Vec<i8>>, AnalysisOptions)],
-    ) -> Result<Vec<AnalysisResult>, String> {
-        if inputs.is_empty() {
-            return Ok(vec![]);
+    #[test]
+    fn genmove_prefers_a_weak_legal_move_over_pass_in_an_open_game() {
+        // Pass dominates the policy but the game is even
+        let mut policy = vec![0.0; 82];
+        policy[81] = 0.8;
+        policy[40] = 0.05;
+        policy[20] = 0.15;
+        assert_eq!(choose_genmove(&policy, 9, 0.5, 0.5, None), Some((2, 2)));
+        // Sampling never picks pass or an illegal (zero) move either
+        for _ in 0..20 {
+            let pick = choose_genmove(&policy, 9, 0.5, 0.5, Some(5)).unwrap();
+            assert!([(2, 2), (4, 4)].contains(&pick));
         }
+    }
 
-        self.board_size = inputs[0].0.len();
-        let size = self.board_size;
-        let batch_size = inputs.len();
-
-        // Prepare batch tensors
-        let mut bin_input = Array4::<f32>::zeros((batch_size, 22, size, size));
-        let mut global_input = Array2::<f32>::zeros((batch_size, 19));
-        let mut plas = Vec::with_capacity(batch_size);
+    #[test]
+    fn genmove_passes_when_decided_or_out_of_moves() {
+        let mut policy = vec![0.0; 82];
+        policy[81] = 0.8;
+        policy[20] = 0.2;
+        assert_eq!(choose_genmove(&policy, 9, 0.99, 0.5, None), None);
+        assert_eq!(choose_genmove(&policy, 9, 0.01, 0.5, None), None);
+        // Below the pass threshold it keeps playing
+        assert_eq!(choose_genmove(&policy, 9, 0.99, 0.9, None), Some((2, 2)));
 
-        for (b, (sign_map, options)) in inputs.iter().enumerate() {
-            let next_pla: i8 = match &options.next_to_play {
-                Some(s) if s == "W" => -1,
-                _ => 1,
-            };
-            plas.push(next_pla);
+        let mut only_pass = vec![0.0; 82];
+        only_pass[81] = 1.0;
+        assert_eq!(choose_genmove(&only_pass, 9, 0.5, 0.5, None), None);
+    }
 
-            let (bin, global) =
-                self.featurize(sign_map, next_pla, options.komi, &options.history);
+    #[test]
+    fn full_policy_is_returned_on_request() {
+        let (sign_map, mut options) = white_after_ko_capture();
+        options.include_policy = true;
+        let item = BatchItem {
+            sign_map: &sign_map,
+            pla: -1,
+            options: &options,
+        };
+        let result = process_batch_results(5, &single_outputs(vec![0.0; 26]), &[item]).unwrap();
+        let policy = result[0].policy.as_ref().unwrap();
+        assert_eq!(policy.len(), 26);
+        assert_eq!(policy[6], 0.0);
+        assert!((policy.iter().sum::<f32>() - 1.0).abs() < 1e-5);
+    }
 
-            // Copy to batch tensors
-            for c in 0..22 {
-                for h in 0..size {
-                    for w in 0..size {
-                        bin_input[[b, c, h, w]] = bin[[0, c, h, w]];
-                    }
-                }
+    #[test]
+    fn score_to_winrate_is_monotonic() {
+        let win_rates: Vec<f32> = (0..=100).map(|i| i as f32 / 100.0).collect();
+        let leads: Vec<f32> = (-60..=60).map(|i| i as f32).collect();
+        for &lead in &leads {
+            for pair in win_rates.windows(2) {
+                assert!(score_to_winrate(pair[0], lead) <= score_to_winrate(pair[1], lead));
             }
-            for i in 0..19 {
-                global_input[[b, i]] = global[[0, i]];
+        }
+        for &win_rate in &win_rates {
+            for pair in leads.windows(2) {
+                assert!(score_to_winrate(win_rate, pair[0]) <= score_to_winrate(win_rate, pair[1]));
             }
         }
+    }
 
-        // Run batch inference
-        let results = self.run_inference(&bin_input, &global_input, batch_size)?;
+    #[test]
+    fn score_to_winrate_only_moves_saturated_win_rates() {
+        // Unchanged up to the band edges, whatever the lead
+        for win_rate in [0.05, 0.3, 0.5, 0.8, 0.95] {
+            assert_eq!(score_to_winrate(win_rate, 35.0), win_rate);
+        }
+        // Inside the band the lead decides where the result lands
+        let small = score_to_winrate(0.999, 5.0);
+        let large = score_to_winrate(0.999, 40.0);
+        assert!((0.95..=1.0).contains(&small) && small < large && large <= 1.0);
+        assert!((score_to_winrate(0.96, 0.0) - 0.975).abs() < 1e-6);
+        // Mirrored for the losing side
+        assert!((score_to_winrate(0.001, -5.0) - (1.0 - small)).abs() < 1e-6);
+        assert!(score_to_winrate(0.001, -40.0) < score_to_winrate(0.001, -5.0));
+    }
 
-        // Process batch results
-        self.process_batch_results(&results, &plas)
+    #[test]
+    fn results_from_before_a_model_switch_are_not_cached() {
+        let mut sign_map = vec![vec![0i8; 7]; 7];
+        sign_map[3][3] = 1;
+        let options = AnalysisOptions::default();
+        let item = BatchItem {
+            sign_map: &sign_map,
+            pla: -1,
+            options: &options,
+        };
+        let result = process_batch_results(7, &single_outputs(vec![0.0; 50]), &[item]).unwrap();
+        let key = || AnalysisCacheKey::new(&sign_map, &options).unwrap();
+
+        let generation = ANALYSIS_GENERATION.load(Ordering::SeqCst);
+        clear_analysis_cache();
+        store_analysis(key(), generation, &result[0]);
+        assert!(cached_analysis(&key()).is_none());
+
+        store_analysis(
+            key(),
+            ANALYSIS_GENERATION.load(Ordering::SeqCst),
+            &result[0],
+        );
+        assert!(cached_analysis(&key()).is_some());
     }
 
-    /// Featurize a board position into neural network inputs
-    fn featurize(
-        &self,
-        sign_map: &[Vec<i8>],
-        pla: i8,
-        komi: f32,
-        history: &[HistoryMove],
-    ) -> (Array4<f32>, Array2<f32>) {
-        let size = self.board_size;
-        let opp = -pla;
+    #[test]
+    fn autosave_replaces_the_saved_position_without_leftovers() {
+        let dir = std::env::temp_dir().join(format!("kaya-autosave-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("last-position.json");
+        set_autosave_path(Some(path.clone()));
 
-        let mut bin_input = Array4::<f32>::zeros((1, 22, size, size));
-        let mut global_input = Array2::<f32>::zeros((1, 19));
+        let mut sign_map = vec![vec![0i8; 9]; 9];
+        autosave_position(&sign_map, &AnalysisOptions::default());
+        sign_map[4][4] = 1;
+        let options = AnalysisOptions {
+            next_to_play: Some("W".to_string()),
+            ..AnalysisOptions::default()
+        };
+        autosave_position(&sign_map, &options);
 
-        // Compute liberties for each group
-        let liberties = self.compute_liberties(sign_map);
+        let saved = read_saved_position(&path).unwrap();
+        assert_eq!(saved.sign_map, sign_map);
+        assert_eq!(saved.options.next_to_play.as_deref(), Some("W"));
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
 
-        for y in 0..size {
-            for x in 0..size {
-                // Channel 0: all ones
-                bin_input[[0, 0, y, x]] = 1.0;
+        set_autosave_path(None);
+        assert!(!path.exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
 
-                let color = sign_map[y][x];
-                if color == pla {
-                    bin_input[[0, 1, y, x]] = 1.0;
-                } else if color == opp {
-                    bin_input[[0, 2, y, x]] = 1.0;
-                }
+    #[test]
+    fn moves_are_graded_by_the_win_rate_the_mover_loses() {
+        let options = AnalysisOptions::default();
+        let grade = |loss| MoveGrade::from_win_rate_loss(loss, &options);
+        assert_eq!(grade(-0.1), MoveGrade::Good);
+        assert_eq!(grade(0.049), MoveGrade::Good);
+        assert_eq!(grade(0.05), MoveGrade::Inaccuracy);
+        assert_eq!(grade(0.1), MoveGrade::Mistake);
+        assert_eq!(grade(0.25), MoveGrade::Blunder);
 
-                if color != 0 {
-                    let libs = liberties[y][x];
-                    if libs == 1 {
-                        bin_input[[0, 3, y, x]] = 1.0;
-                    }
-                    if libs == 2 {
-                        bin_input[[0, 4, y, x]] = 1.0;
-                    }
-                    if libs == 3 {
-                        bin_input[[0, 5, y, x]] = 1.0;
-                    }
-                }
-            }
-        }
+        // White to play: Black's win rate going up is White's loss
+        let (sign_map, white_options) = white_after_ko_capture();
+        let item = BatchItem {
+            sign_map: &sign_map,
+            pla: -1,
+            options: &white_options,
+        };
+        let mut policy = vec![0.0; 26];
+        policy[18] = 5.0;
+        let mut before = process_batch_results(5, &single_outputs(policy), &[item])
+            .unwrap()
+            .remove(0);
+        let mut after = before.clone();
+        before.win_rate = 0.4;
+        after.win_rate = 0.55;
+        let graded = grade_from_analyses(&before, &after, (4, 4), 5, &options).unwrap();
+        assert_eq!(graded.best_move, "D2");
+        assert!((graded.win_rate_delta + 0.15).abs() < 1e-6);
+        assert_eq!(graded.grade, MoveGrade::Mistake);
 
-        // Ko feature (channel 6) - would need ko info from game state
-        // For now, skip as we don't have ko position
+        // The engine's top move is best whatever it loses
+        let graded = grade_from_analyses(&before, &after, (3, 3), 5, &options).unwrap();
+        assert_eq!(graded.grade, MoveGrade::Best);
+    }
 
-        // History features (channels 9-13: last 5 moves)
-        let hist_len = history.len();
-        for (move_idx, feature_idx) in [(1, 9), (2, 10), (3, 11), (4, 12), (5, 13)] {
-            if hist_len >= move_idx {
-                let m = &history[hist_len - move_idx];
-                if m.x >= 0 && m.y >= 0 && (m.x as usize) < size && (m.y as usize) < size {
-                    bin_input[[0, feature_idx, m.y as usize, m.x as usize]] = 1.0;
-                }
-            }
+    #[test]
+    fn legal_move_mask_keeps_pass_and_respects_ko() {
+        let (sign_map, _) = white_after_ko_capture();
+        let with_ko = legal_move_mask(&sign_map, -1, Some((1, 1)));
+        let without_ko = legal_move_mask(&sign_map, -1, None);
+        assert_eq!(with_ko.len(), 26);
+        assert!(with_ko[25]);
+        assert!(!with_ko[6] && without_ko[6]);
+        assert_eq!(with_ko.iter().filter(|&&legal| legal).count(), 17);
+    }
+
+    /// A pool whose sessions are all checked out by other requests
+    fn exhausted_pool() -> OnnxSessionPool {
+        OnnxSessionPool {
+            state: Mutex::new(PoolState {
+                idle: Vec::new(),
+                waiting: Vec::new(),
+            }),
+            available: Condvar::new(),
+            provider_name: "CPU".to_string(),
+            fallback_used: false,
+            is_quantized: false,
+            device_index: 0,
+            device_name: None,
+            coreml_compute_units: CoreMlComputeUnits::default(),
+            model_path: None,
+            model_id: None,
+            model_sha256: None,
+            optimal_batch_size: AtomicUsize::new(0),
+            deterministic: Mutex::new(None),
+            komi_scale: None,
+            board_size: None,
+            config: None,
         }
+    }
 
-        // Global features
-        // Pass history (channels 0-4)
-        for (move_idx, global_idx) in [(1, 0), (2, 1), (3, 2), (4, 3), (5, 4)] {
-            if hist_len >= move_idx && history[hist_len - move_idx].x < 0 {
-                global_input[[0, global_idx]] = 1.0;
-            }
+    #[test]
+    fn cancelling_a_request_ends_its_wait_for_a_session() {
+        let pool = exhausted_pool();
+        let id = "test-cancel-while-waiting".to_string();
+        let cancel = CancelToken::register(Some(id.clone())).unwrap();
+        std::thread::scope(|scope| {
+            let waiter = scope.spawn(|| pool.checkout_cancellable(false, 1, Some(&cancel)).err());
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            assert!(cancel_request(&id));
+            assert!(matches!(waiter.join().unwrap(), Some(OnnxError::Cancelled)));
+        });
+        assert!(pool.state.lock().unwrap().waiting.is_empty());
+    }
+
+    #[test]
+    fn a_request_id_can_only_be_running_once() {
+        let id = "test-duplicate-request".to_string();
+        let first = CancelToken::register(Some(id.clone())).unwrap();
+        assert!(CancelToken::register(Some(id.clone())).is_err());
+        assert!(CancelToken::register(None).is_ok());
+        drop(first);
+        let second = CancelToken::register(Some(id.clone())).unwrap();
+        assert!(cancel_request(&id));
+        assert!(second.check().is_err());
+        drop(second);
+        assert!(!cancel_request(&id));
+    }
+
+    #[test]
+    #[cfg(not(feature = "rocm"))]
+    fn rocm_is_never_tried_without_the_feature() {
+        assert!(!is_provider_available(ExecutionProviderPreference::Rocm));
+        assert!(!fallback_chain(ExecutionProviderPreference::Auto)
+            .contains(&ExecutionProviderPreference::Rocm));
+    }
+
+    #[test]
+    fn feature_templates_are_reused_until_evicted() {
+        let mut templates = Vec::new();
+        let chinese = cached_feature_template(&mut templates, 19, Rules::Chinese);
+        assert_eq!(chinese.bin.index_axis(Axis(0), 0).sum(), 361.0);
+        assert_eq!(chinese.bin.sum(), 361.0);
+        assert_eq!(chinese.global.sum(), 0.0);
+        let japanese = cached_feature_template(&mut templates, 19, Rules::Japanese);
+        assert_eq!(japanese.global[9], 1.0);
+
+        assert!(Arc::ptr_eq(
+            &chinese,
+            &cached_feature_template(&mut templates, 19, Rules::Chinese)
+        ));
+        // Chinese 19x19 was just used, so Japanese 19x19 is the first to go
+        for size in [9, 13, 15] {
+            cached_feature_template(&mut templates, size, Rules::Chinese);
         }
+        assert_eq!(templates.len(), MAX_FEATURE_TEMPLATES);
+        assert!(Arc::ptr_eq(
+            &chinese,
+            &cached_feature_template(&mut templates, 19, Rules::Chinese)
+        ));
+        assert!(!Arc::ptr_eq(
+            &japanese,
+            &cached_feature_template(&mut templates, 19, Rules::Japanese)
+        ));
+    }
 
-        // Komi
-        global_input[[0, 5]] = komi / 20.0;
+    #[test]
+    fn win_rate_is_reported_for_the_side_to_move_by_default() {
+        let sign_map = vec![vec![0i8; 9]; 9];
+        let white = AnalysisOptions {
+            next_to_play: Some("W".to_string()),
+            ..AnalysisOptions::default()
+        };
+        let evaluate = |value: [f32; 3], orient_to: Option<i8>| {
+            let mut outputs = single_outputs(vec![0.0; 82]);
+            outputs.value = value.to_vec();
+            let item = BatchItem {
+                sign_map: &sign_map,
+                pla: -1,
+                options: &white,
+            };
+            let mut result = process_batch_results(9, &outputs, &[item])
+                .unwrap()
+                .remove(0);
+            result.orient(orient_to).unwrap();
+            result
+        };
 
-        (bin_input, global_input)
+        let even = evaluate([0.0, 0.0, -30.0], None);
+        assert!((even.win_rate - 0.5).abs() < 1e-6);
+        assert!(even.draw_rate < 1e-6);
+
+        // The value head is from White's point of view here, as White is to move
+        let won = evaluate([8.0, -8.0, -8.0], None);
+        assert!(won.win_rate > 0.99, "{}", won.win_rate);
+        assert!(won.draw_rate < 0.01);
+        assert!(
+            (evaluate([8.0, -8.0, -8.0], Some(1)).win_rate - (1.0 - won.win_rate)).abs() < 1e-6
+        );
+        assert_eq!(evaluate([8.0, -8.0, -8.0], Some(0)).win_rate, won.win_rate);
+
+        let drawn = evaluate([0.0, 0.0, 0.0], None);
+        assert!((drawn.draw_rate - 1.0 / 3.0).abs() < 1e-6);
     }
 
-    /// Compute liberties for each position
-    fn compute_liberties(&self, sign_map: &[Vec<i8>]) -> Vec<Vec<usize>> {
-        let size = sign_map.len();
-        let mut liberties = vec![vec![0usize; size]; size];
-        let mut visited = vec![vec![false; size]; size];
+    #[test]
+    fn fixing_the_orientation_to_black_keeps_alternating_turns_continuous() {
+        let sign_map = vec![vec![0i8; 9]; 9];
+        // One evaluation of the same position, seen by Black then by White: the raw
+        // value and score heads are from the side to move, so they are complementary
+        let turns = [
+            ("B", 1, [2.0, -2.0, -30.0], 0.3),
+            ("W", -1, [-2.0, 2.0, -30.0], -0.3),
+        ];
+        let evaluate = |(turn, pla, value, lead): (&str, i8, [f32; 3], f32), orient_to| {
+            let options = AnalysisOptions {
+                next_to_play: Some(turn.to_string()),
+                orient_to,
+                ..AnalysisOptions::default()
+            };
+            let mut outputs = single_outputs(vec![0.0; 82]);
+            outputs.value = value.to_vec();
+            outputs.miscvalue[2] = lead;
+            let item = BatchItem {
+                sign_map: &sign_map,
+                pla,
+                options: &options,
+            };
+            let mut result = process_batch_results(9, &outputs, &[item])
+                .unwrap()
+                .remove(0);
+            result.orient(options.orient_to).unwrap();
+            result
+        };
 
-        for y in 0..size {
-            for x in 0..size {
-                if sign_map[y][x] != 0 && !visited[y][x] {
-                    // Find group and count liberties
-                    let mut group = Vec::new();
-                    let mut liberty_set = std::collections::HashSet::new();
-                    let mut stack = vec![(x, y)];
-                    let color = sign_map[y][x];
+        let [black, white] = turns.map(|turn| evaluate(turn, None));
+        assert!(black.win_rate > 0.95);
+        assert!((black.win_rate + white.win_rate - 1.0).abs() < 1e-6);
+        assert_eq!(black.score_lead, -white.score_lead);
 
-                    while let Some((cx, cy)) = stack.pop() {
-                        if visited[cy][cx] {
-                            continue;
-                        }
-                        if sign_map[cy][cx] != color {
-                            if sign_map[cy][cx] == 0 {
-                                liberty_set.insert((cx, cy));
-                            }
-                            continue;
-                        }
+        let [black, white] = turns.map(|turn| evaluate(turn, Some(1)));
+        assert!((black.win_rate - white.win_rate).abs() < 1e-6);
+        assert_eq!(black.score_lead, white.score_lead);
+        assert!((black.score_lead - 6.0).abs() < 1e-5);
+    }
 
-                        visited[cy][cx] = true;
-                        group.push((cx, cy));
+    #[test]
+    fn searched_values_follow_the_root_orientation() {
+        let sign_map = vec![vec![0i8; 9]; 9];
+        let white = AnalysisOptions {
+            next_to_play: Some("W".to_string()),
+            ..AnalysisOptions::default()
+        };
+        let item = BatchItem {
+            sign_map: &sign_map,
+            pla: -1,
+            options: &white,
+        };
+        let root = process_batch_results(9, &single_outputs(vec![0.0; 82]), &[item])
+            .unwrap()
+            .remove(0);
+        let searched = |win_rate: f32, score_lead: f32| SearchedMove {
+            move_str: "E5".to_string(),
+            prior: 0.5,
+            win_rate,
+            score_lead,
+        };
+        let black_view = SearchedAnalysis {
+            policy: root,
+            searched_moves: vec![searched(0.2, -3.0), searched(0.4, -1.0)],
+            win_rate: 0.2,
+            score_lead: -3.0,
+        };
 
-                        // Check neighbors
-                        if cx > 0 {
-                            stack.push((cx - 1, cy));
-                        }
-                        if cx + 1 < size {
-                            stack.push((cx + 1, cy));
-                        }
-                        if cy > 0 {
-                            stack.push((cx, cy - 1));
-                        }
-                        if cy + 1 < size {
-                            stack.push((cx, cy + 1));
-                        }
-                    }
+        let mut fixed = black_view.clone();
+        fixed.orient(Some(1)).unwrap();
+        assert_eq!((fixed.win_rate, fixed.score_lead), (0.2, -3.0));
 
-                    // Check liberties from group edges
-                    for &(gx, gy) in &group {
-                        let neighbors = [
-                            (gx.wrapping_sub(1), gy),
-                            (gx + 1, gy),
-                            (gx, gy.wrapping_sub(1)),
-                            (gx, gy + 1),
-                        ];
-                        for (nx, ny) in neighbors {
-                            if nx < size && ny < size && sign_map[ny][nx] == 0 {
-                                liberty_set.insert((nx, ny));
-                            }
-                        }
-                    }
+        // White is to move at the root, so the default view is White's
+        let mut to_move = black_view.clone();
+        to_move.orient(None).unwrap();
+        assert_eq!((to_move.win_rate, to_move.score_lead), (0.8, 3.0));
+        let values: Vec<_> = to_move
+            .searched_moves
+            .iter()
+            .map(|m| (m.win_rate, m.score_lead))
+            .collect();
+        assert_eq!(values, [(0.8, 3.0), (0.6, 1.0)]);
+        assert_eq!(to_move.policy.win_rate, 1.0 - black_view.policy.win_rate);
+
+        let mut bad = black_view;
+        assert!(matches!(
+            bad.orient(Some(3)),
+            Err(OnnxError::InvalidBoard(_))
+        ));
+    }
+
+    #[test]
+    fn only_device_failures_blame_the_provider() {
+        use ort::error::ErrorCode;
+        let classify = |provider: &str, code: ErrorCode, message: &str| {
+            run_error(provider, ort::Error::new_with_code(code, message))
+        };
+
+        let ep_failure = classify(
+            "cuda",
+            ErrorCode::ExecutionProviderFailure,
+            "kernel launch failed",
+        );
+        assert!(matches!(ep_failure, OnnxError::ProviderUnavailable(_)));
+        let lost_device = classify(
+            "directml",
+            ErrorCode::GenericFailure,
+            "DXGI_ERROR_DEVICE_REMOVED",
+        );
+        assert!(matches!(lost_device, OnnxError::ProviderUnavailable(_)));
+        let oom = classify(
+            "cuda",
+            ErrorCode::RuntimeException,
+            "CUDA error cudaErrorMemoryAllocation: out of memory",
+        );
+        assert!(matches!(oom, OnnxError::ProviderUnavailable(_)));
 
-                    let lib_count = liberty_set.len();
-                    for (gx, gy) in group {
-                        liberties[gy][gx] = lib_count;
-                    }
-                }
-            }
+        let bad_input = classify(
+            "cuda",
+            ErrorCode::InvalidArgument,
+            "Got invalid dimensions for input: input_binary",
+        );
+        assert!(matches!(bad_input, OnnxError::Inference(_)));
+        let on_cpu = classify("cpu", ErrorCode::ExecutionProviderFailure, "out of memory");
+        assert!(
+            matches!(on_cpu, OnnxError::Inference(message) if message.contains("out of memory"))
+        );
+    }
+
+    #[test]
+    fn bad_orientations_are_rejected_before_any_inference() {
+        let position = |orient_to| {
+            let options = AnalysisOptions {
+                orient_to,
+                ..AnalysisOptions::default()
+            };
+            (vec![vec![0i8; 9]; 9], options)
+        };
+        // Only the last position is invalid, but nothing may be reported for the others
+        let inputs = vec![position(Some(1)), position(None), position(Some(2))];
+        let cancel = CancelToken::register(None).unwrap();
+        let mut reported = 0;
+        let error =
+            analyze_batch_chunked(&inputs, &cancel, Some(1), |_, _| reported += 1).unwrap_err();
+        assert!(
+            matches!(error, OnnxError::InvalidBoard(message) if message.contains("orientation"))
+        );
+        assert_eq!(reported, 0);
+
+        let (sign_map, options) = position(Some(-2));
+        assert!(matches!(
+            analyze_position(sign_map, options),
+            Err(OnnxError::InvalidBoard(_))
+        ));
+        for orient_to in [None, Some(-1), Some(0), Some(1)] {
+            assert!(validate_orient_to(orient_to).is_ok());
         }
+    }
 
-        liberties
+    /// Load the model at `KAYA_TEST_MODEL` as the active engine, once per test run
+    /// Returns false when it isn't set, so tests that need a real network can skip
+    fn load_test_model() -> bool {
+        static LOADED: std::sync::OnceLock<bool> = std::sync::OnceLock::new();
+        *LOADED.get_or_init(|| {
+            let Ok(path) = std::env::var("KAYA_TEST_MODEL") else {
+                return false;
+            };
+            initialize_engine_from_path(&path, ModelLoadOptions::default())
+                .expect("failed to load KAYA_TEST_MODEL");
+            true
+        })
     }
 
-    /// Run ONNX inference
-    fn run_inference(
-        &mut self,
-        bin_input: &Array4<f32>,
-        global_input: &Array2<f32>,
-        _batch_size: usize,
-    ) -> Result<OnnxOutputs, String> {
-        if self.is_fp16 {
-            self.run_inference_fp16(bin_input, global_input)
-        } else {
-            self.run_inference_fp32(bin_input, global_input)
+    #[test]
+    fn concurrent_analyses_all_succeed() {
+        if !load_test_model() {
+            return;
+        }
+        let analyses: Vec<_> = (0..8)
+            .map(|i| {
+                std::thread::spawn(move || {
+                    let mut sign_map = vec![vec![0i8; 19]; 19];
+                    sign_map[3][i + 3] = 1;
+                    analyze_position(sign_map, AnalysisOptions::default())
+                })
+            })
+            .collect();
+        for analysis in analyses {
+            let result = analysis.join().unwrap().unwrap();
+            assert_eq!(result.current_turn, "W");
+            assert!(!result.move_suggestions.is_empty());
         }
     }
 
-    /// Run ONNX inference with fp32 tensors
-    fn run_inference_fp32(
-        &mut self,
-        bin_input: &Array4<f32>,
-        global_input: &Array2<f32>,
-    ) -> Result<OnnxOutputs, String> {
-        // Clone arrays to get owned data for tensor creation
-        let bin_owned = bin_input.clone();
-        let global_owned = global_input.clone();
+    #[test]
+    fn malformed_boards_are_rejected_with_their_dimensions() {
+        let invalid_board = |sign_map: &[Vec<i8>]| match validate_sign_map(sign_map) {
+            Err(OnnxError::InvalidBoard(message)) => message,
+            other => panic!("expected InvalidBoard, got {:?}", other),
+        };
 
-        // Create input tensors from owned arrays
-        let bin_tensor = Tensor::from_array(bin_owned)
-            .map_err(|e| format!("Failed to create bin_input tensor: {}", e))?;
+        assert_eq!(invalid_board(&[]), "Board is empty");
+        let mut ragged = vec![vec![0i8; 9]; 9];
+        ragged[4].pop();
+        assert_eq!(
+            invalid_board(&ragged),
+            "Board is not square: row 4 has 8 cells, expected 9"
+        );
+        assert!(invalid_board(&vec![vec![0i8; 9]; 8]).contains("row 0 has 9 cells, expected 8"));
+        let mut out_of_range = vec![vec![0i8; 9]; 9];
+        out_of_range[2][7] = 2;
+        assert_eq!(
+            invalid_board(&out_of_range),
+            "Invalid value 2 at (7, 2), expected -1, 0 or 1"
+        );
 
-        let global_tensor = Tensor::from_array(global_owned)
-            .map_err(|e| format!("Failed to create global_input tensor: {}", e))?;
+        let mut valid = vec![vec![0i8; 9]; 9];
+        valid[0][0] = -1;
+        valid[8][8] = 1;
+        assert!(validate_sign_map(&valid).is_ok());
+        assert!(unflatten_sign_maps(&[0; 80], 9, 1).is_err());
+        assert_eq!(unflatten_sign_maps(&[0; 162], 9, 2).unwrap().len(), 2);
+    }
 
-        // Run inference
-        let outputs = self
-            .session
-            .run(ort::inputs![bin_tensor, global_tensor])
-            .map_err(|e| format!("Inference failed: {}", e))?;
+    #[test]
+    fn history_planes_mark_recent_moves_most_recent_first() {
+        let mv = |color, x, y| HistoryMove { color, x, y };
+        // Black captures the White stone at (0, 0) with (0, 1), then White plays elsewhere
+        let mut history = vec![mv(1, 1, 0), mv(-1, 0, 0), mv(1, 0, 1), mv(-1, 4, 4)];
+        let mut board = GoBoard::new(9).unwrap();
+        for m in &history {
+            board.play(m.x, m.y, m.color).unwrap();
+        }
+        let sign_map = board.to_signmap();
+        assert_eq!(sign_map[0][0], 0);
 
-        // Extract outputs - try_extract_tensor returns (&Shape, &[T])
-        let (policy_shape, policy_data) = outputs["policy"]
-            .try_extract_tensor::<f32>()
-            .map_err(|e| format!("Failed to extract policy: {}", e))?;
+        let featurize = |history: &[HistoryMove], pla: i8| {
+            let options = AnalysisOptions {
+                history: history.to_vec(),
+                ..AnalysisOptions::default()
+            };
+            let template = FeatureTemplate::new(9, options.rules);
+            let mut bin = Array3::<f32>::zeros((22, 9, 9));
+            let mut global = Array1::<f32>::zeros(19);
+            OnnxEngine::featurize_into(
+                &sign_map,
+                pla,
+                &options,
+                None,
+                &template,
+                bin.view_mut(),
+                global.view_mut(),
+            );
+            (bin, global)
+        };
+        let marked = |bin: &Array3<f32>, channel: usize| -> Vec<(usize, usize)> {
+            bin.index_axis(Axis(0), channel)
+                .indexed_iter()
+                .filter(|(_, &v)| v == 1.0)
+                .map(|((y, x), _)| (x, y))
+                .collect()
+        };
 
-        let (_value_shape, value_data) = outputs["value"]
-            .try_extract_tensor::<f32>()
-            .map_err(|e| format!("Failed to extract value: {}", e))?;
+        let (bin, global) = featurize(&history, 1);
+        assert_eq!(marked(&bin, 9), [(4, 4)]);
+        assert_eq!(marked(&bin, 10), [(0, 1)]);
+        // The captured stone's move is still marked, though the point is now empty
+        assert_eq!(marked(&bin, 11), [(0, 0)]);
+        assert_eq!(marked(&bin, 12), [(1, 0)]);
+        assert!(marked(&bin, 13).is_empty());
+        assert_eq!(global.slice(ndarray::s![0..5]).sum(), 0.0);
 
-        let (_misc_shape, miscvalue_data) = outputs["miscvalue"]
-            .try_extract_tensor::<f32>()
-            .map_err(|e| format!("Failed to extract miscvalue: {}", e))?;
+        // A pass sets its global flag instead of a plane, and moves older than 5 drop out
+        history.extend([mv(1, -1, -1), mv(-1, 6, 6)]);
+        let (bin, global) = featurize(&history, 1);
+        assert_eq!(marked(&bin, 9), [(6, 6)]);
+        assert!(marked(&bin, 10).is_empty());
+        assert_eq!(global[1], 1.0);
+        assert_eq!(marked(&bin, 13), [(0, 0)]);
+        assert!((9..14).all(|channel| !marked(&bin, channel).contains(&(1, 0))));
 
-        let ownership = if outputs.contains_key("ownership") {
-            let (_own_shape, own_data) = outputs["ownership"]
-                .try_extract_tensor::<f32>()
-                .map_err(|e| format!("Failed to extract ownership: {}", e))?;
-            Some(own_data.to_vec())
-        } else {
-            None
-        };
+        // History stops where the colors stop alternating into the side to move
+        let (bin, _) = featurize(&history, -1);
+        assert!((9..14).all(|channel| marked(&bin, channel).is_empty()));
+    }
 
-        // Convert Shape to Vec<usize>
-        let policy_dims: Vec<usize> = policy_shape.iter().map(|&d| d as usize).collect();
+    #[test]
+    fn mixed_board_sizes_come_back_in_input_order() {
+        // Tag each position by its komi so the fake analyzer's output can be traced back
+        let sizes = [19, 9, 13, 9, 19, 13, 9];
+        let inputs: Vec<_> = sizes
+            .iter()
+            .enumerate()
+            .map(|(i, &size)| {
+                let options = AnalysisOptions {
+                    komi: i as f32,
+                    ..Default::default()
+                };
+                (vec![vec![0i8; size]; size], options)
+            })
+            .collect();
 
-        Ok(OnnxOutputs {
-            policy: policy_data.to_vec(),
-            value: value_data.to_vec(),
-            miscvalue: miscvalue_data.to_vec(),
-            ownership,
-            policy_dims,
+        let mut batches = Vec::new();
+        let results = analyze_by_board_size(&inputs, |group| {
+            let size = group[0].0.len();
+            assert!(group.iter().all(|(sign_map, _)| sign_map.len() == size));
+            batches.push(size);
+            Ok(group
+                .iter()
+                .map(|(_, options)| (size, options.komi as usize))
+                .collect())
         })
+        .unwrap();
+
+        // One run per size, smallest first, each size seen once
+        assert_eq!(batches, vec![9, 13, 19]);
+        let expected: Vec<_> = sizes.iter().copied().zip(0..).collect();
+        assert_eq!(results, expected);
+
+        let failed = analyze_by_board_size(&inputs, |group| {
+            if group[0].0.len() == 13 {
+                Err(OnnxError::Inference("boom".to_string()))
+            } else {
+                Ok(vec![(); group.len()])
+            }
+        });
+        assert!(matches!(failed, Err(OnnxError::Inference(_))));
     }
 
-    /// Run ONNX inference with fp16 tensors (converts f32 inputs to f16, runs inference, converts f16 outputs back to f32)
-    fn run_inference_fp16(
-        &mut self,
-        bin_input: &Array4<f32>,
-        global_input: &Array2<f32>,
-    ) -> Result<OnnxOutputs, String> {
-        // Convert f32 inputs to f16
-        let bin_fp16 = bin_input.mapv(|v| f16::from_f32(v));
-        let global_fp16 = global_input.mapv(|v| f16::from_f32(v));
+    #[test]
+    fn deterministic_analysis_needs_a_model_file() {
+        // Models loaded from bytes have no file to open the dedicated CPU session from
+        let pool = exhausted_pool();
+        let Err(OnnxError::Inference(message)) = pool.checkout_for(true, 0) else {
+            panic!("deterministic checkout without a model file should fail");
+        };
+        assert!(message.contains("needs a model loaded from a file"));
+        assert!(pool.deterministic.lock().unwrap().is_none());
+    }
 
-        // Create input tensors from f16 arrays
-        let bin_tensor = Tensor::from_array(bin_fp16)
-            .map_err(|e| format!("Failed to create bin_input f16 tensor: {}", e))?;
+    #[test]
+    fn deterministic_batches_are_bit_identical() {
+        if !load_test_model() {
+            return;
+        }
+        let inputs: Vec<_> = (0..4)
+            .map(|i| {
+                let mut sign_map = vec![vec![0i8; 19]; 19];
+                sign_map[3][3 + i] = 1;
+                sign_map[15][15 - i] = -1;
+                let options = AnalysisOptions {
+                    deterministic: true,
+                    symmetries: 8,
+                    include_policy: true,
+                    ..Default::default()
+                };
+                (sign_map, options)
+            })
+            .collect();
+        let run = || analyze_batch(inputs.clone(), &CancelToken::register(None).unwrap()).unwrap();
+        let (first, second) = (run(), run());
+        assert_eq!(first.len(), second.len());
+        for (a, b) in first.iter().zip(&second) {
+            assert_eq!(a.win_rate.to_bits(), b.win_rate.to_bits());
+            assert_eq!(a.score_lead.to_bits(), b.score_lead.to_bits());
+            let bits = |values: &Option<Vec<f32>>| {
+                values
+                    .as_ref()
+                    .map(|v| v.iter().map(|x| x.to_bits()).collect::<Vec<_>>())
+            };
+            assert_eq!(bits(&a.policy), bits(&b.policy));
+            assert_eq!(bits(&a.ownership), bits(&b.ownership));
+        }
+    }
 
-        let global_tensor = Tensor::from_array(global_fp16)
-            .map_err(|e| format!("Failed to create global_input f16 tensor: {}", e))?;
+    #[test]
+    fn a_higher_ownership_threshold_leaves_more_dame() {
+        let sign_map = vec![vec![1, 0, -1], vec![1, 0, -1], vec![1, 0, -1]];
+        #[rustfmt::skip]
+        let ownership = [
+            0.95, 0.7, -0.95,
+            0.95, 0.2, -0.6,
+            0.95, -0.7, 0.6,
+        ];
+        let dame = |score: &FinalScore| 9.0 - score.black_score - score.white_score;
 
-        // Run inference
-        let outputs = self
-            .session
-            .run(ort::inputs![bin_tensor, global_tensor])
-            .map_err(|e| format!("Inference failed: {}", e))?;
+        let loose = score_from_ownership(&sign_map, &ownership, Rules::Chinese, 0.0, 0.5);
+        assert_eq!((loose.black_score, loose.white_score), (5.0, 3.0));
+        assert_eq!(dame(&loose), 1.0);
+        assert_eq!(loose.dead_stones, vec![(2, 2)]);
 
-        // Extract outputs as f16 and convert to f32
-        let (policy_shape, policy_data) = outputs["policy"]
-            .try_extract_tensor::<f16>()
-            .map_err(|e| format!("Failed to extract policy: {}", e))?;
+        // Only the 0.95 points are confident enough, and the doubtful white stone is no longer dead
+        let strict = score_from_ownership(&sign_map, &ownership, Rules::Chinese, 0.0, 0.8);
+        assert_eq!((strict.black_score, strict.white_score), (3.0, 1.0));
+        assert_eq!(dame(&strict), 5.0);
+        assert!(strict.dead_stones.is_empty());
 
-        let (_value_shape, value_data) = outputs["value"]
-            .try_extract_tensor::<f16>()
-            .map_err(|e| format!("Failed to extract value: {}", e))?;
+        // Territory counts empty points, plus the dead stone twice as a prisoner, on top of komi
+        let territory = score_from_ownership(&sign_map, &ownership, Rules::Japanese, 6.5, 0.5);
+        assert_eq!((territory.black_score, territory.white_score), (3.0, 7.5));
+        assert_eq!(territory.score_lead, -4.5);
+    }
 
-        let (_misc_shape, miscvalue_data) = outputs["miscvalue"]
-            .try_extract_tensor::<f16>()
-            .map_err(|e| format!("Failed to extract miscvalue: {}", e))?;
+    #[test]
+    fn encore_globals_follow_the_phase_and_the_last_pass() {
+        let sign_map = vec![vec![0i8; 9]; 9];
+        let template = FeatureTemplate::new(9, Rules::Japanese);
+        let encore_globals = |encore_phase: u8, history: Vec<HistoryMove>| -> [f32; 3] {
+            let options = AnalysisOptions {
+                rules: Rules::Japanese,
+                encore_phase,
+                history,
+                ..AnalysisOptions::default()
+            };
+            let mut bin = Array3::<f32>::zeros((22, 9, 9));
+            let mut global = Array1::<f32>::zeros(19);
+            OnnxEngine::featurize_into(
+                &sign_map,
+                1,
+                &options,
+                None,
+                &template,
+                bin.view_mut(),
+                global.view_mut(),
+            );
+            [global[12], global[13], global[14]]
+        };
+        let white_pass = || HistoryMove {
+            color: -1,
+            x: -1,
+            y: -1,
+        };
+        let white_move = || HistoryMove {
+            color: -1,
+            x: 2,
+            y: 2,
+        };
+        let black_pass = || HistoryMove {
+            color: 1,
+            x: -1,
+            y: -1,
+        };
 
-        let ownership = if outputs.contains_key("ownership") {
-            let (_own_shape, own_data) = outputs["ownership"]
-                .try_extract_tensor::<f16>()
-                .map_err(|e| format!("Failed to extract ownership: {}", e))?;
-            Some(own_data.iter().map(|v| v.to_f32()).collect())
-        } else {
-            None
+        assert_eq!(encore_globals(0, vec![]), [0.0, 0.0, 0.0]);
+        assert_eq!(encore_globals(1, vec![]), [1.0, 0.0, 0.0]);
+        assert_eq!(encore_globals(2, vec![]), [1.0, 1.0, 0.0]);
+        // Only a pass by the opponent as the last move lets Black end the phase by passing
+        assert_eq!(encore_globals(1, vec![white_pass()]), [1.0, 0.0, 1.0]);
+        assert_eq!(encore_globals(0, vec![white_pass()]), [0.0, 0.0, 1.0]);
+        assert_eq!(
+            encore_globals(1, vec![white_pass(), black_pass(), white_move()]),
+            [1.0, 0.0, 0.0]
+        );
+        assert_eq!(
+            encore_globals(2, vec![white_move(), black_pass()]),
+            [1.0, 1.0, 0.0]
+        );
+    }
+
+    #[test]
+    fn non_finite_outputs_are_flagged_and_replaced() {
+        let sign_map = vec![vec![0i8; 5]; 5];
+        let options = AnalysisOptions::default();
+        let item = BatchItem {
+            sign_map: &sign_map,
+            pla: 1,
+            options: &options,
         };
 
-        // Convert Shape to Vec<usize>
-        let policy_dims: Vec<usize> = policy_shape.iter().map(|&d| d as usize).collect();
+        let clean = process_batch_results(5, &single_outputs(vec![0.0; 26]), &[item]).unwrap();
+        assert!(!clean[0].had_invalid_outputs);
 
-        // Convert f16 outputs to f32
-        Ok(OnnxOutputs {
-            policy: policy_data.iter().map(|v| v.to_f32()).collect(),
-            value: value_data.iter().map(|v| v.to_f32()).collect(),
-            miscvalue: miscvalue_data.iter().map(|v| v.to_f32()).collect(),
-            ownership,
-            policy_dims,
-        })
-    }
+        // One bad logit loses its probability while the rest stay a distribution
+        let mut policy = vec![0.0; 26];
+        policy[12] = f32::NAN;
+        policy[7] = 5.0;
+        let mut outputs = single_outputs(policy);
+        outputs.value = vec![f32::INFINITY, 0.0, f32::NAN];
+        outputs.miscvalue[2] = f32::NEG_INFINITY;
+        outputs.ownership = Some(
+            (0..25)
+                .map(|i| if i % 2 == 0 { f32::NAN } else { 0.5 })
+                .collect(),
+        );
+        let item = BatchItem {
+            sign_map: &sign_map,
+            pla: 1,
+            options: &options,
+        };
+        let result = process_batch_results(5, &outputs, &[item])
+            .unwrap()
+            .remove(0);
 
-    /// Process single inference result
-    fn process_results(
-        &self,
-        outputs: &OnnxOutputs,
-        pla: i8,
-    ) -> Result<AnalysisResult, String> {
-        let results = self.process_batch_results(outputs, &[pla])?;
-        results.into_iter().next().ok_or("No results".to_string())
+        assert!(result.had_invalid_outputs);
+        assert!(result.win_rate.is_finite() && (0.0..=1.0).contains(&result.win_rate));
+        // Replaced values are neutral, so the evaluation matches an all-zero value head
+        assert_eq!(result.win_rate, clean[0].win_rate);
+        assert_eq!(result.score_lead, 0.0);
+        let ownership = result.ownership.unwrap();
+        assert!(ownership.iter().all(|v| v.is_finite()));
+        assert_eq!((ownership[0], ownership[1]), (0.0, 0.5));
+
+        let suggestions = &result.move_suggestions;
+        assert_eq!(suggestions[0].move_str, "C4");
+        assert!(suggestions
+            .iter()
+            .all(|s| s.probability.is_finite() && s.move_str != "C3"));
+        let others = 1.0 - suggestions[0].probability;
+        assert!(
+            (others - 24.0 / (24.0 + 5f32.exp())).abs() < 1e-4,
+            "{}",
+            others
+        );
     }
 
-    /// Process batch inference results
-    fn process_batch_results(
-        &self,
-        outputs: &OnnxOutputs,
-        plas: &[i8],
-    ) -> Result<Vec<AnalysisResult>, String> {
-        let size = self.board_size;
-        let batch_size = plas.len();
-        let letters = "ABCDEFGHJKLMNOPQRST";
+    #[test]
+    fn batches_larger_than_the_cap_run_in_windows_in_order() {
+        let inputs: Vec<usize> = (0..10).collect();
+        let mut windows = Vec::new();
+        let results = analyze_in_windows(&inputs, 4, |window| {
+            windows.push(window.len());
+            Ok(window.iter().map(|i| i * 10).collect())
+        })
+        .unwrap();
+        assert_eq!(windows, vec![4, 4, 2]);
+        assert_eq!(results, (0..10).map(|i| i * 10).collect::<Vec<_>>());
 
-        // Determine strides from dimensions
-        let policy_dims = &outputs.policy_dims;
-        let num_policy_heads = if policy_dims.len() == 3 {
-            policy_dims[1]
-        } else {
-            1
-        };
-        let num_moves = if policy_dims.len() == 3 {
-            policy_dims[2]
-        } else {
-            policy_dims[1]
-        };
-        let policy_stride = num_policy_heads * num_moves;
-        let value_stride = 3;
-        let miscvalue_stride = 10;
-        let ownership_stride = size * size;
-
-        let mut results = Vec::with_capacity(batch_size);
-
-        for b in 0..batch_size {
-            let pla = plas[b];
-
-            // Extract policy for this batch item
-            let policy_start = b * policy_stride;
-            let policy_end = policy_start + num_moves;
-            let policy = &outputs.policy[policy_start..policy_end];
-
-            // Extract value
-            let value_start = b * value_stride;
-            let value = &outputs.value[value_start..value_start + 3];
-
-            // Extract miscvalue
-            let misc_start = b * miscvalue_stride;
-            let miscvalue = &outputs.miscvalue[misc_start..misc_start + miscvalue_stride];
-
-            // Win rate from value head
-            let exp_values: Vec<f32> = value.iter().map(|v| v.exp()).collect();
-            let sum_value: f32 = exp_values.iter().sum();
-            let winrate_current = exp_values[0] / sum_value;
-            let black_winrate = if pla == 1 {
-                winrate_current
-            } else {
-                1.0 - winrate_current
-            };
+        // A zero cap still makes progress one input at a time
+        let mut calls = 0;
+        analyze_in_windows(&inputs[..3], 0, |window| {
+            calls += 1;
+            Ok(window.to_vec())
+        })
+        .unwrap();
+        assert_eq!(calls, 3);
+    }
 
-            // Score lead
-            let lead_current = miscvalue[2] * 20.0;
-            let black_lead = lead_current * (pla as f32);
+    #[test]
+    fn batches_over_the_max_batch_size_match_single_analyses() {
+        if !load_test_model() {
+            return;
+        }
+        let inputs: Vec<_> = (0..5)
+            .map(|i| {
+                let mut sign_map = vec![vec![0i8; 19]; 19];
+                sign_map[3][3] = 1;
+                sign_map[15][3 + 2 * i] = -1;
+                let next = if i % 2 == 0 { "B" } else { "W" };
+                let options = AnalysisOptions {
+                    next_to_play: Some(next.to_string()),
+                    orient_to: Some(1),
+                    ..Default::default()
+                };
+                (sign_map, options)
+            })
+            .collect();
 
-            // Policy softmax
-            let max_logit = policy.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
-            let mut probs: Vec<f32> = policy.iter().map(|p| (p - max_logit).exp()).collect();
-            let sum_probs: f32 = probs.iter().sum();
-            for p in &mut probs {
-                *p /= sum_probs;
-            }
+        set_max_batch_size(2);
+        let batched = analyze_batch(inputs.clone(), &CancelToken::register(None).unwrap());
+        set_max_batch_size(DEFAULT_MAX_BATCH_SIZE);
+        let batched = batched.unwrap();
 
-            // Get top 10 moves
-            let mut indices: Vec<usize> = (0..num_moves).collect();
-            indices.sort_by(|&a, &b| probs[b].partial_cmp(&probs[a]).unwrap());
+        assert_eq!(batched.len(), inputs.len());
+        for ((sign_map, options), result) in inputs.into_iter().zip(&batched) {
+            assert!(result.batch_size <= 2);
+            assert_eq!(Some(&result.current_turn), options.next_to_play.as_ref());
+            let single = analyze_position(sign_map, options).unwrap();
+            assert!((single.win_rate - result.win_rate).abs() < 1e-3);
+        }
+    }
 
-            let move_suggestions: Vec<MoveSuggestion> = indices
-                .iter()
-                .take(10)
-                .map(|&idx| {
-                    let move_str = if idx == size * size {
-                        "PASS".to_string()
-                    } else {
-                        let y = idx / size;
-                        let x = idx % size;
-                        format!(
-                            "{}{}",
-                            letters.chars().nth(x).unwrap_or('?'),
-                            size - y
-                        )
-                    };
-                    MoveSuggestion {
-                        move_str,
-                        probability: probs[idx],
-                    }
-                })
-                .collect();
+    #[test]
+    fn empty_batches_return_no_results_without_an_engine() {
+        let cancel = CancelToken::register(None).unwrap();
+        assert!(analyze_batch(vec![], &cancel).unwrap().is_empty());
+        assert!(analyze_batch_auto(vec![], &cancel).unwrap().is_empty());
+        let mut streamed = 0;
+        assert_eq!(
+            analyze_batch_streaming(vec![], &cancel, |_, _| streamed += 1).unwrap(),
+            0
+        );
+        assert_eq!(streamed, 0);
 
-            // Ownership
-            let ownership = outputs.ownership.as_ref().map(|own| {
-                let start = b * ownership_stride;
-                own[start..start + ownership_stride]
-                    .iter()
-                    .map(|v| v * (pla as f32))
-                    .collect()
-            });
+        assert!(unflatten_sign_maps(&[], 19, 0).unwrap().is_empty());
+        assert!(process_batch_results(19, &single_outputs(vec![]), &[])
+            .unwrap()
+            .is_empty());
+        let none = analyze_by_board_size(&[], |_| -> Result<Vec<()>, OnnxError> {
+            panic!("nothing to analyze")
+        });
+        assert!(none.unwrap().is_empty());
+    }
 
-            results.push(AnalysisResult {
-                move_suggestions,
-                win_rate: black_winrate,
-                score_lead: black_lead,
-                current_turn: if pla == 1 { "B" } else { "W" }.to_string(),
-                ownership,
-            });
+    #[test]
+    fn empty_and_all_pass_games_are_analyzed_from_the_start_position() {
+        if !load_test_model() {
+            return;
         }
+        let options = GameAnalysisOptions {
+            board_size: 9,
+            komi: 7.5,
+        };
+        let cancel = CancelToken::register(None).unwrap();
+        let mut progress = Vec::new();
+        let empty = analyze_game(&[], &options, &cancel, |done, total| {
+            progress.push((done, total))
+        })
+        .unwrap();
+        assert_eq!(empty.results.len(), 1);
+        assert!(empty.win_rate_deltas.is_empty());
+        assert_eq!(progress.last(), Some(&(1, 1)));
 
-        Ok(results)
+        let passes: Vec<_> = (0..4)
+            .map(|i| HistoryMove {
+                color: if i % 2 == 0 { 1 } else { -1 },
+                x: -1,
+                y: -1,
+            })
+            .collect();
+        let game = analyze_game(&passes, &options, &cancel, |_, _| {}).unwrap();
+        assert_eq!(game.results.len(), 5);
+        assert_eq!(game.win_rate_deltas.len(), 4);
+        let turns: Vec<_> = game
+            .results
+            .iter()
+            .map(|r| r.current_turn.as_str())
+            .collect();
+        assert_eq!(turns, ["B", "W", "B", "W", "B"]);
     }
-}
 
-/// Internal struct for ONNX outputs
-struct OnnxOutputs {
-    policy: Vec<f32>,
-    value: Vec<f32>,
-    miscvalue: Vec<f32>,
-    ownership: Option<Vec<f32>>,
-    policy_dims: Vec<usize>,
-}
+    #[test]
+    fn komi_input_is_scaled_by_the_options_then_the_model() {
+        let sign_map = vec![vec![0i8; 9]; 9];
+        let template = FeatureTemplate::new(9, Rules::Chinese);
+        let komi_input = |komi_scale: Option<f32>, model_komi_scale: Option<f32>| {
+            let options = AnalysisOptions {
+                komi: 7.5,
+                komi_scale,
+                ..AnalysisOptions::default()
+            };
+            let mut bin = Array3::<f32>::zeros((22, 9, 9));
+            let mut global = Array1::<f32>::zeros(19);
+            OnnxEngine::featurize_into(
+                &sign_map,
+                1,
+                &options,
+                model_komi_scale,
+                &template,
+                bin.view_mut(),
+                global.view_mut(),
+            );
+            global[5]
+        };
 
-// Public API for Tauri commands
+        assert_eq!(komi_input(None, None), 7.5 / DEFAULT_KOMI_SCALE);
+        assert_eq!(komi_input(None, Some(15.0)), 0.5);
+        assert_eq!(komi_input(Some(10.0), Some(15.0)), 0.75);
+        // Unusable overrides fall through to the next source
+        assert_eq!(komi_input(Some(0.0), Some(15.0)), 0.5);
+        assert_eq!(komi_input(Some(f32::NAN), None), 7.5 / DEFAULT_KOMI_SCALE);
 
-/// Initialize the global engine with model bytes
-pub fn initialize_engine(model_bytes: &[u8]) -> Result<(), String> {
-    let engine = OnnxEngine::from_bytes(model_bytes)?;
-    let mut global = ENGINE.lock().map_err(|e| e.to_string())?;
-    *global = Some(engine);
-    Ok(())
-}
+        let with_metadata = |value: &str| crate::onnx_proto::ModelInfo {
+            metadata_props: vec![
+                ("author".into(), "x".into()),
+                ("komi_scale".into(), value.into()),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(model_komi_scale(&with_metadata(" 15 ")), Some(15.0));
+        assert_eq!(model_komi_scale(&with_metadata("fifteen")), None);
+        assert_eq!(model_komi_scale(&with_metadata("-1")), None);
+        assert_eq!(
+            model_komi_scale(&crate::onnx_proto::ModelInfo::default()),
+            None
+        );
+    }
 
-/// Initialize the global engine from a file path
-pub fn initialize_engine_from_path(model_path: &str) -> Result<(), String> {
-    let engine = OnnxEngine::new(Path::new(model_path))?;
-    let mut global = ENGINE.lock().map_err(|e| e.to_string())?;
-    *global = Some(engine);
-    Ok(())
-}
+    #[test]
+    fn a_separate_pass_output_decodes_like_an_appended_pass_logit() {
+        let sign_map = vec![vec![0i8; 5]; 5];
+        let options = AnalysisOptions::default();
+        let decode = |outputs: OnnxOutputs| {
+            let item = BatchItem {
+                sign_map: &sign_map,
+                pla: 1,
+                options: &options,
+            };
+            let result = process_batch_results(5, &outputs, &[item])
+                .unwrap()
+                .remove(0);
+            result
+                .move_suggestions
+                .iter()
+                .map(|s| (s.move_str.clone(), s.probability))
+                .collect::<Vec<_>>()
+        };
+        let board: Vec<f32> = (0..25).map(|i| i as f32 / 10.0).collect();
 
-/// Analyze a single position
-pub fn analyze_position(
-    sign_map: Vec<Vec<i8>>,
-    options: AnalysisOptions,
-) -> Result<AnalysisResult, String> {
-    let mut global = ENGINE.lock().map_err(|e| e.to_string())?;
-    let engine = global.as_mut().ok_or("Engine not initialized")?;
-    engine.analyze(&sign_map, &options)
-}
+        // Pass already last in the policy output
+        let mut combined = board.clone();
+        combined.push(4.0);
+        let expected = decode(single_outputs(combined));
+        assert_eq!(expected[0].0, "PASS");
 
-/// Analyze multiple positions in a batch
-pub fn analyze_batch(
-    inputs: Vec<(Vec<Vec<i8>>, AnalysisOptions)>,
-) -> Result<Vec<AnalysisResult>, String> {
-    let mut global = ENGINE.lock().map_err(|e| e.to_string())?;
-    let engine = global.as_mut().ok_or("Engine not initialized")?;
-    engine.analyze_batch(&inputs)
-}
+        // Board-only policy plus a policy_pass output
+        let (policy, dims) = append_pass_logits(&board, &[1, 25], &[4.0]).unwrap();
+        assert_eq!(dims, vec![1, 26]);
+        let mut separate = single_outputs(policy);
+        separate.policy_dims = dims;
+        assert_eq!(decode(separate), expected);
 
-/// Dispose the global engine
-pub fn dispose_engine() -> Result<(), String> {
-    let mut global = ENGINE.lock().map_err(|e| e.to_string())?;
-    *global = None;
-    Ok(())
-}
+        // Every head gets its own pass logit at the end of its row
+        let two_heads: Vec<f32> = [board.clone(), board.clone()].concat();
+        let (policy, dims) = append_pass_logits(&two_heads, &[1, 2, 25], &[4.0, -1.0]).unwrap();
+        assert_eq!(dims, vec![1, 2, 26]);
+        assert_eq!((policy[25], policy[51]), (4.0, -1.0));
+        assert_eq!(policy[26..51], board[..]);
 
-/// Check if engine is initialized
-pub fn is_engine_initialized() -> bool {
-    ENGINE.lock().map(|g| g.is_some()).unwrap_or(false)
-}
+        let mismatch = append_pass_logits(&two_heads, &[1, 2, 25], &[4.0]);
+        assert!(matches!(mismatch, Err(OnnxError::ShapeMismatch(_))));
+    }
 
-/// Get information about the current execution provider
-pub fn get_provider_info() -> Option<ExecutionProviderInfo> {
-    let global = ENGINE.lock().ok()?;
-    let engine = global.as_ref()?;
-    
-    let name = engine.get_provider_name();
-    let (is_gpu, description) = match name {
-        "cuda" => (true, "NVIDIA CUDA GPU acceleration"),
-        "coreml" => (true, "Apple CoreML (Metal/Neural Engine)"),
-        "directml" => (true, "Windows DirectML GPU acceleration"),
-        "nnapi" => (true, "Android NNAPI (Neural Networks API)"),
-        "cpu" => (false, "CPU (multi-threaded)"),
-        "auto" => {
-            // When auto is selected, we can't easily know which one is actually used
-            // ONNX Runtime doesn't provide a direct API for this
-            // We'll report it as "auto" with GPU likely
-            (true, "Auto-selected (GPU if available)")
-        }
-        _ => (false, "Unknown execution provider"),
-    };
-    
-    Some(ExecutionProviderInfo {
-        name: name.to_string(),
-        is_gpu,
-        description: description.to_string(),
-    })
-}
+    #[test]
+    fn a_cancelled_request_leaves_idle_sessions_in_the_pool() {
+        let mut state = PoolState {
+            idle: vec!["a", "b"],
+            waiting: vec![0, 2],
+        };
+        // Cancelled just as sessions are free: nothing is taken, the wait is over
+        assert!(matches!(state.poll(0, true), Err(OnnxError::Cancelled)));
+        assert_eq!(state.idle.len(), 2);
+        assert_eq!(state.waiting, vec![2]);
 
-/// Get available execution providers for this platform
-pub fn get_available_providers() -> Vec<ExecutionProviderInfo> {
-    let mut providers = vec![];
-    
-    // Auto is always available
-    providers.push(ExecutionProviderInfo {
-        name: "auto".to_string(),
-        is_gpu: true,
-        description: "Auto-select best available (recommended)".to_string(),
-    });
-    
-    // Platform-specific GPU providers
-    #[cfg(target_os = "android")]
-    providers.push(ExecutionProviderInfo {
-        name: "nnapi".to_string(),
-        is_gpu: true,
-        description: "Android NNAPI (Neural Networks API)".to_string(),
-    });
-    
-    #[cfg(target_os = "macos")]
-    providers.push(ExecutionProviderInfo {
-        name: "coreml".to_string(),
-        is_gpu: true,
-        description: "Apple CoreML (Metal/Neural Engine)".to_string(),
-    });
-    
-    #[cfg(target_os = "windows")]
-    {
-        providers.push(ExecutionProviderInfo {
-            name: "directml".to_string(),
-            is_gpu: true,
-            description: "DirectML (Windows GPU)".to_string(),
-        });
-        providers.push(ExecutionProviderInfo {
-            name: "cuda".to_string(),
-            is_gpu: true,
-            description: "NVIDIA CUDA (requires CUDA toolkit)".to_string(),
-        });
+        // Otherwise the highest priority takes a session and lower ones keep waiting
+        state.waiting.push(1);
+        assert_eq!(state.poll(1, false).unwrap(), None);
+        assert_eq!(state.poll(2, false).unwrap(), Some("b"));
+        assert_eq!(state.poll(1, false).unwrap(), Some("a"));
+        assert!(state.waiting.is_empty());
+        state.waiting.push(0);
+        assert_eq!(state.poll(0, false).unwrap(), None);
+        assert_eq!(state.waiting, vec![0]);
+    }
+
+    #[test]
+    fn game_positions_are_all_analyzed_from_black_view() {
+        let moves = [(1, 2, 2), (-1, 6, 6), (1, -1, -1), (-1, 2, 6)]
+            .map(|(color, x, y)| HistoryMove { color, x, y });
+        let options = GameAnalysisOptions {
+            board_size: 9,
+            komi: 6.5,
+        };
+        let positions = game_positions(&moves, &options).unwrap();
+        assert_eq!(positions.len(), 5);
+        let to_play: Vec<_> = positions
+            .iter()
+            .map(|(_, options)| options.next_to_play.as_deref().unwrap())
+            .collect();
+        assert_eq!(to_play, ["B", "W", "B", "W", "B"]);
+        // Whoever is to move, the deltas between neighbours compare Black's win rate
+        assert!(positions
+            .iter()
+            .all(|(_, options)| options.orient_to == Some(1) && options.komi == 6.5));
+        assert_eq!(positions[4].1.history.len(), 4);
+        assert_eq!(positions[4].0[6][2], -1);
+    }
+
+    #[test]
+    fn a_neutral_game_has_small_win_rate_deltas() {
+        if !load_test_model() {
+            return;
+        }
+        // Four corner points, answered symmetrically
+        let moves = [(1, 15, 3), (-1, 3, 15), (1, 15, 15), (-1, 3, 3)]
+            .map(|(color, x, y)| HistoryMove { color, x, y });
+        let options = GameAnalysisOptions {
+            board_size: 19,
+            komi: 7.5,
+        };
+        let cancel = CancelToken::register(None).unwrap();
+        let game = analyze_game(&moves, &options, &cancel, |_, _| {}).unwrap();
+        assert_eq!(game.win_rate_deltas.len(), 4);
+        for delta in &game.win_rate_deltas {
+            assert!(delta.abs() < 0.1, "{:?}", game.win_rate_deltas);
+        }
     }
-    
-    #[cfg(target_os = "linux")]
-    providers.push(ExecutionProviderInfo {
-        name: "cuda".to_string(),
-        is_gpu: true,
-        description: "NVIDIA CUDA (requires CUDA toolkit)".to_string(),
-    });
-    
-    // CPU is always available
-    providers.push(ExecutionProviderInfo {
-        name: "cpu".to_string(),
-        is_gpu: false,
-        description: "CPU only (most compatible)".to_string(),
-    });
-    
-    providers
 }
@@ -0,0 +1,595 @@
+//! Minimal reader for the ONNX protobuf format.
+//!
+//! Only the fields needed to inspect a model's graph signature are decoded.
+//! Large fields such as initializer weights are skipped with seeks, so a
+//! model can be inspected without reading it fully or creating a session.
+
+use std::fs::File;
+use std::io::{BufReader, Cursor, Read, Seek};
+use std::path::Path;
+
+const WIRE_VARINT: u8 = 0;
+const WIRE_FIXED64: u8 = 1;
+const WIRE_LEN: u8 = 2;
+const WIRE_FIXED32: u8 = 5;
+
+/// Upper bound for small submessages read into memory (value infos, metadata entries, ...)
+const MAX_SUBMESSAGE_LEN: u64 = 64 * 1024 * 1024;
+
+// ModelProto fields
+const MODEL_GRAPH: u32 = 7;
+const MODEL_METADATA_PROPS: u32 = 14;
+// GraphProto fields
+const GRAPH_NODE: u32 = 1;
+const GRAPH_INITIALIZER: u32 = 5;
+const GRAPH_INPUT: u32 = 11;
+const GRAPH_OUTPUT: u32 = 12;
+// NodeProto fields
+const NODE_OP_TYPE: u32 = 4;
+// TensorProto fields
+const TENSOR_EXTERNAL_DATA: u32 = 13;
+// StringStringEntryProto fields
+const ENTRY_KEY: u32 = 1;
+const ENTRY_VALUE: u32 = 2;
+// ValueInfoProto fields
+const VALUE_INFO_NAME: u32 = 1;
+const VALUE_INFO_TYPE: u32 = 2;
+// TypeProto fields
+const TYPE_TENSOR: u32 = 1;
+// TypeProto.Tensor fields
+const TENSOR_TYPE_ELEM_TYPE: u32 = 1;
+const TENSOR_TYPE_SHAPE: u32 = 2;
+// TensorShapeProto fields
+const SHAPE_DIM: u32 = 1;
+// TensorShapeProto.Dimension fields
+const DIM_VALUE: u32 = 1;
+
+/// A graph input or output
+#[derive(Debug, Clone, Default)]
+pub struct ValueInfo {
+    pub name: String,
+    /// ONNX `TensorProto.DataType` (1 = float, 10 = float16)
+    pub elem_type: i32,
+    /// Tensor dimensions, None for symbolic or unknown dimensions
+    pub shape: Vec<Option<i64>>,
+}
+
+/// The parts of a ModelProto read by [`read_model_info`]
+#[derive(Debug, Clone, Default)]
+pub struct ModelInfo {
+    pub inputs: Vec<ValueInfo>,
+    pub outputs: Vec<ValueInfo>,
+    /// Distinct operator types of the main graph's nodes, in order of first use
+    pub op_types: Vec<String>,
+    /// Distinct files holding initializer data outside the model, as given by their
+    /// `location` entries (relative to the model's directory)
+    pub external_data: Vec<String>,
+    /// Key/value pairs from the model's `metadata_props`, in file order
+    pub metadata_props: Vec<(String, String)>,
+}
+
+/// A decoded protobuf field value
+enum Field<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    Fixed,
+}
+
+/// Iterator over the fields of an in-memory protobuf message
+struct Fields<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Fields<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    fn varint(&mut self) -> Result<u64, String> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let (&byte, rest) = self
+                .buf
+                .split_first()
+                .ok_or_else(|| "Truncated varint".to_string())?;
+            self.buf = rest;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err("Varint too long".to_string())
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if len > self.buf.len() {
+            return Err("Truncated field".to_string());
+        }
+        let (head, rest) = self.buf.split_at(len);
+        self.buf = rest;
+        Ok(head)
+    }
+}
+
+impl<'a> Iterator for Fields<'a> {
+    type Item = Result<(u32, Field<'a>), String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.buf.is_empty() {
+            return None;
+        }
+        let field = (|| {
+            let tag = self.varint()?;
+            let number = (tag >> 3) as u32;
+            let value = match (tag & 0x7) as u8 {
+                WIRE_VARINT => Field::Varint(self.varint()?),
+                WIRE_FIXED64 => {
+                    self.take(8)?;
+                    Field::Fixed
+                }
+                WIRE_LEN => {
+                    let len = self.varint()? as usize;
+                    Field::Bytes(self.take(len)?)
+                }
+                WIRE_FIXED32 => {
+                    self.take(4)?;
+                    Field::Fixed
+                }
+                wire => return Err(format!("Unsupported wire type {}", wire)),
+            };
+            Ok((number, value))
+        })();
+        if field.is_err() {
+            // Stop iterating after a malformed field
+            self.buf = &[];
+        }
+        Some(field)
+    }
+}
+
+/// Read a varint from a stream, returning None on a clean end of stream
+fn read_varint<R: Read>(reader: &mut R) -> Result<Option<u64>, String> {
+    let mut value = 0u64;
+    for (i, shift) in (0..64).step_by(7).enumerate() {
+        let mut byte = [0u8];
+        if reader.read(&mut byte).map_err(|e| e.to_string())? == 0 {
+            return if i == 0 {
+                Ok(None)
+            } else {
+                Err("Truncated varint".to_string())
+            };
+        }
+        value |= u64::from(byte[0] & 0x7f) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(Some(value));
+        }
+    }
+    Err("Varint too long".to_string())
+}
+
+/// Streaming reader over a model file
+struct StreamReader<R> {
+    inner: BufReader<R>,
+    /// Bytes left in the message currently being read
+    remaining: u64,
+}
+
+impl<R: Read + Seek> StreamReader<R> {
+    /// Read the next field header, returning (field number, wire type, length)
+    /// The length is only meaningful for length-delimited fields
+    fn next_field(&mut self) -> Result<Option<(u32, u8, u64)>, String> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        let start = self.position()?;
+        let Some(tag) = read_varint(&mut self.inner)? else {
+            return Err("Unexpected end of file".to_string());
+        };
+        let wire = (tag & 0x7) as u8;
+        let len = match wire {
+            WIRE_LEN => read_varint(&mut self.inner)?.ok_or("Unexpected end of file")?,
+            _ => 0,
+        };
+        let header_len = self.position()? - start;
+        self.remaining = self
+            .remaining
+            .checked_sub(header_len)
+            .ok_or("Field header overruns message")?;
+        Ok(Some(((tag >> 3) as u32, wire, len)))
+    }
+
+    /// Skip the value of a field whose header was just read
+    fn skip(&mut self, wire: u8, len: u64) -> Result<(), String> {
+        let skip = match wire {
+            WIRE_VARINT => {
+                let start = self.position()?;
+                read_varint(&mut self.inner)?.ok_or("Unexpected end of file")?;
+                self.position()? - start
+            }
+            WIRE_FIXED64 => 8,
+            WIRE_LEN => len,
+            WIRE_FIXED32 => 4,
+            wire => return Err(format!("Unsupported wire type {}", wire)),
+        };
+        if wire != WIRE_VARINT {
+            self.inner
+                .seek_relative(skip as i64)
+                .map_err(|e| e.to_string())?;
+        }
+        self.consume(skip)
+    }
+
+    /// Read a length-delimited field value into memory
+    fn read_bytes(&mut self, len: u64) -> Result<Vec<u8>, String> {
+        if len > MAX_SUBMESSAGE_LEN {
+            return Err(format!("Field too large: {} bytes", len));
+        }
+        let mut buf = vec![0u8; len as usize];
+        self.inner
+            .read_exact(&mut buf)
+            .map_err(|e| format!("Failed to read model: {}", e))?;
+        self.consume(len)?;
+        Ok(buf)
+    }
+
+    fn consume(&mut self, len: u64) -> Result<(), String> {
+        self.remaining = self
+            .remaining
+            .checked_sub(len)
+            .ok_or("Field overruns message")?;
+        Ok(())
+    }
+
+    fn position(&mut self) -> Result<u64, String> {
+        self.inner.stream_position().map_err(|e| e.to_string())
+    }
+}
+
+/// Read the graph signature of an ONNX model file
+pub fn read_model_info(path: &Path) -> Result<ModelInfo, String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open {:?}: {}", path, e))?;
+    let len = file
+        .metadata()
+        .map_err(|e| format!("Failed to read metadata for {:?}: {}", path, e))?
+        .len();
+    read_model(file, len)
+}
+
+/// Read the graph signature of an ONNX model held in memory
+pub fn read_model_info_from_bytes(bytes: &[u8]) -> Result<ModelInfo, String> {
+    read_model(Cursor::new(bytes), bytes.len() as u64)
+}
+
+fn read_model<R: Read + Seek>(inner: R, len: u64) -> Result<ModelInfo, String> {
+    let mut reader = StreamReader {
+        inner: BufReader::new(inner),
+        remaining: len,
+    };
+
+    let mut info = ModelInfo::default();
+    let mut found_graph = false;
+    while let Some((field, wire, len)) = reader.next_field()? {
+        if field == MODEL_GRAPH && wire == WIRE_LEN {
+            let after_graph = reader
+                .remaining
+                .checked_sub(len)
+                .ok_or("Graph overruns model file")?;
+            reader.remaining = len;
+            read_graph(&mut reader, &mut info)?;
+            reader.remaining = after_graph;
+            found_graph = true;
+        } else if field == MODEL_METADATA_PROPS && wire == WIRE_LEN {
+            let (key, value) = parse_entry(&reader.read_bytes(len)?)?;
+            info.metadata_props.push((key, value));
+        } else {
+            reader.skip(wire, len)?;
+        }
+    }
+
+    if !found_graph {
+        return Err("Not an ONNX model: no graph found".to_string());
+    }
+    Ok(info)
+}
+
+fn read_graph<R: Read + Seek>(
+    reader: &mut StreamReader<R>,
+    info: &mut ModelInfo,
+) -> Result<(), String> {
+    while let Some((field, wire, len)) = reader.next_field()? {
+        match (field, wire) {
+            (GRAPH_NODE, WIRE_LEN) => {
+                let op_type = read_op_type(reader, len)?;
+                if !info.op_types.contains(&op_type) {
+                    info.op_types.push(op_type);
+                }
+            }
+            (GRAPH_INITIALIZER, WIRE_LEN) => read_initializer(reader, len, info)?,
+            (GRAPH_INPUT, WIRE_LEN) => info
+                .inputs
+                .push(parse_value_info(&reader.read_bytes(len)?)?),
+            (GRAPH_OUTPUT, WIRE_LEN) => info
+                .outputs
+                .push(parse_value_info(&reader.read_bytes(len)?)?),
+            _ => reader.skip(wire, len)?,
+        }
+    }
+    Ok(())
+}
+
+/// Read a node's operator type, seeking over its other fields
+/// (the attribute of a `Constant` node holds a whole tensor)
+fn read_op_type<R: Read + Seek>(reader: &mut StreamReader<R>, len: u64) -> Result<String, String> {
+    let after_node = reader
+        .remaining
+        .checked_sub(len)
+        .ok_or("Node overruns graph")?;
+    reader.remaining = len;
+    let mut op_type = String::new();
+    while let Some((field, wire, len)) = reader.next_field()? {
+        if field == NODE_OP_TYPE && wire == WIRE_LEN {
+            op_type = String::from_utf8_lossy(&reader.read_bytes(len)?).to_string();
+        } else {
+            reader.skip(wire, len)?;
+        }
+    }
+    reader.remaining = after_node;
+    Ok(op_type)
+}
+
+/// Read an initializer's external data location, seeking over any inline weights
+fn read_initializer<R: Read + Seek>(
+    reader: &mut StreamReader<R>,
+    len: u64,
+    info: &mut ModelInfo,
+) -> Result<(), String> {
+    let after_initializer = reader
+        .remaining
+        .checked_sub(len)
+        .ok_or("Initializer overruns graph")?;
+    reader.remaining = len;
+    while let Some((field, wire, len)) = reader.next_field()? {
+        if field == TENSOR_EXTERNAL_DATA && wire == WIRE_LEN {
+            if let Some(location) = parse_external_location(&reader.read_bytes(len)?)? {
+                if !info.external_data.contains(&location) {
+                    info.external_data.push(location);
+                }
+            }
+        } else {
+            reader.skip(wire, len)?;
+        }
+    }
+    reader.remaining = after_initializer;
+    Ok(())
+}
+
+/// Parse an external data entry, returning its value if it is the `location` key
+fn parse_external_location(buf: &[u8]) -> Result<Option<String>, String> {
+    let (mut key, mut value) = (None, None);
+    for field in Fields::new(buf) {
+        match field? {
+            (ENTRY_KEY, Field::Bytes(bytes)) => key = Some(bytes),
+            (ENTRY_VALUE, Field::Bytes(bytes)) => value = Some(bytes),
+            _ => {}
+        }
+    }
+    Ok(match (key, value) {
+        (Some(b"location"), Some(location)) => Some(String::from_utf8_lossy(location).to_string()),
+        _ => None,
+    })
+}
+
+/// Parse a StringStringEntryProto into its key and value
+fn parse_entry(buf: &[u8]) -> Result<(String, String), String> {
+    let (mut key, mut value) = (String::new(), String::new());
+    for field in Fields::new(buf) {
+        match field? {
+            (ENTRY_KEY, Field::Bytes(bytes)) => key = String::from_utf8_lossy(bytes).to_string(),
+            (ENTRY_VALUE, Field::Bytes(bytes)) => {
+                value = String::from_utf8_lossy(bytes).to_string()
+            }
+            _ => {}
+        }
+    }
+    Ok((key, value))
+}
+
+fn parse_value_info(buf: &[u8]) -> Result<ValueInfo, String> {
+    let mut info = ValueInfo::default();
+    for field in Fields::new(buf) {
+        match field? {
+            (VALUE_INFO_NAME, Field::Bytes(name)) => {
+                info.name = String::from_utf8_lossy(name).to_string();
+            }
+            (VALUE_INFO_TYPE, Field::Bytes(type_proto)) => {
+                for field in Fields::new(type_proto) {
+                    if let (TYPE_TENSOR, Field::Bytes(tensor_type)) = field? {
+                        parse_tensor_type(tensor_type, &mut info)?;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(info)
+}
+
+fn parse_tensor_type(buf: &[u8], info: &mut ValueInfo) -> Result<(), String> {
+    for field in Fields::new(buf) {
+        match field? {
+            (TENSOR_TYPE_ELEM_TYPE, Field::Varint(elem_type)) => info.elem_type = elem_type as i32,
+            (TENSOR_TYPE_SHAPE, Field::Bytes(shape)) => {
+                for field in Fields::new(shape) {
+                    if let (SHAPE_DIM, Field::Bytes(dim)) = field? {
+                        info.shape.push(parse_dimension(dim)?);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Parse a dimension, returning None for symbolic (`dim_param`) dimensions
+fn parse_dimension(buf: &[u8]) -> Result<Option<i64>, String> {
+    for field in Fields::new(buf) {
+        if let (DIM_VALUE, Field::Varint(value)) = field? {
+            return Ok(Some(value as i64));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::SeekFrom;
+
+    /// Protobuf message assembled field by field
+    #[derive(Default)]
+    struct Message(Vec<u8>);
+
+    impl Message {
+        fn varint(mut self, field: u32, value: u64) -> Self {
+            put_varint(&mut self.0, u64::from(field) << 3 | u64::from(WIRE_VARINT));
+            put_varint(&mut self.0, value);
+            self
+        }
+
+        fn bytes(mut self, field: u32, value: &[u8]) -> Self {
+            put_varint(&mut self.0, u64::from(field) << 3 | u64::from(WIRE_LEN));
+            put_varint(&mut self.0, value.len() as u64);
+            self.0.extend_from_slice(value);
+            self
+        }
+
+        fn message(self, field: u32, value: Message) -> Self {
+            self.bytes(field, &value.0)
+        }
+    }
+
+    fn put_varint(buf: &mut Vec<u8>, mut value: u64) {
+        while value >= 0x80 {
+            buf.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        buf.push(value as u8);
+    }
+
+    /// A float input of shape [N, 22, 19, 19] with a symbolic batch dimension
+    fn bin_input() -> Message {
+        let shape = Message::default()
+            .message(SHAPE_DIM, Message::default().bytes(2, b"N"))
+            .message(SHAPE_DIM, Message::default().varint(DIM_VALUE, 22))
+            .message(SHAPE_DIM, Message::default().varint(DIM_VALUE, 19))
+            .message(SHAPE_DIM, Message::default().varint(DIM_VALUE, 19));
+        let tensor_type = Message::default()
+            .varint(TENSOR_TYPE_ELEM_TYPE, 1)
+            .message(TENSOR_TYPE_SHAPE, shape);
+        Message::default()
+            .bytes(VALUE_INFO_NAME, b"input_binary")
+            .message(
+                VALUE_INFO_TYPE,
+                Message::default().message(TYPE_TENSOR, tensor_type),
+            )
+    }
+
+    /// Counts the bytes actually read from the underlying model
+    struct CountingReader {
+        inner: Cursor<Vec<u8>>,
+        read: usize,
+    }
+
+    impl Read for CountingReader {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = self.inner.read(buf)?;
+            self.read += n;
+            Ok(n)
+        }
+    }
+
+    impl Seek for CountingReader {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    #[test]
+    fn reads_the_signature_of_a_quantized_model() {
+        let external = Message::default()
+            .bytes(ENTRY_KEY, b"location")
+            .bytes(ENTRY_VALUE, b"weights.bin");
+        let graph = Message::default()
+            .message(
+                GRAPH_NODE,
+                Message::default()
+                    .bytes(1, b"x")
+                    .bytes(NODE_OP_TYPE, b"DequantizeLinear"),
+            )
+            .message(GRAPH_NODE, Message::default().bytes(NODE_OP_TYPE, b"Conv"))
+            .message(
+                GRAPH_NODE,
+                Message::default().bytes(NODE_OP_TYPE, b"DequantizeLinear"),
+            )
+            .message(
+                GRAPH_INITIALIZER,
+                Message::default().message(TENSOR_EXTERNAL_DATA, external),
+            )
+            .message(GRAPH_INPUT, bin_input())
+            .message(
+                GRAPH_OUTPUT,
+                Message::default().bytes(VALUE_INFO_NAME, b"output_policy"),
+            );
+        let model = Message::default()
+            .varint(1, 8)
+            .message(MODEL_GRAPH, graph)
+            .message(
+                MODEL_METADATA_PROPS,
+                Message::default()
+                    .bytes(ENTRY_KEY, b"komi_scale")
+                    .bytes(ENTRY_VALUE, b"15"),
+            );
+
+        let info = read_model_info_from_bytes(&model.0).unwrap();
+        assert_eq!(info.op_types, ["DequantizeLinear", "Conv"]);
+        assert_eq!(info.external_data, ["weights.bin"]);
+        assert_eq!(
+            info.metadata_props,
+            [("komi_scale".to_string(), "15".to_string())]
+        );
+        assert_eq!(info.inputs[0].name, "input_binary");
+        assert_eq!(info.inputs[0].elem_type, 1);
+        assert_eq!(info.inputs[0].shape, [None, Some(22), Some(19), Some(19)]);
+        assert_eq!(info.outputs[0].name, "output_policy");
+        assert!(crate::onnx_engine::is_quantized_model(&info));
+        assert_eq!(crate::onnx_engine::model_board_size(&info), Some(19));
+
+        assert!(read_model_info_from_bytes(&Message::default().varint(1, 8).0).is_err());
+    }
+
+    #[test]
+    fn node_attributes_are_skipped_without_being_read() {
+        const ATTRIBUTE_LEN: usize = 1 << 20;
+        let constant = Message::default()
+            .bytes(5, &vec![0u8; ATTRIBUTE_LEN])
+            .bytes(NODE_OP_TYPE, b"Constant");
+        let model = Message::default().message(
+            MODEL_GRAPH,
+            Message::default().message(GRAPH_NODE, constant),
+        );
+        let len = model.0.len() as u64;
+        let mut reader = CountingReader {
+            inner: Cursor::new(model.0),
+            read: 0,
+        };
+
+        let info = read_model(&mut reader, len).unwrap();
+        assert_eq!(info.op_types, ["Constant"]);
+        assert!(
+            reader.read < ATTRIBUTE_LEN / 10,
+            "read {} bytes",
+            reader.read
+        );
+    }
+}
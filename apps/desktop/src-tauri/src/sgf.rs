@@ -0,0 +1,252 @@
+//! Minimal SGF reader for turning a game record into an analysis input.
+//!
+//! Only the main line (first variation) of the first game tree is read.
+//! Setup stones (AB/AW/AE), moves (B/W), board size (SZ), komi (KM) and
+//! player to move (PL) are interpreted; every other property is ignored.
+
+use crate::board::GoBoard;
+use crate::onnx_engine::{AnalysisOptions, HistoryMove};
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_BOARD_SIZE: usize = 19;
+/// Largest board that SGF point coordinates (a-z) can address
+const MAX_BOARD_SIZE: usize = 26;
+
+/// A position replayed from an SGF file, ready for analysis
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SgfPosition {
+    pub sign_map: Vec<Vec<i8>>,
+    pub options: AnalysisOptions,
+}
+
+/// An SGF node as a list of (property, values) pairs
+type Node = Vec<(String, Vec<String>)>;
+
+struct Parser<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self.pos < self.input.len() && self.input[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.input.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), String> {
+        if self.peek() != Some(c) {
+            return Err(format!(
+                "Invalid SGF: expected '{}' at offset {}",
+                c as char, self.pos
+            ));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    /// Parse a game tree, appending the nodes of its main line to `nodes`
+    fn game_tree(&mut self, nodes: &mut Vec<Node>) -> Result<(), String> {
+        self.expect(b'(')?;
+        while self.peek() == Some(b';') {
+            self.pos += 1;
+            nodes.push(self.node()?);
+        }
+        // Follow the first variation, skip the others
+        if self.peek() == Some(b'(') {
+            self.game_tree(nodes)?;
+            while self.peek() == Some(b'(') {
+                self.game_tree(&mut Vec::new())?;
+            }
+        }
+        self.expect(b')')
+    }
+
+    fn node(&mut self) -> Result<Node, String> {
+        let mut node = Node::new();
+        while self.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+            // FF[3] allows lowercase letters in identifiers (e.g. "AddBlack"), keep only the uppercase ones
+            let mut ident = String::new();
+            while let Some(&c) = self.input.get(self.pos).filter(|c| c.is_ascii_alphabetic()) {
+                if c.is_ascii_uppercase() {
+                    ident.push(c as char);
+                }
+                self.pos += 1;
+            }
+            let mut values = Vec::new();
+            while self.peek() == Some(b'[') {
+                values.push(self.value()?);
+            }
+            if values.is_empty() {
+                return Err(format!("Invalid SGF: property {} has no value", ident));
+            }
+            node.push((ident, values));
+        }
+        Ok(node)
+    }
+
+    fn value(&mut self) -> Result<String, String> {
+        self.expect(b'[')?;
+        let mut value = Vec::new();
+        loop {
+            match self.input.get(self.pos) {
+                None => return Err("Invalid SGF: unterminated property value".to_string()),
+                Some(b']') => break,
+                Some(b'\\') => {
+                    self.pos += 1;
+                    if let Some(&c) = self.input.get(self.pos) {
+                        value.push(c);
+                    }
+                }
+                Some(&c) => value.push(c),
+            }
+            self.pos += 1;
+        }
+        self.pos += 1;
+        Ok(String::from_utf8_lossy(&value).trim().to_string())
+    }
+}
+
+/// Parse an SGF point ("pd"), returning None for a pass ("" or "tt" on boards up to 19x19)
+fn parse_point(value: &str, size: usize) -> Result<Option<(usize, usize)>, String> {
+    if value.is_empty() || (value == "tt" && size <= 19) {
+        return Ok(None);
+    }
+    let bytes = value.as_bytes();
+    if bytes.len() != 2 || !bytes.iter().all(|c| c.is_ascii_lowercase()) {
+        return Err(format!("Invalid SGF point: {}", value));
+    }
+    let (x, y) = ((bytes[0] - b'a') as usize, (bytes[1] - b'a') as usize);
+    if x >= size || y >= size {
+        return Err(format!(
+            "SGF point {} is outside the {}x{} board",
+            value, size, size
+        ));
+    }
+    Ok(Some((x, y)))
+}
+
+/// Expand a point or a compressed rectangle ("aa:cc") into its points
+fn parse_point_list(value: &str, size: usize) -> Result<Vec<(usize, usize)>, String> {
+    let Some((from, to)) = value.split_once(':') else {
+        return Ok(parse_point(value, size)?.into_iter().collect());
+    };
+    let (Some((x1, y1)), Some((x2, y2))) = (parse_point(from, size)?, parse_point(to, size)?)
+    else {
+        return Err(format!("Invalid SGF point list: {}", value));
+    };
+    let mut points = Vec::new();
+    for y in y1.min(y2)..=y1.max(y2) {
+        for x in x1.min(x2)..=x1.max(x2) {
+            points.push((x, y));
+        }
+    }
+    Ok(points)
+}
+
+fn color_to_player(color: i8) -> String {
+    if color == 1 { "B" } else { "W" }.to_string()
+}
+
+/// Replay the main line of an SGF up to `move_number` moves (all moves if None)
+/// Returns the board (1 = Black, -1 = White), komi, player to move, move history and
+/// ko point; fails on an illegal move (occupied point, suicide or ko retake)
+pub fn sgf_to_signmap(
+    sgf: &str,
+    move_number: Option<usize>,
+) -> Result<(Vec<Vec<i8>>, AnalysisOptions), String> {
+    let mut parser = Parser {
+        input: sgf.as_bytes(),
+        pos: 0,
+    };
+    let mut nodes = Vec::new();
+    parser.game_tree(&mut nodes)?;
+
+    let root = nodes.first().ok_or("Invalid SGF: no nodes")?;
+    let root_value = |ident: &str| {
+        root.iter()
+            .find(|(id, _)| id == ident)
+            .map(|(_, values)| values[0].as_str())
+    };
+
+    let size = match root_value("SZ") {
+        Some(sz) => sz
+            .parse::<usize>()
+            .map_err(|_| format!("Unsupported board size: {}", sz))?,
+        None => DEFAULT_BOARD_SIZE,
+    };
+    if !(2..=MAX_BOARD_SIZE).contains(&size) {
+        return Err(format!("Unsupported board size: {}", size));
+    }
+
+    let mut options = AnalysisOptions::default();
+    if let Some(km) = root_value("KM") {
+        options.komi = km.parse().map_err(|_| format!("Invalid komi: {}", km))?;
+    }
+
+    let mut board = GoBoard::new(size)?;
+    let mut next_color = 1i8;
+    let mut moves_played = 0;
+
+    for node in &nodes {
+        let has_move = node.iter().any(|(ident, _)| ident == "B" || ident == "W");
+        if has_move && move_number.is_some_and(|n| moves_played >= n) {
+            break;
+        }
+        for (ident, values) in node {
+            match ident.as_str() {
+                "AB" | "AW" | "AE" => {
+                    let color = match ident.as_str() {
+                        "AB" => 1,
+                        "AW" => -1,
+                        _ => 0,
+                    };
+                    for value in values {
+                        for (x, y) in parse_point_list(value, size)? {
+                            board.set_stone(x, y, color)?;
+                        }
+                    }
+                    // Handicap stones give White the first move unless PL says otherwise
+                    if ident == "AB" && moves_played == 0 {
+                        next_color = -1;
+                    }
+                }
+                "PL" => {
+                    next_color = if values[0].eq_ignore_ascii_case("W") {
+                        -1
+                    } else {
+                        1
+                    }
+                }
+                "B" | "W" => {
+                    let color = if ident == "B" { 1 } else { -1 };
+                    let point = parse_point(&values[0], size)?;
+                    let (x, y) = point.map_or((-1, -1), |(x, y)| (x as i32, y as i32));
+                    board
+                        .play(x, y, color)
+                        .map_err(|e| format!("Illegal SGF move {}: {}", moves_played + 1, e))?;
+                    options.history.push(HistoryMove { color, x, y });
+                    next_color = -color;
+                    moves_played += 1;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    if let Some(n) = move_number {
+        if moves_played < n {
+            return Err(format!("SGF main line only has {} moves", moves_played));
+        }
+    }
+
+    options.next_to_play = Some(color_to_player(next_color));
+    options.ko_point = board.ko_point().map(|(x, y)| (x as i32, y as i32));
+    Ok((board.to_signmap(), options))
+}
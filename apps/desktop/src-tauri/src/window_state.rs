@@ -9,6 +9,9 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::OnceLock;
+use std::time::Duration;
 use tauri::{AppHandle, Manager, Monitor, PhysicalPosition, PhysicalSize, WebviewWindow, Window};
 
 /// Window state for a specific position and size
@@ -19,6 +22,8 @@ pub struct WindowState {
     pub width: u32,
     pub height: u32,
     pub maximized: bool,
+    #[serde(default)]
+    pub fullscreen: bool,
 }
 
 /// Collection of window states keyed by monitor fingerprint
@@ -28,6 +33,9 @@ pub struct MonitorWindowStates {
     pub states: HashMap<String, WindowState>,
     /// Fallback state when no monitor-specific state exists
     pub default_state: Option<WindowState>,
+    /// Name of the monitor the window was last on
+    #[serde(default)]
+    pub last_monitor_name: Option<String>,
 }
 
 impl MonitorWindowStates {
@@ -62,9 +70,7 @@ impl MonitorWindowStates {
 
     /// Get state for a specific monitor fingerprint
     pub fn get_for_monitor(&self, fingerprint: &str) -> Option<&WindowState> {
-        self.states
-            .get(fingerprint)
-            .or(self.default_state.as_ref())
+        self.states.get(fingerprint).or(self.default_state.as_ref())
     }
 
     /// Set state for a specific monitor fingerprint
@@ -75,29 +81,120 @@ impl MonitorWindowStates {
     }
 }
 
+/// Name and bounds of a connected monitor, in physical pixels
+#[derive(Debug, Clone, PartialEq)]
+struct MonitorArea {
+    name: Option<String>,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+}
+
+impl MonitorArea {
+    fn of(monitor: &Monitor) -> Self {
+        Self {
+            name: monitor.name().cloned(),
+            x: monitor.position().x,
+            y: monitor.position().y,
+            width: monitor.size().width,
+            height: monitor.size().height,
+        }
+    }
+
+    /// Top-left corner that centers a `width`x`height` window on this monitor
+    fn centered(&self, width: u32, height: u32) -> (i32, i32) {
+        (
+            self.x + (self.width as i32 - width as i32) / 2,
+            self.y + (self.height as i32 - height as i32) / 2,
+        )
+    }
+}
+
 /// Generate a fingerprint for a monitor based on its dimensions.
 /// Format: "WxH" (e.g., "2560x1440" or "1920x1080")
 ///
 /// For multi-monitor setups with identical monitors, we include
 /// the monitor's position to differentiate them.
 pub fn monitor_fingerprint(monitor: &Monitor, all_monitors: &[Monitor]) -> String {
-    let size = monitor.size();
-    let pos = monitor.position();
+    let all: Vec<MonitorArea> = all_monitors.iter().map(MonitorArea::of).collect();
+    area_fingerprint(&MonitorArea::of(monitor), &all)
+}
 
+/// [`monitor_fingerprint`] of a monitor's bounds
+fn area_fingerprint(monitor: &MonitorArea, all_monitors: &[MonitorArea]) -> String {
     // Check if there are other monitors with the same dimensions
     let same_size_count = all_monitors
         .iter()
-        .filter(|m| m.size().width == size.width && m.size().height == size.height)
+        .filter(|m| m.width == monitor.width && m.height == monitor.height)
         .count();
 
     if same_size_count > 1 {
         // Include position for disambiguation
-        format!("{}x{}@{},{}", size.width, size.height, pos.x, pos.y)
+        format!(
+            "{}x{}@{},{}",
+            monitor.width, monitor.height, monitor.x, monitor.y
+        )
     } else {
-        format!("{}x{}", size.width, size.height)
+        format!("{}x{}", monitor.width, monitor.height)
+    }
+}
+
+/// Quiet time after the last `Moved`/`Resized` event before the state is saved
+const SAVE_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Calls `flush` with the latest value once no newer one has arrived for `delay`,
+/// on a background thread
+struct Debouncer<T> {
+    sender: mpsc::Sender<T>,
+}
+
+impl<T: Send + 'static> Debouncer<T> {
+    fn new(delay: Duration, flush: impl Fn(T) + Send + 'static) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            let mut pending = None;
+            loop {
+                let received = match pending {
+                    Some(_) => receiver.recv_timeout(delay),
+                    None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+                };
+                match received {
+                    Ok(value) => pending = Some(value),
+                    Err(RecvTimeoutError::Timeout) => flush(pending.take().unwrap()),
+                    Err(RecvTimeoutError::Disconnected) => {
+                        if let Some(value) = pending.take() {
+                            flush(value);
+                        }
+                        return;
+                    }
+                }
+            }
+        });
+        Self { sender }
+    }
+
+    fn push(&self, value: T) {
+        let _ = self.sender.send(value);
     }
 }
 
+/// Debouncer behind [`save_window_state_debounced`]
+static SAVE_DEBOUNCER: OnceLock<Debouncer<(Window, AppHandle)>> = OnceLock::new();
+
+/// Save the window state once the bursts of `Moved`/`Resized` events while dragging
+/// have been quiet for [`SAVE_DEBOUNCE`], so the final bounds of a drag are written
+/// even if the app is killed before `CloseRequested`
+pub fn save_window_state_debounced(window: &Window, app: &AppHandle) {
+    SAVE_DEBOUNCER
+        .get_or_init(|| {
+            Debouncer::new(SAVE_DEBOUNCE, |(window, app)| {
+                save_window_state_from_window(&window, &app)
+            })
+        })
+        .push((window.clone(), app.clone()));
+}
+
 /// Save the current window state for the current monitor (for Window)
 pub fn save_window_state_from_window(window: &Window, app: &AppHandle) {
     save_window_state_impl(
@@ -105,22 +202,25 @@ pub fn save_window_state_from_window(window: &Window, app: &AppHandle) {
         || window.outer_position(),
         || window.outer_size(),
         || window.is_maximized(),
+        || window.is_fullscreen(),
         app,
     );
 }
 
 /// Internal implementation for saving window state
-fn save_window_state_impl<F1, F2, F3, F4>(
+fn save_window_state_impl<F1, F2, F3, F4, F5>(
     get_monitors: F1,
     get_position: F2,
     get_size: F3,
     get_maximized: F4,
+    get_fullscreen: F5,
     app: &AppHandle,
 ) where
     F1: FnOnce() -> Result<Vec<Monitor>, tauri::Error>,
     F2: FnOnce() -> Result<PhysicalPosition<i32>, tauri::Error>,
     F3: FnOnce() -> Result<PhysicalSize<u32>, tauri::Error>,
     F4: FnOnce() -> Result<bool, tauri::Error>,
+    F5: FnOnce() -> Result<bool, tauri::Error>,
 {
     let monitors = match get_monitors() {
         Ok(m) => m,
@@ -140,6 +240,7 @@ fn save_window_state_impl<F1, F2, F3, F4>(
         Err(_) => return,
     };
     let maximized = get_maximized().unwrap_or(false);
+    let fullscreen = get_fullscreen().unwrap_or(false);
 
     // Find current monitor based on window center
     let win_center_x = pos.x + (size.width as i32) / 2;
@@ -156,25 +257,42 @@ fn save_window_state_impl<F1, F2, F3, F4>(
 
     let target_monitor = current_monitor.unwrap_or(&monitors[0]);
     let fingerprint = monitor_fingerprint(target_monitor, &monitors);
+    let monitor_name = target_monitor.name().cloned();
+
+    let mut states = MonitorWindowStates::load(app);
 
-    let state = WindowState {
-        x: pos.x,
-        y: pos.y,
-        width: size.width,
-        height: size.height,
-        maximized,
+    // While maximized or fullscreen the window reports the enlarged bounds,
+    // so keep the previously saved normal bounds to un-maximize into
+    let previous = states
+        .states
+        .get(&fingerprint)
+        .filter(|_| maximized || fullscreen);
+    let state = match previous {
+        Some(previous) => WindowState {
+            maximized,
+            fullscreen,
+            ..previous.clone()
+        },
+        None => WindowState {
+            x: pos.x,
+            y: pos.y,
+            width: size.width,
+            height: size.height,
+            maximized,
+            fullscreen,
+        },
     };
 
-    // Load, update, and save
-    let mut states = MonitorWindowStates::load(app);
+    // Update and save
     states.set_for_monitor(fingerprint, state);
+    states.last_monitor_name = monitor_name;
     let _ = states.save(app);
 }
 
 /// Restore window state for the current monitor, with fallback logic
 pub fn restore_window_state(window: &WebviewWindow, app: &AppHandle) {
-    let monitors: Vec<Monitor> = match window.available_monitors() {
-        Ok(m) => m,
+    let monitors: Vec<MonitorArea> = match window.available_monitors() {
+        Ok(m) => m.iter().map(MonitorArea::of).collect(),
         Err(_) => return,
     };
 
@@ -182,74 +300,150 @@ pub fn restore_window_state(window: &WebviewWindow, app: &AppHandle) {
         return;
     }
 
-    // Get primary or first monitor
-    let primary = window.primary_monitor().ok().flatten();
-    let target_monitor = primary.as_ref().unwrap_or(&monitors[0]);
-    let fingerprint = monitor_fingerprint(target_monitor, &monitors);
-
-    let mon_pos = target_monitor.position();
-    let mon_size = target_monitor.size();
-
     // Load states
     let states = MonitorWindowStates::load(app);
+    let primary = window
+        .primary_monitor()
+        .ok()
+        .flatten()
+        .map(|m| MonitorArea::of(&m));
+
+    match plan_restore(&states, &monitors, primary.as_ref()) {
+        Placement::Bounds {
+            x,
+            y,
+            width,
+            height,
+            maximized,
+            fullscreen,
+        } => {
+            let _ = window.set_size(tauri::Size::Physical(PhysicalSize { width, height }));
+            let _ = window.set_position(tauri::Position::Physical(PhysicalPosition { x, y }));
+
+            // Reapply after positioning so un-maximizing returns to the normal bounds
+            if fullscreen {
+                let _ = window.set_fullscreen(true);
+            } else if maximized {
+                let _ = window.maximize();
+            }
+        }
+        Placement::FitTo(monitor) => ensure_window_fits(window, &monitor),
+    }
+}
 
-    if let Some(state) = states.get_for_monitor(&fingerprint) {
-        // We have a saved state for this monitor
-        // Validate it still fits (in case monitor resolution changed)
-        let margin = 50;
-        let max_width = (mon_size.width as i32 - margin * 2).max(800) as u32;
-        let max_height = (mon_size.height as i32 - margin * 2).max(600) as u32;
+/// Where [`restore_window_state`] puts the window
+#[derive(Debug, PartialEq)]
+enum Placement {
+    /// Move and resize the window, then maximize it or make it fullscreen
+    Bounds {
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+        maximized: bool,
+        fullscreen: bool,
+    },
+    /// Nothing is saved for the monitor: keep the window, shrunk to fit if needed
+    FitTo(MonitorArea),
+}
 
-        let width = state.width.min(max_width).max(1000);
-        let height = state.height.min(max_height).max(700);
+/// Default window size used when the saved state cannot be restored
+const DEFAULT_WIDTH: u32 = 1280;
+const DEFAULT_HEIGHT: u32 = 800;
+
+/// Decide where to restore the window, given the connected monitors
+fn plan_restore(
+    states: &MonitorWindowStates,
+    monitors: &[MonitorArea],
+    primary: Option<&MonitorArea>,
+) -> Placement {
+    // Prefer the monitor the window was last on if it is still connected,
+    // otherwise the primary or first monitor
+    let last_monitor = states
+        .last_monitor_name
+        .as_ref()
+        .and_then(|name| monitors.iter().find(|m| m.name.as_ref() == Some(name)));
+    let target_monitor = last_monitor.or(primary).unwrap_or(&monitors[0]);
+    let fingerprint = area_fingerprint(target_monitor, monitors);
+
+    let Some(state) = states.get_for_monitor(&fingerprint) else {
+        // No saved state for this monitor - check if window fits
+        return Placement::FitTo(target_monitor.clone());
+    };
 
-        // Check if position is valid for this monitor
-        let x_valid = state.x >= mon_pos.x
-            && state.x + width as i32 <= mon_pos.x + mon_size.width as i32;
-        let y_valid = state.y >= mon_pos.y
-            && state.y + height as i32 <= mon_pos.y + mon_size.height as i32;
+    let margin = 50;
+    let max_width = (target_monitor.width as i32 - margin * 2).max(800) as u32;
+    let max_height = (target_monitor.height as i32 - margin * 2).max(600) as u32;
+
+    if !intersects_any_monitor(state, monitors) {
+        // The saved position is on a monitor that is no longer connected:
+        // center at the default size, shrunk to fit if needed
+        let width = DEFAULT_WIDTH.min(max_width);
+        let height = DEFAULT_HEIGHT.min(max_height);
+        let (x, y) = target_monitor.centered(width, height);
+        return Placement::Bounds {
+            x,
+            y,
+            width,
+            height,
+            maximized: false,
+            fullscreen: false,
+        };
+    }
 
-        if x_valid && y_valid && width == state.width && height == state.height {
-            // Position and size are valid, restore exactly
-            let _ = window.set_size(tauri::Size::Physical(PhysicalSize { width, height }));
-            let _ = window.set_position(tauri::Position::Physical(PhysicalPosition {
-                x: state.x,
-                y: state.y,
-            }));
-        } else {
-            // Size needs adjustment or position is invalid
-            let _ = window.set_size(tauri::Size::Physical(PhysicalSize { width, height }));
-            // Center on monitor
-            let new_x = mon_pos.x + (mon_size.width as i32 - width as i32) / 2;
-            let new_y = mon_pos.y + (mon_size.height as i32 - height as i32) / 2;
-            let _ = window.set_position(tauri::Position::Physical(PhysicalPosition {
-                x: new_x,
-                y: new_y,
-            }));
-        }
+    // Validate it still fits (in case monitor resolution changed)
+    let width = state.width.min(max_width).max(1000);
+    let height = state.height.min(max_height).max(700);
 
-        if state.maximized {
-            let _ = window.maximize();
-        }
+    // Check if position is valid for this monitor
+    let x_valid = state.x >= target_monitor.x
+        && state.x + width as i32 <= target_monitor.x + target_monitor.width as i32;
+    let y_valid = state.y >= target_monitor.y
+        && state.y + height as i32 <= target_monitor.y + target_monitor.height as i32;
+
+    // Restore exactly if position and size are valid, otherwise center on the monitor
+    let (x, y) = if x_valid && y_valid && width == state.width && height == state.height {
+        (state.x, state.y)
     } else {
-        // No saved state for this monitor - check if window fits
-        ensure_window_fits(window, target_monitor);
+        target_monitor.centered(width, height)
+    };
+    Placement::Bounds {
+        x,
+        y,
+        width,
+        height,
+        maximized: state.maximized,
+        fullscreen: state.fullscreen,
     }
 }
 
+/// Check whether a saved window rectangle overlaps at least one connected monitor
+fn intersects_any_monitor(state: &WindowState, monitors: &[MonitorArea]) -> bool {
+    monitors.iter().any(|monitor| {
+        rects_intersect(
+            (state.x, state.y, state.width, state.height),
+            (monitor.x, monitor.y, monitor.width, monitor.height),
+        )
+    })
+}
+
+/// Check whether two (x, y, width, height) rectangles overlap
+fn rects_intersect(a: (i32, i32, u32, u32), b: (i32, i32, u32, u32)) -> bool {
+    let (ax, ay, aw, ah) = a;
+    let (bx, by, bw, bh) = b;
+    ax < bx + bw as i32 && bx < ax + aw as i32 && ay < by + bh as i32 && by < ay + ah as i32
+}
+
 /// Ensure window fits within the given monitor
-fn ensure_window_fits(window: &WebviewWindow, monitor: &Monitor) {
+fn ensure_window_fits(window: &WebviewWindow, monitor: &MonitorArea) {
     let win_size = match window.outer_size() {
         Ok(s) => s,
         Err(_) => return,
     };
 
-    let mon_pos = monitor.position();
-    let mon_size = monitor.size();
-
     let margin = 50;
-    let max_width = (mon_size.width as i32 - margin * 2).max(800) as u32;
-    let max_height = (mon_size.height as i32 - margin * 2).max(600) as u32;
+    let max_width = (monitor.width as i32 - margin * 2).max(800) as u32;
+    let max_height = (monitor.height as i32 - margin * 2).max(600) as u32;
 
     if win_size.width > max_width || win_size.height > max_height {
         let new_width = win_size.width.min(max_width).max(1000);
@@ -261,11 +455,116 @@ fn ensure_window_fits(window: &WebviewWindow, monitor: &Monitor) {
         }));
 
         // Center on monitor
-        let new_x = mon_pos.x + (mon_size.width as i32 - new_width as i32) / 2;
-        let new_y = mon_pos.y + (mon_size.height as i32 - new_height as i32) / 2;
+        let (new_x, new_y) = monitor.centered(new_width, new_height);
         let _ = window.set_position(tauri::Position::Physical(PhysicalPosition {
             x: new_x,
             y: new_y,
         }));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitor(name: &str, x: i32, width: u32, height: u32) -> MonitorArea {
+        MonitorArea {
+            name: Some(name.to_string()),
+            x,
+            y: 0,
+            width,
+            height,
+        }
+    }
+
+    /// States saved while the window sat on an external monitor right of the laptop
+    fn saved_on_external() -> MonitorWindowStates {
+        let mut states = MonitorWindowStates::default();
+        let state = WindowState {
+            x: 2200,
+            y: 100,
+            width: 1600,
+            height: 1000,
+            maximized: true,
+            fullscreen: false,
+        };
+        states.set_for_monitor("2560x1440".to_string(), state);
+        states.last_monitor_name = Some("External".to_string());
+        states
+    }
+
+    #[test]
+    fn a_window_saved_on_a_disconnected_monitor_is_centered_on_the_primary() {
+        let laptop = monitor("Built-in", 0, 1920, 1080);
+        let placement = plan_restore(
+            &saved_on_external(),
+            std::slice::from_ref(&laptop),
+            Some(&laptop),
+        );
+        assert_eq!(
+            placement,
+            Placement::Bounds {
+                x: 320,
+                y: 140,
+                width: DEFAULT_WIDTH,
+                height: DEFAULT_HEIGHT,
+                maximized: false,
+                fullscreen: false,
+            }
+        );
+    }
+
+    #[test]
+    fn a_reconnected_monitor_gets_its_window_back() {
+        let laptop = monitor("Built-in", 0, 1920, 1080);
+        let monitors = [laptop.clone(), monitor("External", 1920, 2560, 1440)];
+        let placement = plan_restore(&saved_on_external(), &monitors, Some(&laptop));
+        assert_eq!(
+            placement,
+            Placement::Bounds {
+                x: 2200,
+                y: 100,
+                width: 1600,
+                height: 1000,
+                maximized: true,
+                fullscreen: false,
+            }
+        );
+
+        let nothing_saved = plan_restore(&MonitorWindowStates::default(), &monitors, Some(&laptop));
+        assert_eq!(nothing_saved, Placement::FitTo(laptop));
+    }
+
+    #[test]
+    fn identical_monitors_are_told_apart_by_position() {
+        let left = monitor("Left", 0, 1920, 1080);
+        let right = monitor("Right", 1920, 1920, 1080);
+        let both = [left.clone(), right.clone()];
+        assert_eq!(area_fingerprint(&right, &both), "1920x1080@1920,0");
+        assert_eq!(
+            area_fingerprint(&left, std::slice::from_ref(&left)),
+            "1920x1080"
+        );
+    }
+
+    #[test]
+    fn a_burst_of_saves_is_written_once_with_the_last_state() {
+        let (flushed, receiver) = mpsc::channel();
+        let debouncer = Debouncer::new(Duration::from_millis(50), move |value: u32| {
+            flushed.send(value).unwrap()
+        });
+        for value in 1..=5 {
+            debouncer.push(value);
+        }
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(2)), Ok(5));
+        // Nothing else from the first burst; a later event is written on its own
+        assert!(receiver.recv_timeout(Duration::from_millis(150)).is_err());
+        debouncer.push(6);
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(2)), Ok(6));
+
+        // A state still pending when the sender goes away is not lost
+        debouncer.push(7);
+        drop(debouncer);
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(2)), Ok(7));
+    }
+}
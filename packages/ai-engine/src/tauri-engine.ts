@@ -90,6 +90,8 @@ interface TauriAnalysisOptions {
   komi: number;
   nextToPlay?: string;
   history: HistoryMove[];
+  /** 1 reports winRate, scoreLead and ownership from Black's perspective */
+  orientTo: number;
 }
 
 /**
@@ -261,30 +263,38 @@ export class TauriEngine extends Engine {
 
         // Start the chunked upload
         const uploadStart = performance.now();
-        await this.invoke('onnx_start_upload');
-
-        // Upload chunks with base64 encoding (more efficient than JSON arrays)
-        const bytes = new Uint8Array(this.modelBuffer);
-        for (let i = 0; i < totalChunks; i++) {
-          const start = i * CHUNK_SIZE;
-          const end = Math.min(start + CHUNK_SIZE, bytes.length);
-          const chunk = bytes.subarray(start, end);
-
-          // Convert to base64 and upload
-          const chunkBase64 = chunkToBase64(chunk);
-          await this.invoke('onnx_upload_chunk', { chunkBase64 });
-
-          // Yield to UI every chunk to stay responsive
-          await yieldToUI();
-
-          // Report progress on every chunk for smooth UI updates
-          const progressPct = Math.round(((i + 1) / totalChunks) * 100);
-          const uploadedMB = (((i + 1) * CHUNK_SIZE) / 1024 / 1024).toFixed(0);
-          this.reportProgress(
-            'uploading',
-            progressPct,
-            `Uploading model: ${uploadedMB}/${sizeMB}MB (${progressPct}%)`
-          );
+        const uploadId = await this.invoke<string>('onnx_start_upload', {
+          totalBytes: this.modelBuffer.byteLength,
+        });
+
+        try {
+          // Upload chunks with base64 encoding (more efficient than JSON arrays)
+          const bytes = new Uint8Array(this.modelBuffer);
+          for (let i = 0; i < totalChunks; i++) {
+            const start = i * CHUNK_SIZE;
+            const end = Math.min(start + CHUNK_SIZE, bytes.length);
+            const chunk = bytes.subarray(start, end);
+
+            // Convert to base64 and upload
+            const chunkBase64 = chunkToBase64(chunk);
+            await this.invoke('onnx_upload_chunk', { uploadId, chunkBase64 });
+
+            // Yield to UI every chunk to stay responsive
+            await yieldToUI();
+
+            // Report progress on every chunk for smooth UI updates
+            const progressPct = Math.round(((i + 1) / totalChunks) * 100);
+            const uploadedMB = (((i + 1) * CHUNK_SIZE) / 1024 / 1024).toFixed(0);
+            this.reportProgress(
+              'uploading',
+              progressPct,
+              `Uploading model: ${uploadedMB}/${sizeMB}MB (${progressPct}%)`
+            );
+          }
+        } catch (e) {
+          // Don't leave a partial model behind in the temp directory
+          await this.invoke('onnx_cancel_upload', { uploadId }).catch(() => {});
+          throw e;
         }
 
         const uploadTime = performance.now() - uploadStart;
@@ -293,7 +303,7 @@ export class TauriEngine extends Engine {
         // Finish upload and initialize engine (with optional caching)
         this.reportProgress('initializing', 100, 'Initializing ONNX engine...');
         const engineStart = performance.now();
-        await this.invoke('onnx_finish_upload', { modelId: this.modelId ?? null });
+        await this.invoke('onnx_finish_upload', { uploadId, modelId: this.modelId ?? null });
         const engineTime = performance.now() - engineStart;
         this.debugLog('Engine initialized', { engineTimeMs: engineTime });
 
@@ -359,6 +369,7 @@ export class TauriEngine extends Engine {
       komi: options.komi ?? 7.5,
       nextToPlay: options.nextToPlay,
       history: this.convertHistory(options.history),
+      orientTo: 1,
     };
 
     this.debugLog('Analyzing position', {
@@ -423,6 +434,7 @@ export class TauriEngine extends Engine {
             komi: options.komi ?? 7.5,
             nextToPlay: options.nextToPlay,
             history: this.convertHistory(options.history),
+            orientTo: 1,
           },
         },
       });